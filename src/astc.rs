@@ -0,0 +1,716 @@
+use std::sync::LazyLock;
+
+// ldr astc block decoding for devices without TEXTURE_COMPRESSION_ASTC. illegal encodings and partitions
+// using hdr endpoint modes decode to magenta, the spec's error color
+const ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+// levels each quantization method can represent, indexed by the method
+const QUANT_LEVELS: [u32; 21] = [2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32, 40, 48, 64, 80, 96, 128, 160, 192, 256];
+
+// a value range splits into plain bits and at most one trit or quint
+struct Quant {
+    bits: u32,
+    trits: bool,
+    quints: bool
+}
+
+impl Quant {
+    fn new(levels: u32) -> Self {
+        if levels.is_multiple_of(3) {
+            Self { bits: (levels / 3).trailing_zeros(), trits: true, quints: false }
+        } else if levels.is_multiple_of(5) {
+            Self { bits: (levels / 5).trailing_zeros(), trits: false, quints: true }
+        } else {
+            Self { bits: levels.trailing_zeros(), trits: false, quints: false }
+        }
+    }
+
+    fn encoded_bits(&self, count: u32) -> u32 {
+        let extra = if self.trits {
+            (count * 8).div_ceil(5)
+        } else if self.quints {
+            (count * 7).div_ceil(3)
+        } else {
+            0
+        };
+        count * self.bits + extra
+    }
+}
+
+// reads lsb first, bits past the end of the range read as zero
+struct Bits {
+    data: u128,
+    position: u32,
+    end: u32
+}
+
+impl Bits {
+    fn read(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+        for i in 0..count {
+            let bit = self.position + i;
+            if bit < self.end {
+                value |= (((self.data >> bit) & 1) as u32) << i;
+            }
+        }
+        self.position += count;
+        value
+    }
+}
+
+fn bits(data: u128, start: u32, count: u32) -> u32 {
+    ((data >> start) as u32) & ((1u64 << count) - 1) as u32
+}
+
+// integer sequence encoding, trits pack five values into 8 bits and quints three values into 7
+fn decode_ise(bits: &mut Bits, quant: &Quant, count: usize) -> Vec<u32> {
+    let mut values = Vec::with_capacity(count);
+    while values.len() < count {
+        if quant.trits {
+            let mut m = [0; 5];
+            m[0] = bits.read(quant.bits);
+            let mut t = bits.read(2);
+            m[1] = bits.read(quant.bits);
+            t |= bits.read(2) << 2;
+            m[2] = bits.read(quant.bits);
+            t |= bits.read(1) << 4;
+            m[3] = bits.read(quant.bits);
+            t |= bits.read(2) << 5;
+            m[4] = bits.read(quant.bits);
+            t |= bits.read(1) << 7;
+
+            let trits = decode_trits(t);
+            values.extend((0..5).map(|i| (trits[i] << quant.bits) | m[i]));
+        } else if quant.quints {
+            let mut m = [0; 3];
+            m[0] = bits.read(quant.bits);
+            let mut q = bits.read(3);
+            m[1] = bits.read(quant.bits);
+            q |= bits.read(2) << 3;
+            m[2] = bits.read(quant.bits);
+            q |= bits.read(2) << 5;
+
+            let quints = decode_quints(q);
+            values.extend((0..3).map(|i| (quints[i] << quant.bits) | m[i]));
+        } else {
+            values.push(bits.read(quant.bits));
+        }
+    }
+    values.truncate(count);
+    values
+}
+
+fn decode_trits(t: u32) -> [u32; 5] {
+    let bit = |i: u32| (t >> i) & 1;
+    let (c, t4, t3);
+    if (t >> 2) & 7 == 7 {
+        c = ((t >> 5) << 2) | (t & 3);
+        t4 = 2;
+        t3 = 2;
+    } else {
+        c = t & 0x1F;
+        if (t >> 5) & 3 == 3 {
+            t4 = 2;
+            t3 = bit(7);
+        } else {
+            t4 = bit(7);
+            t3 = (t >> 5) & 3;
+        }
+    }
+
+    let c_bit = |i: u32| (c >> i) & 1;
+    let (t2, t1, t0);
+    if c & 3 == 3 {
+        t2 = 2;
+        t1 = c_bit(4);
+        t0 = (c_bit(3) << 1) | (c_bit(2) & !c_bit(3) & 1);
+    } else if (c >> 2) & 3 == 3 {
+        t2 = 2;
+        t1 = 2;
+        t0 = c & 3;
+    } else {
+        t2 = c_bit(4);
+        t1 = (c >> 2) & 3;
+        t0 = (c_bit(1) << 1) | (c_bit(0) & !c_bit(1) & 1);
+    }
+    [t0, t1, t2, t3, t4]
+}
+
+fn decode_quints(q: u32) -> [u32; 3] {
+    let bit = |i: u32| (q >> i) & 1;
+    if (q >> 1) & 3 == 3 && (q >> 5) & 3 == 0 {
+        let q2 = (bit(0) << 2) | ((bit(4) & !bit(0) & 1) << 1) | (bit(3) & !bit(0) & 1);
+        return [4, 4, q2];
+    }
+
+    let (q2, c) = if (q >> 1) & 3 == 3 {
+        (4, (((q >> 3) & 3) << 3) | ((!(q >> 5) & 3) << 1) | bit(0))
+    } else {
+        ((q >> 5) & 3, q & 0x1F)
+    };
+    let (q1, q0) = if c & 7 == 5 { (4, (c >> 3) & 3) } else { ((c >> 3) & 3, c & 7) };
+    [q0, q1, q2]
+}
+
+// spells out the 9 (colors) or 7 (weights) bit b term of unquantization, letters name the bits of the
+// value above the lowest
+fn pattern_value(pattern: &str, value: u32) -> u32 {
+    pattern.bytes().fold(0, |acc, c| (acc << 1) | match c {
+        b'0' => 0,
+        c => (value >> (c - b'a')) & 1
+    })
+}
+
+fn unquantize_color(value: u32, quant: &Quant) -> u32 {
+    if !quant.trits && !quant.quints {
+        // plain bits are replicated up to 8
+        let mut result = 0;
+        let mut shift = 8i32 - quant.bits as i32;
+        while shift > -(quant.bits as i32) {
+            result |= if shift >= 0 { value << shift } else { value >> -shift };
+            shift -= quant.bits as i32;
+        }
+        return result & 0xFF;
+    }
+
+    let d = value >> quant.bits;
+    let a = if value & 1 != 0 { 0x1FF } else { 0 };
+    let (b, c) = match (quant.trits, quant.bits) {
+        (true, 1) => (0, 204),
+        (true, 2) => (pattern_value("b000b0bb0", value), 93),
+        (true, 3) => (pattern_value("cb000cbcb", value), 44),
+        (true, 4) => (pattern_value("dcb000dcb", value), 22),
+        (true, 5) => (pattern_value("edcb000ed", value), 11),
+        (true, _) => (pattern_value("fedcb000f", value), 5),
+        (false, 1) => (0, 113),
+        (false, 2) => (pattern_value("b0000bb00", value), 54),
+        (false, 3) => (pattern_value("cb0000cbc", value), 26),
+        (false, 4) => (pattern_value("dcb0000dc", value), 13),
+        (false, _) => (pattern_value("edcb0000e", value), 6)
+    };
+    let t = (d * c + b) ^ a;
+    (a & 0x80) | (t >> 2)
+}
+
+fn unquantize_weight(value: u32, quant: &Quant) -> u32 {
+    let result = if !quant.trits && !quant.quints {
+        let mut result = 0;
+        let mut shift = 6i32 - quant.bits as i32;
+        while shift > -(quant.bits as i32) {
+            result |= if shift >= 0 { value << shift } else { value >> -shift };
+            shift -= quant.bits as i32;
+        }
+        result & 0x3F
+    } else if quant.bits == 0 {
+        let steps = if quant.trits { 32 } else { 16 };
+        return value * steps;
+    } else {
+        let d = value >> quant.bits;
+        let a = if value & 1 != 0 { 0x7F } else { 0 };
+        let (b, c) = match (quant.trits, quant.bits) {
+            (true, 1) => (0, 50),
+            (true, 2) => (pattern_value("b000b0b", value), 23),
+            (true, _) => (pattern_value("cb000cb", value), 11),
+            (false, 1) => (0, 28),
+            (false, _) => (pattern_value("b0000b0", value), 13)
+        };
+        let t = (d * c + b) ^ a;
+        (a & 0x20) | (t >> 2)
+    };
+    if result > 32 { result + 1 } else { result }
+}
+
+// moves the top bit of b into a and sign extends the 6 bit a
+fn bit_transfer_signed(a: i32, b: i32) -> (i32, i32) {
+    let b = (b >> 1) | (a & 0x80);
+    let a = (a >> 1) & 0x3F;
+    let a = if a & 0x20 != 0 { a - 0x40 } else { a };
+    (a, b)
+}
+
+fn blue_contract(r: i32, g: i32, b: i32, a: i32) -> [i32; 4] {
+    [(r + b) >> 1, (g + b) >> 1, b, a]
+}
+
+fn decode_endpoints(mode: u32, v: &[u32]) -> Option<[[i32; 4]; 2]> {
+    let mut v: Vec<i32> = v.iter().map(|&v| v as i32).collect();
+    let endpoints = match mode {
+        0 => [[v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]],
+        1 => {
+            let l0 = (v[0] >> 2) | (v[1] & 0xC0);
+            let l1 = (l0 + (v[1] & 0x3F)).min(255);
+            [[l0, l0, l0, 255], [l1, l1, l1, 255]]
+        },
+        4 => [[v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]],
+        5 => {
+            (v[1], v[0]) = bit_transfer_signed(v[1], v[0]);
+            (v[3], v[2]) = bit_transfer_signed(v[3], v[2]);
+            let l1 = v[0] + v[1];
+            [[v[0], v[0], v[0], v[2]], [l1, l1, l1, v[2] + v[3]]]
+        },
+        6 => [[(v[0] * v[3]) >> 8, (v[1] * v[3]) >> 8, (v[2] * v[3]) >> 8, 255], [v[0], v[1], v[2], 255]],
+        8 | 12 => {
+            let (a0, a1) = if mode == 12 { (v[6], v[7]) } else { (255, 255) };
+            if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                [[v[0], v[2], v[4], a0], [v[1], v[3], v[5], a1]]
+            } else {
+                [blue_contract(v[1], v[3], v[5], a1), blue_contract(v[0], v[2], v[4], a0)]
+            }
+        },
+        9 | 13 => {
+            (v[1], v[0]) = bit_transfer_signed(v[1], v[0]);
+            (v[3], v[2]) = bit_transfer_signed(v[3], v[2]);
+            (v[5], v[4]) = bit_transfer_signed(v[5], v[4]);
+            let (a0, a1) = if mode == 13 {
+                (v[7], v[6]) = bit_transfer_signed(v[7], v[6]);
+                (v[6], v[6] + v[7])
+            } else {
+                (255, 255)
+            };
+            if v[1] + v[3] + v[5] >= 0 {
+                [[v[0], v[2], v[4], a0], [v[0] + v[1], v[2] + v[3], v[4] + v[5], a1]]
+            } else {
+                [blue_contract(v[0] + v[1], v[2] + v[3], v[4] + v[5], a1), blue_contract(v[0], v[2], v[4], a0)]
+            }
+        },
+        10 => [[(v[0] * v[3]) >> 8, (v[1] * v[3]) >> 8, (v[2] * v[3]) >> 8, v[4]], [v[0], v[1], v[2], v[5]]],
+        // the remaining modes are hdr
+        _ => return None
+    };
+    Some(endpoints.map(|e| e.map(|c| c.clamp(0, 255))))
+}
+
+fn hash52(mut p: u32) -> u32 {
+    p ^= p >> 15;
+    p = p.wrapping_sub(p << 17);
+    p = p.wrapping_add(p << 7);
+    p = p.wrapping_add(p << 4);
+    p ^= p >> 5;
+    p = p.wrapping_add(p << 16);
+    p ^= p >> 7;
+    p ^= p >> 3;
+    p ^= p << 6;
+    p ^= p >> 17;
+    p
+}
+
+// partitions aren't stored as tables in astc, a seeded hash picks the subset of each texel
+fn select_partition(seed: u32, x: u32, y: u32, partition_count: u32, small_block: bool) -> usize {
+    let (x, y) = if small_block { (x << 1, y << 1) } else { (x, y) };
+    let seed = seed + (partition_count - 1) * 1024;
+    let rnum = hash52(seed);
+
+    let mut seeds = [
+        rnum & 0xF, (rnum >> 4) & 0xF, (rnum >> 8) & 0xF, (rnum >> 12) & 0xF,
+        (rnum >> 16) & 0xF, (rnum >> 20) & 0xF, (rnum >> 24) & 0xF, (rnum >> 28) & 0xF,
+        (rnum >> 18) & 0xF, (rnum >> 22) & 0xF, (rnum >> 26) & 0xF, rnum.rotate_left(2) & 0xF
+    ];
+    seeds.iter_mut().for_each(|s| *s *= *s);
+
+    let (sh1, sh2) = if seed & 1 != 0 {
+        (if seed & 2 != 0 { 4 } else { 5 }, if partition_count == 3 { 6 } else { 5 })
+    } else {
+        (if partition_count == 3 { 6 } else { 5 }, if seed & 2 != 0 { 4 } else { 5 })
+    };
+    let sh3 = if seed & 0x10 != 0 { sh1 } else { sh2 };
+    for (i, s) in seeds.iter_mut().enumerate() {
+        *s >>= match i {
+            0..8 if i % 2 == 0 => sh1,
+            0..8 => sh2,
+            _ => sh3
+        };
+    }
+
+    // blocks are 2d so the z terms drop out
+    let a = (seeds[0] * x + seeds[1] * y + (rnum >> 14)) & 0x3F;
+    let b = (seeds[2] * x + seeds[3] * y + (rnum >> 10)) & 0x3F;
+    let c = if partition_count >= 3 { (seeds[4] * x + seeds[5] * y + (rnum >> 6)) & 0x3F } else { 0 };
+    let d = if partition_count >= 4 { (seeds[6] * x + seeds[7] * y + (rnum >> 2)) & 0x3F } else { 0 };
+
+    if a >= b && a >= c && a >= d {
+        0
+    } else if b >= c && b >= d {
+        1
+    } else if c >= d {
+        2
+    } else {
+        3
+    }
+}
+
+struct BlockMode {
+    width: u32,
+    height: u32,
+    dual_plane: bool,
+    weight_quant: u32
+}
+
+fn decode_block_mode(mode: u32) -> Option<BlockMode> {
+    let a = (mode >> 5) & 3;
+    let b = (mode >> 7) & 3;
+    let mut high_precision = mode & 0x200 != 0;
+    let mut dual_plane = mode & 0x400 != 0;
+
+    let (range, width, height) = if mode & 3 != 0 {
+        let range = ((mode >> 4) & 1) | ((mode & 3) << 1);
+        let (width, height) = match (mode >> 2) & 3 {
+            0 => (b + 4, a + 2),
+            1 => (b + 8, a + 2),
+            2 => (a + 2, b + 8),
+            _ if mode & 0x100 == 0 => (a + 2, (b & 1) + 6),
+            _ => ((b & 1) + 2, a + 2)
+        };
+        (range, width, height)
+    } else {
+        let range = ((mode >> 4) & 1) | (((mode >> 2) & 3) << 1);
+        if mode & 0xF == 0 {
+            return None;
+        }
+        let (width, height) = match b {
+            0 => (12, a + 2),
+            1 => (a + 2, 12),
+            2 => {
+                high_precision = false;
+                dual_plane = false;
+                (a + 6, ((mode >> 9) & 3) + 6)
+            },
+            _ => match a {
+                0 => (6, 10),
+                1 => (10, 6),
+                _ => return None
+            }
+        };
+        (range, width, height)
+    };
+
+    if range < 2 {
+        return None;
+    }
+    let weight_quant = QUANT_LEVELS[(range - 2 + if high_precision { 6 } else { 0 }) as usize];
+    Some(BlockMode { width, height, dual_plane, weight_quant })
+}
+
+pub fn decode_block(block: &[u8], block_width: u32, block_height: u32, out: &mut [[u8; 4]]) {
+    let data = u128::from_le_bytes(block[..16].try_into().unwrap());
+    let texels = decode_texels(data, block_width, block_height);
+    match texels {
+        Some(texels) => out.copy_from_slice(&texels),
+        None => out.fill(ERROR_COLOR)
+    }
+}
+
+// the other direction, for transcoders that already hold quantized astc values and only need them packed
+
+// the smallest trit and quint code of every combination, so the unused values of a partial bundle leave
+// its truncated bits zero
+static TRIT_CODES: LazyLock<[u8; 243]> = LazyLock::new(|| {
+    let mut codes = [0; 243];
+    for t in (0..256).rev() {
+        codes[decode_trits(t).iter().rev().fold(0, |acc, &t| acc * 3 + t) as usize] = t as u8;
+    }
+    codes
+});
+
+static QUINT_CODES: LazyLock<[u8; 125]> = LazyLock::new(|| {
+    let mut codes = [0; 125];
+    for q in (0..128).rev() {
+        codes[decode_quints(q).iter().rev().fold(0, |acc, &q| acc * 5 + q) as usize] = q as u8;
+    }
+    codes
+});
+
+// writes lsb first, the mirror of Bits
+struct BitWriter {
+    data: u128,
+    position: u32
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        if self.position < 128 {
+            self.data |= ((value & ((1u64 << count) - 1) as u32) as u128) << self.position;
+        }
+        self.position += count;
+    }
+}
+
+fn encode_ise(values: &[u32], quant: &Quant) -> u128 {
+    let mut out = BitWriter { data: 0, position: 0 };
+    let bundle = if quant.trits { 5 } else if quant.quints { 3 } else { 1 };
+    for chunk in values.chunks(bundle) {
+        let m = |i: usize| chunk.get(i).copied().unwrap_or(0);
+        let high = |base: u32| (0..bundle).rev().fold(0, |acc, i| acc * base + (m(i) >> quant.bits));
+        if quant.trits {
+            let t = TRIT_CODES[high(3) as usize] as u32;
+            for (i, (shift, count)) in [(0, 2), (2, 2), (4, 1), (5, 2), (7, 1)].into_iter().enumerate() {
+                out.write(m(i), quant.bits);
+                out.write(t >> shift, count);
+            }
+        } else if quant.quints {
+            let q = QUINT_CODES[high(5) as usize] as u32;
+            for (i, (shift, count)) in [(0, 3), (3, 2), (5, 2)].into_iter().enumerate() {
+                out.write(m(i), quant.bits);
+                out.write(q >> shift, count);
+            }
+        } else {
+            out.write(m(0), quant.bits);
+        }
+    }
+    out.data & ((1u128 << quant.encoded_bits(values.len() as u32)) - 1)
+}
+
+// an ldr 4x4 block with a full 4x4 weight grid and one endpoint mode shared by every partition
+pub struct BlockParams<'a> {
+    pub partition_count: u32,
+    pub partition_seed: u32,
+    pub endpoint_mode: u32,
+    pub color_levels: u32,
+    pub colors: &'a [u32],
+    pub weight_levels: u32,
+    // one weight per texel, or two interleaved when a component has its own plane
+    pub weights: &'a [u32],
+    pub plane_component: Option<u32>
+}
+
+pub fn encode_block_4x4(block: &BlockParams) -> [u8; 16] {
+    let quant_index = QUANT_LEVELS.iter().position(|&levels| levels == block.weight_levels).unwrap() as u32;
+    let high_precision = quant_index >= 6;
+    let range = quant_index + 2 - if high_precision { 6 } else { 0 };
+    // the first block mode layout with b = 0 and a = 2 is a 4x4 grid
+    let mode = (range >> 1) | ((range & 1) << 4) | (2 << 5) | ((high_precision as u32) << 9) | ((block.plane_component.is_some() as u32) << 10);
+
+    let mut data = mode as u128 | (((block.partition_count - 1) as u128) << 11);
+    let color_start = if block.partition_count == 1 {
+        data |= (block.endpoint_mode as u128) << 13;
+        17
+    } else {
+        data |= ((block.partition_seed as u128) << 13) | ((block.endpoint_mode as u128) << 25);
+        29
+    };
+
+    let weight_quant = Quant::new(block.weight_levels);
+    let mut below_weights = 128 - weight_quant.encoded_bits(block.weights.len() as u32);
+    if let Some(component) = block.plane_component {
+        below_weights -= 2;
+        data |= (component as u128) << below_weights;
+    }
+
+    // decoders infer the color range from the bits left over, so the colors have to be in exactly that range
+    debug_assert_eq!(color_levels(block.colors.len() as u32, below_weights - color_start), Some(block.color_levels));
+    data |= encode_ise(block.colors, &Quant::new(block.color_levels)) << color_start;
+    data |= encode_ise(block.weights, &weight_quant).reverse_bits();
+    data.to_le_bytes()
+}
+
+// a single color over the whole texture
+pub fn encode_void_extent(color: [u8; 4]) -> [u8; 16] {
+    let mut data = 0x1FC | (3 << 10) | (((1u128 << 52) - 1) << 12);
+    for (i, &c) in color.iter().enumerate() {
+        data |= (c as u128 * 257) << (64 + 16 * i);
+    }
+    data.to_le_bytes()
+}
+
+pub fn unquantize_color_value(value: u32, levels: u32) -> u32 {
+    unquantize_color(value, &Quant::new(levels))
+}
+
+// the subset of a texel in a 4x4 block
+pub fn partition_4x4(seed: u32, partition_count: u32, x: u32, y: u32) -> usize {
+    select_partition(seed, x, y, partition_count, true)
+}
+
+// the highest level the bits left for colors allow, anything coarser than six levels is illegal
+fn color_levels(count: u32, bits: u32) -> Option<u32> {
+    QUANT_LEVELS.iter().rev().copied().find(|&levels| Quant::new(levels).encoded_bits(count) <= bits).filter(|&levels| levels >= 6)
+}
+
+fn decode_texels(data: u128, block_width: u32, block_height: u32) -> Option<Vec<[u8; 4]>> {
+    let texel_count = (block_width * block_height) as usize;
+    let mode = bits(data, 0, 11);
+
+    // void extent blocks are a single unorm16 color
+    if mode & 0x1FF == 0x1FC {
+        // hdr, or an extent that isn't all ones and is empty are illegal
+        let (s_min, s_max, t_min, t_max) = (bits(data, 12, 13), bits(data, 25, 13), bits(data, 38, 13), bits(data, 51, 13));
+        let all_ones = s_min == 0x1FFF && s_max == 0x1FFF && t_min == 0x1FFF && t_max == 0x1FFF;
+        if mode & 0x200 != 0 || (!all_ones && (s_min >= s_max || t_min >= t_max)) {
+            return None;
+        }
+        let color = [0, 1, 2, 3].map(|i| (bits(data, 64 + 16 * i, 16) >> 8) as u8);
+        return Some(vec![color; texel_count]);
+    }
+
+    let mode = decode_block_mode(mode)?;
+    let partition_count = bits(data, 11, 2) + 1;
+    let plane_count = if mode.dual_plane { 2 } else { 1 };
+    let weight_count = (mode.width * mode.height * plane_count) as usize;
+    let weight_quant = Quant::new(mode.weight_quant);
+    let weight_bits = weight_quant.encoded_bits(weight_count as u32);
+
+    if mode.width > block_width || mode.height > block_height || weight_count > 64
+        || !(24..=96).contains(&weight_bits) || (partition_count == 4 && mode.dual_plane) {
+        return None;
+    }
+
+    // endpoint modes, multiple partitions either share one or store per partition class and mode bits
+    // split between bits 23..29 and just below the weights
+    let mut below_weights = 128 - weight_bits;
+    let (endpoint_modes, color_start) = if partition_count == 1 {
+        (vec![bits(data, 13, 4)], 17)
+    } else {
+        let shared = bits(data, 23, 6);
+        if shared & 3 == 0 {
+            (vec![shared >> 2; partition_count as usize], 29)
+        } else {
+            let extra_bits = 3 * partition_count - 4;
+            below_weights -= extra_bits;
+            let encoded = shared | (bits(data, below_weights, extra_bits) << 6);
+            let base_class = (encoded & 3) - 1;
+            let modes = (0..partition_count).map(|i| {
+                let class = base_class + ((encoded >> (2 + i)) & 1);
+                let mode = (encoded >> (2 + partition_count + 2 * i)) & 3;
+                (class << 2) | mode
+            }).collect();
+            (modes, 29)
+        }
+    };
+    let plane_component = if mode.dual_plane {
+        below_weights -= 2;
+        Some(bits(data, below_weights, 2) as usize)
+    } else {
+        None
+    };
+
+    let color_value_count: u32 = endpoint_modes.iter().map(|m| ((m >> 2) + 1) * 2).sum();
+    if color_value_count > 18 || below_weights < color_start {
+        return None;
+    }
+    let color_levels = color_levels(color_value_count, below_weights - color_start)?;
+    let color_quant = Quant::new(color_levels);
+
+    let mut color_stream = Bits { data, position: color_start, end: color_start + color_quant.encoded_bits(color_value_count) };
+    let color_values: Vec<u32> = decode_ise(&mut color_stream, &color_quant, color_value_count as usize).into_iter().map(|v| unquantize_color(v, &color_quant)).collect();
+
+    let mut endpoints = Vec::with_capacity(partition_count as usize);
+    let mut offset = 0;
+    for &endpoint_mode in &endpoint_modes {
+        let count = (((endpoint_mode >> 2) + 1) * 2) as usize;
+        // an hdr mode only turns its own partition into the error color
+        endpoints.push(decode_endpoints(endpoint_mode, &color_values[offset..offset + count]).unwrap_or([ERROR_COLOR.map(i32::from); 2]));
+        offset += count;
+    }
+
+    // weights are stored bit reversed from the top of the block
+    let mut weight_stream = Bits { data: data.reverse_bits(), position: 0, end: weight_bits };
+    let weights: Vec<u32> = decode_ise(&mut weight_stream, &weight_quant, weight_count).into_iter().map(|w| unquantize_weight(w, &weight_quant)).collect();
+
+    let partition_seed = bits(data, 13, 10);
+    let small_block = texel_count < 31;
+    let scale_s = (1024 + block_width / 2) / (block_width - 1).max(1);
+    let scale_t = (1024 + block_height / 2) / (block_height - 1).max(1);
+
+    let mut texels = vec![[0u8; 4]; texel_count];
+    for t in 0..block_height {
+        for s in 0..block_width {
+            // bilinear infill from the weight grid
+            let gs = (scale_s * s * (mode.width - 1) + 32) >> 6;
+            let gt = (scale_t * t * (mode.height - 1) + 32) >> 6;
+            let (js, fs) = (gs >> 4, gs & 0xF);
+            let (jt, ft) = (gt >> 4, gt & 0xF);
+            let w11 = (fs * ft + 8) >> 4;
+            let w10 = ft - w11;
+            let w01 = fs - w11;
+            let w00 = 16 + w11 - fs - ft;
+
+            let grid_weight = |plane: u32| -> u32 {
+                let weight = |x: u32, y: u32| -> u32 {
+                    weights.get(((y * mode.width + x) * plane_count + plane) as usize).copied().unwrap_or(0)
+                };
+                (weight(js, jt) * w00 + weight(js + 1, jt) * w01 + weight(js, jt + 1) * w10 + weight(js + 1, jt + 1) * w11 + 8) >> 4
+            };
+            let plane_weights = [grid_weight(0), if mode.dual_plane { grid_weight(1) } else { 0 }];
+
+            let partition = if partition_count > 1 { select_partition(partition_seed, s, t, partition_count, small_block) } else { 0 };
+            let [e0, e1] = endpoints[partition];
+            let texel = &mut texels[(t * block_width + s) as usize];
+            for channel in 0..4 {
+                let weight = if plane_component == Some(channel) { plane_weights[1] } else { plane_weights[0] };
+                let (c0, c1) = ((e0[channel] * 257) as u32, (e1[channel] * 257) as u32);
+                let c = (c0 * (64 - weight) + c1 * weight + 32) >> 6;
+                texel[channel] = (c >> 8) as u8;
+            }
+        }
+    }
+
+    Some(texels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_4x4(block: &[u8; 16]) -> [[u8; 4]; 16] {
+        let mut out = [[0; 4]; 16];
+        decode_block(block, 4, 4, &mut out);
+        out
+    }
+
+    #[test]
+    fn void_extent() {
+        let block = [0xFC, 0xFD, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x80, 0x00, 0x40, 0xFF, 0xFF, 0xFF, 0x00];
+        assert!(decode_4x4(&block).iter().all(|&t| t == [128, 64, 255, 0]));
+    }
+
+    #[test]
+    fn two_endpoints() {
+        // one partition, direct rgb from red to blue, a 4x4 grid of 2 bit weights 0, 3, 1, 2 along the first row
+        let block = [0x42, 0x00, 0xFF, 0x01, 0x00, 0x00, 0x00, 0xFE, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x39];
+        let texels = decode_4x4(&block);
+        assert_eq!(texels[..4], [[255, 0, 0, 255], [0, 0, 255, 255], [171, 0, 84, 255], [84, 0, 171, 255]]);
+        assert!(texels[4..].iter().all(|&t| t == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn ise_round_trip() {
+        for levels in QUANT_LEVELS {
+            let quant = Quant::new(levels);
+            for count in 1..=12 {
+                let values: Vec<u32> = (0..count).map(|i| (i * 7 + 3) % levels).collect();
+                let mut bits = Bits { data: encode_ise(&values, &quant), position: 0, end: quant.encoded_bits(count) };
+                assert_eq!(decode_ise(&mut bits, &quant, count as usize), values, "{levels} levels");
+            }
+        }
+    }
+
+    #[test]
+    fn encode_dual_plane() {
+        // red with alpha on its own plane ramping along each row, value 1 of 48 levels is 255
+        let weights: Vec<u32> = (0..32).map(|i| if i % 2 == 1 { (i / 2) % 4 } else { 0 }).collect();
+        let block = encode_block_4x4(&BlockParams {
+            partition_count: 1,
+            partition_seed: 0,
+            endpoint_mode: 12,
+            color_levels: 48,
+            colors: &[1, 1, 0, 0, 0, 0, 0, 1],
+            weight_levels: 4,
+            weights: &weights,
+            plane_component: Some(3)
+        });
+        for row in decode_4x4(&block).chunks(4) {
+            assert_eq!(row, [[255, 0, 0, 0], [255, 0, 0, 84], [255, 0, 0, 171], [255, 0, 0, 255]]);
+        }
+    }
+
+    #[test]
+    fn reserved_block_mode() {
+        assert!(decode_4x4(&[0; 16]).iter().all(|&t| t == ERROR_COLOR));
+    }
+
+    #[test]
+    fn hdr_void_extent() {
+        let mut block = [0xFF; 16];
+        block[..2].copy_from_slice(&0xFFFCu16.to_le_bytes());
+        assert!(decode_4x4(&block).iter().all(|&t| t == ERROR_COLOR));
+    }
+}
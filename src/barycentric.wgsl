@@ -1,45 +1,74 @@
+// the wireframe debug view where the device can't draw lines. each triangle is drawn filled from its own three corners
+// in Mesh::wireframe_buffer, and only the pixels close to one of its edges are kept
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
-    @location(1) color: vec3<f32>,
+}
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
 }
 
 // @builtin(position) is in framebuffer space aka pixel space
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
-    @location(0) color: vec3<f32>,
+    @location(0) barycentric: vec3<f32>,
 };
 
+// matches camera::CameraUniform
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view_pos: vec4<f32>,
+    unjittered_view_proj: mat4x4<f32>,
+    previous_view_proj: mat4x4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
 
+const LINE_COLOR = vec3<f32>(0.9, 0.9, 0.9);
+const LINE_WIDTH = 1.0;     // in pixels
 
 @vertex
-fn vs_main(model: VertexInput, @builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+fn vs_main(model: VertexInput, instance: InstanceInput, @builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+
     var out: VertexOutput;
-    out.color = model.color;
-    out.clip_position = vec4<f32>(model.position, 1.0);
-
-    if in_vertex_index % 3 == 0 {
-        out.color = vec3<f32>(1.0, 0.0, 0.0);
-    } else if in_vertex_index % 3 == 1 {
-        out.color = vec3<f32>(0.0, 1.0, 0.0);
-    } else {
-        out.color = vec3<f32>(0.0, 0.0, 1.0);
-    }
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+
+    // 1 at its own corner and 0 at the other two, so one component goes to 0 along each edge
+    var corners = array<vec3<f32>, 3>(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(0.0, 0.0, 1.0));
+    out.barycentric = corners[in_vertex_index % 3];
 
     return out;
 }
 
-
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) probe_history: vec4<f32>,
+    @location(2) motion: vec2<f32>,
+}
 
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(convert_color(in.color), 1.0);
-}
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    // distance to the nearest edge in pixels, faded over one more pixel so the lines don't alias
+    let edge_distance = in.barycentric / fwidth(in.barycentric);
+    let coverage = 1.0 - smoothstep(LINE_WIDTH * 0.5, LINE_WIDTH * 0.5 + 1.0, min(edge_distance.x, min(edge_distance.y, edge_distance.z)));
+    if coverage <= 0.0 {
+        discard;
+    }
 
-fn convert_color(srgb_color: vec3<f32>) -> vec3<f32> {
-    var rgb_color: vec3<f32> = ((srgb_color + 0.055) / 1.055);
-    rgb_color.r = pow(rgb_color.r, 2.4);
-    rgb_color.g = pow(rgb_color.g, 2.4);
-    rgb_color.b = pow(rgb_color.b, 2.4);
-    return rgb_color;
-}
\ No newline at end of file
+    var out: FragmentOutput;
+    out.color = vec4<f32>(LINE_COLOR, coverage);
+    out.probe_history = vec4<f32>(0.0);
+    out.motion = vec2<f32>(0.0);
+    return out;
+}
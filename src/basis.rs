@@ -0,0 +1,812 @@
+use anyhow::Result;
+use wgpu::{AstcBlock, AstcChannel, TextureFormat};
+
+use crate::{astc, block_decode, container::ContainerImage};
+
+// basis universal transcoding. no gpu samples uastc or etc1s directly, but every uastc block repacks
+// losslessly into an astc 4x4 block and every etc1s block is an etc1 block, so both end up in formats
+// from_container already uploads or decodes to rgba8 when the device lacks them
+
+pub const UASTC_FORMAT: TextureFormat = TextureFormat::Astc { block: AstcBlock::B4x4, channel: AstcChannel::Unorm };
+
+// reads lsb first, bits past the end read as zero
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn read(&mut self, count: usize) -> u32 {
+        let mut value = 0;
+        for i in 0..count {
+            let bit = self.position + i;
+            let byte = self.data.get(bit / 8).copied().unwrap_or(0);
+            value |= (((byte >> (bit % 8)) & 1) as u32) << i;
+        }
+        self.position += count;
+        value
+    }
+
+    // variable length integer in chunks, the bit above each chunk says another one follows
+    fn read_vlc(&mut self, chunk_bits: usize) -> Result<u32> {
+        let mut value = 0;
+        for shift in (0..32).step_by(chunk_bits) {
+            let chunk = self.read(chunk_bits + 1);
+            value |= (chunk & ((1 << chunk_bits) - 1)) << shift;
+            if chunk >> chunk_bits == 0 {
+                return Ok(value);
+            }
+        }
+        anyhow::bail!("basis vlc overflows 32 bits")
+    }
+}
+
+
+struct UastcMode {
+    code: u32,
+    code_bits: usize,
+    // bc1 and etc hints for the other transcode targets, astc doesn't need them
+    hint_bits: usize,
+    subsets: usize,
+    pattern_bits: usize,
+    dual_plane: bool,
+    endpoint_mode: u32,
+    endpoint_levels: u32,
+    weight_bits: usize
+}
+
+const UASTC_SOLID_MODE: usize = 8;
+
+const UASTC_MODES: [UastcMode; 19] = [
+    UastcMode { code: 0x01, code_bits: 4, hint_bits: 15, subsets: 1, pattern_bits: 0, dual_plane: false, endpoint_mode: 8, endpoint_levels: 192, weight_bits: 4 },
+    UastcMode { code: 0x35, code_bits: 6, hint_bits: 15, subsets: 1, pattern_bits: 0, dual_plane: false, endpoint_mode: 8, endpoint_levels: 256, weight_bits: 2 },
+    UastcMode { code: 0x1D, code_bits: 5, hint_bits: 15, subsets: 2, pattern_bits: 5, dual_plane: false, endpoint_mode: 8, endpoint_levels: 16, weight_bits: 3 },
+    UastcMode { code: 0x03, code_bits: 5, hint_bits: 15, subsets: 3, pattern_bits: 4, dual_plane: false, endpoint_mode: 8, endpoint_levels: 12, weight_bits: 2 },
+    UastcMode { code: 0x13, code_bits: 5, hint_bits: 15, subsets: 2, pattern_bits: 5, dual_plane: false, endpoint_mode: 8, endpoint_levels: 40, weight_bits: 2 },
+    UastcMode { code: 0x0B, code_bits: 5, hint_bits: 15, subsets: 1, pattern_bits: 0, dual_plane: false, endpoint_mode: 8, endpoint_levels: 256, weight_bits: 3 },
+    UastcMode { code: 0x1B, code_bits: 5, hint_bits: 15, subsets: 1, pattern_bits: 0, dual_plane: true, endpoint_mode: 8, endpoint_levels: 160, weight_bits: 2 },
+    UastcMode { code: 0x07, code_bits: 5, hint_bits: 15, subsets: 2, pattern_bits: 5, dual_plane: false, endpoint_mode: 8, endpoint_levels: 40, weight_bits: 2 },
+    UastcMode { code: 0x17, code_bits: 5, hint_bits: 0, subsets: 0, pattern_bits: 0, dual_plane: false, endpoint_mode: 0, endpoint_levels: 0, weight_bits: 0 },
+    UastcMode { code: 0x0F, code_bits: 5, hint_bits: 23, subsets: 2, pattern_bits: 5, dual_plane: false, endpoint_mode: 12, endpoint_levels: 16, weight_bits: 2 },
+    UastcMode { code: 0x02, code_bits: 3, hint_bits: 17, subsets: 1, pattern_bits: 0, dual_plane: false, endpoint_mode: 12, endpoint_levels: 48, weight_bits: 4 },
+    UastcMode { code: 0x00, code_bits: 2, hint_bits: 17, subsets: 1, pattern_bits: 0, dual_plane: true, endpoint_mode: 12, endpoint_levels: 48, weight_bits: 2 },
+    UastcMode { code: 0x06, code_bits: 3, hint_bits: 17, subsets: 1, pattern_bits: 0, dual_plane: false, endpoint_mode: 12, endpoint_levels: 192, weight_bits: 3 },
+    UastcMode { code: 0x1F, code_bits: 5, hint_bits: 23, subsets: 1, pattern_bits: 0, dual_plane: true, endpoint_mode: 12, endpoint_levels: 256, weight_bits: 1 },
+    UastcMode { code: 0x0D, code_bits: 5, hint_bits: 23, subsets: 1, pattern_bits: 0, dual_plane: false, endpoint_mode: 12, endpoint_levels: 256, weight_bits: 2 },
+    UastcMode { code: 0x05, code_bits: 7, hint_bits: 23, subsets: 1, pattern_bits: 0, dual_plane: false, endpoint_mode: 4, endpoint_levels: 256, weight_bits: 4 },
+    UastcMode { code: 0x15, code_bits: 6, hint_bits: 23, subsets: 2, pattern_bits: 5, dual_plane: false, endpoint_mode: 4, endpoint_levels: 256, weight_bits: 2 },
+    UastcMode { code: 0x25, code_bits: 6, hint_bits: 23, subsets: 1, pattern_bits: 0, dual_plane: true, endpoint_mode: 4, endpoint_levels: 256, weight_bits: 2 },
+    UastcMode { code: 0x09, code_bits: 4, hint_bits: 15, subsets: 1, pattern_bits: 0, dual_plane: false, endpoint_mode: 8, endpoint_levels: 32, weight_bits: 5 }
+];
+
+// astc partition seeds of the bc7 shapes uastc can use, two subsets, three subsets and the two subset
+// shapes mode 7 takes from bc7's three subset table
+const UASTC_PATTERNS_2: [u32; 30] = [
+    28, 20, 16, 29, 91, 9, 107, 72, 149, 204, 50, 114, 496, 17, 78, 39, 252, 828, 43, 156, 116, 210, 476, 273, 684, 359, 246, 195, 694, 524
+];
+const UASTC_PATTERNS_3: [u32; 11] = [260, 74, 32, 156, 183, 15, 745, 0, 335, 902, 254];
+const UASTC_PATTERNS_2_FROM_3: [u32; 19] = [36, 48, 61, 137, 161, 183, 226, 281, 302, 307, 479, 495, 593, 594, 605, 799, 812, 988, 993];
+
+// repacks a level of uastc blocks as astc 4x4 blocks
+pub fn transcode_uastc(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+    for block in data.chunks_exact(16) {
+        out.extend_from_slice(&uastc_to_astc(block)?);
+    }
+    Ok(out)
+}
+
+fn uastc_to_astc(block: &[u8]) -> Result<[u8; 16]> {
+    let mut bits = BitReader::new(block);
+    let code = bits.read(7);
+    let Some((mode_index, mode)) = UASTC_MODES.iter().enumerate().find(|(_, m)| code & ((1 << m.code_bits) - 1) == m.code) else {
+        anyhow::bail!("reserved uastc mode");
+    };
+    bits.position = mode.code_bits;
+
+    if mode_index == UASTC_SOLID_MODE {
+        return Ok(astc::encode_void_extent([(); 4].map(|_| bits.read(8) as u8)));
+    }
+    bits.position += mode.hint_bits;
+
+    let patterns: &[u32] = match (mode.subsets, mode_index) {
+        (1, _) => &[0],
+        (3, _) => &UASTC_PATTERNS_3,
+        (_, 7) => &UASTC_PATTERNS_2_FROM_3,
+        _ => &UASTC_PATTERNS_2
+    };
+    let Some(&seed) = patterns.get(bits.read(mode.pattern_bits) as usize) else {
+        anyhow::bail!("uastc mode {mode_index} pattern is out of range");
+    };
+    let partition_count = mode.subsets as u32;
+    let subset_of = |texel: usize| if partition_count == 1 { 0 } else { astc::partition_4x4(seed, partition_count, texel as u32 % 4, texel as u32 / 4) };
+
+    // luminance alpha always gives alpha the second plane
+    let plane_component = match (mode.dual_plane, mode.endpoint_mode) {
+        (false, _) => None,
+        (true, 4) => Some(3),
+        (true, _) => Some(bits.read(2))
+    };
+
+    let components = match mode.endpoint_mode {
+        4 => 2,
+        8 => 3,
+        _ => 4
+    };
+    let mut colors = read_uastc_endpoints(&mut bits, mode.endpoint_levels, components * 2 * mode.subsets);
+
+    // the first texel of each subset is its anchor and drops the weight's top bit
+    let planes = if mode.dual_plane { 2 } else { 1 };
+    let mut anchors = [usize::MAX; 3];
+    for texel in (0..16).rev() {
+        anchors[subset_of(texel)] = texel;
+    }
+    let mut weights = vec![0; 16 * planes];
+    for texel in 0..16 {
+        let size = mode.weight_bits - anchors.contains(&texel) as usize;
+        for plane in 0..planes {
+            weights[texel * planes + plane] = bits.read(size);
+        }
+    }
+
+    // astc reads rgb endpoints whose second color is darker as blue contracted, uastc never means that, so
+    // those subsets swap their endpoints and invert their weights instead
+    if components >= 3 {
+        let weight_max = (1 << mode.weight_bits) - 1;
+        for (subset, values) in colors.chunks_exact_mut(components * 2).enumerate() {
+            let sum = |end: usize| (0..3).map(|c| astc::unquantize_color_value(values[c * 2 + end], mode.endpoint_levels)).sum::<u32>();
+            if sum(1) < sum(0) {
+                values.chunks_exact_mut(2).for_each(|pair| pair.swap(0, 1));
+                for texel in (0..16).filter(|&texel| subset_of(texel) == subset) {
+                    for plane in 0..planes {
+                        weights[texel * planes + plane] = weight_max - weights[texel * planes + plane];
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(astc::encode_block_4x4(&astc::BlockParams {
+        partition_count,
+        partition_seed: seed,
+        endpoint_mode: mode.endpoint_mode,
+        color_levels: mode.endpoint_levels,
+        colors: &colors,
+        weight_levels: 1 << mode.weight_bits,
+        weights: &weights,
+        plane_component
+    }))
+}
+
+// unlike astc's interleaved integer sequences, uastc stores the trits or quints of up to five or three
+// values first as one base 3 or 5 number each, then the plain bits of every value
+fn read_uastc_endpoints(bits: &mut BitReader, levels: u32, count: usize) -> Vec<u32> {
+    let (base, bundle) = if levels.is_multiple_of(3) {
+        (3, 5)
+    } else if levels.is_multiple_of(5) {
+        (5, 3)
+    } else {
+        (1, 1)
+    };
+    let plain = (levels / base).trailing_zeros() as usize;
+
+    let bundles: Vec<u32> = if base == 1 {
+        Vec::new()
+    } else {
+        (0..count.div_ceil(bundle)).map(|i| {
+            let values = (count - i * bundle).min(bundle) as u32;
+            bits.read((u32::BITS - (base.pow(values) - 1).leading_zeros()) as usize)
+        }).collect()
+    };
+
+    (0..count).map(|i| {
+        let low = bits.read(plain);
+        match bundles.get(i / bundle) {
+            Some(&packed) => low | ((packed / base.pow((i % bundle) as u32) % base) << plain),
+            None => low
+        }
+    }).collect()
+}
+
+
+// etc1s in basislz splits blocks into an endpoint codebook of 5 bit colors and intensity tables and a
+// codebook of selectors, each slice is huffman coded indices into both
+
+// canonical huffman code, read a bit at a time from the top of the code
+#[derive(Default)]
+struct Huffman {
+    // codes of each length and the symbols ordered by length
+    counts: [u32; 17],
+    symbols: Vec<u32>
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self> {
+        let mut table = Self::default();
+        for &length in lengths {
+            table.counts[length as usize] += 1;
+        }
+        table.counts[0] = 0;
+
+        let mut left = 1i64;
+        for &count in &table.counts[1..] {
+            left = (left << 1) - count as i64;
+            if left < 0 {
+                anyhow::bail!("oversubscribed basis huffman table");
+            }
+        }
+        for length in 1..17 {
+            table.symbols.extend((0..lengths.len() as u32).filter(|&symbol| lengths[symbol as usize] as usize == length));
+        }
+        Ok(table)
+    }
+
+    // the code lengths are huffman coded themselves, with run codes for zeros and repeats
+    fn read(bits: &mut BitReader) -> Result<Self> {
+        const ORDER: [usize; 21] = [17, 18, 19, 20, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15, 16];
+
+        let symbol_count = bits.read(14) as usize;
+        if symbol_count == 0 {
+            return Ok(Self::default());
+        }
+        let code_count = bits.read(5) as usize;
+        if !(1..=ORDER.len()).contains(&code_count) {
+            anyhow::bail!("invalid basis huffman table");
+        }
+        let mut code_lengths = [0; 21];
+        for &code in &ORDER[..code_count] {
+            code_lengths[code] = bits.read(3) as u8;
+        }
+        let codes = Self::new(&code_lengths)?;
+
+        let mut lengths = Vec::with_capacity(symbol_count);
+        while lengths.len() < symbol_count {
+            let (length, repeat) = match codes.decode(bits)? {
+                length @ 0..=16 => (length as u8, 1),
+                17 => (0, bits.read(3) + 3),
+                18 => (0, bits.read(7) + 11),
+                code => {
+                    let Some(&previous) = lengths.last().filter(|&&length| length != 0) else {
+                        anyhow::bail!("basis huffman table repeats nothing");
+                    };
+                    (previous, if code == 19 { bits.read(2) + 3 } else { bits.read(7) + 7 })
+                }
+            };
+            lengths.extend(std::iter::repeat_n(length, repeat as usize));
+        }
+        if lengths.len() != symbol_count {
+            anyhow::bail!("basis huffman table runs past its symbols");
+        }
+        Self::new(&lengths)
+    }
+
+    fn decode(&self, bits: &mut BitReader) -> Result<u32> {
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for &count in &self.counts[1..] {
+            code |= bits.read(1);
+            if code < first + count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        anyhow::bail!("invalid basis huffman code")
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Etc1sEndpoint {
+    color5: [u8; 3],
+    intensity: u8
+}
+
+// everything the slices of a file share, from the supercompression global data
+struct Codebooks {
+    endpoints: Vec<Etc1sEndpoint>,
+    // etc1 pixel index words
+    selectors: Vec<u32>,
+    endpoint_pred: Huffman,
+    delta_endpoint: Huffman,
+    selector: Huffman,
+    history_rle: Huffman,
+    history_size: usize
+}
+
+fn read_endpoints(data: &[u8], count: usize) -> Result<Vec<Etc1sEndpoint>> {
+    let mut bits = BitReader::new(data);
+    let color_models = [Huffman::read(&mut bits)?, Huffman::read(&mut bits)?, Huffman::read(&mut bits)?];
+    let intensity_model = Huffman::read(&mut bits)?;
+    let grayscale = bits.read(1) != 0;
+
+    // each endpoint is a delta from the previous one, the color delta table depends on how bright it was
+    let (mut color5, mut intensity) = ([16; 3], 0);
+    (0..count).map(|_| {
+        intensity = (intensity + intensity_model.decode(&mut bits)?) & 7;
+        for c in color5.iter_mut().take(if grayscale { 1 } else { 3 }) {
+            let model = match *c {
+                0..=9 => 0,
+                10..=21 => 1,
+                _ => 2
+            };
+            *c = (*c + color_models[model].decode(&mut bits)?) & 31;
+        }
+        if grayscale {
+            color5 = [color5[0]; 3];
+        }
+        Ok(Etc1sEndpoint { color5: color5.map(|c| c as u8), intensity: intensity as u8 })
+    }).collect()
+}
+
+fn read_selectors(data: &[u8], count: usize) -> Result<Vec<u32>> {
+    let mut bits = BitReader::new(data);
+    if bits.read(1) != 0 || bits.read(1) != 0 {
+        anyhow::bail!("basis global and hybrid selector codebooks aren't supported");
+    }
+    let raw = bits.read(1) != 0;
+    let model = if raw { Huffman::default() } else { Huffman::read(&mut bits)? };
+
+    // a byte per row, after the first selector every row is xored with the row of the one before
+    let mut rows = [0; 4];
+    (0..count).map(|i| {
+        for row in rows.iter_mut() {
+            *row = if raw || i == 0 { bits.read(8) } else { model.decode(&mut bits)? ^ *row };
+        }
+        Ok(etc1_pixel_indices(rows))
+    }).collect()
+}
+
+// basis selectors run from the most negative modifier up, etc1 stores a sign and a magnitude bit per
+// texel down each column
+fn etc1_pixel_indices(rows: [u32; 4]) -> u32 {
+    let mut indices = 0;
+    for (y, row) in rows.iter().enumerate() {
+        for x in 0..4 {
+            let index = [3, 2, 0, 1][(row >> (2 * x) & 3) as usize];
+            let texel = x * 4 + y;
+            indices |= ((index >> 1) << (16 + texel)) | ((index & 1) << texel);
+        }
+    }
+    indices
+}
+
+// a differential block without a delta, so both halves use the endpoint
+fn etc1_block(endpoint: Etc1sEndpoint, indices: u32) -> [u8; 8] {
+    let [r, g, b] = endpoint.color5.map(|c| c << 3);
+    let intensity = endpoint.intensity;
+    let [i0, i1, i2, i3] = indices.to_be_bytes();
+    [r, g, b, (intensity << 5) | (intensity << 2) | 2, i0, i1, i2, i3]
+}
+
+fn decode_slice(data: &[u8], blocks_wide: usize, blocks_high: usize, codebooks: &Codebooks) -> Result<Vec<u8>> {
+    let mut bits = BitReader::new(data);
+    let (endpoint_count, selector_count) = (codebooks.endpoints.len(), codebooks.selectors.len());
+
+    // recently used selectors, roughly move to front. new ones go in the back half, used ones move halfway up
+    let mut history = vec![0; codebooks.history_size];
+    let mut rover = history.len() / 2;
+    let rle_symbol = selector_count + history.len();
+
+    let mut endpoints = vec![0; blocks_wide * blocks_high];
+    let mut next_row_preds = vec![0; blocks_wide];
+    let (mut preds, mut repeat_preds, mut repeat_count, mut previous_endpoint, mut selector_run) = (0, 0, 0, 0, 0);
+    let mut out = Vec::with_capacity(endpoints.len() * 8);
+
+    for y in 0..blocks_high {
+        for (x, next_row_pred) in next_row_preds.iter_mut().enumerate() {
+            // a prediction symbol covers a 2x2 group of blocks with two bits each
+            if x % 2 == 0 {
+                if y % 2 == 1 {
+                    preds = *next_row_pred;
+                } else if repeat_count > 0 {
+                    repeat_count -= 1;
+                    preds = repeat_preds;
+                } else {
+                    preds = codebooks.endpoint_pred.decode(&mut bits)?;
+                    if preds == 256 {
+                        repeat_count = bits.read_vlc(4)? + 2;
+                        preds = repeat_preds;
+                    } else {
+                        repeat_preds = preds;
+                    }
+                }
+                if y % 2 == 0 {
+                    *next_row_pred = preds >> 4;
+                }
+            }
+
+            let index = y * blocks_wide + x;
+            let endpoint = match preds & 3 {
+                0 if x > 0 => previous_endpoint,
+                1 if y > 0 => endpoints[index - blocks_wide],
+                2 if x > 0 && y > 0 => endpoints[index - blocks_wide - 1],
+                3 => (previous_endpoint + codebooks.delta_endpoint.decode(&mut bits)? as usize) % endpoint_count,
+                _ => anyhow::bail!("basis endpoint prediction points outside the slice")
+            };
+            preds >>= 2;
+            endpoints[index] = endpoint;
+            previous_endpoint = endpoint;
+
+            let selector = if selector_run > 0 {
+                selector_run -= 1;
+                history[0]
+            } else {
+                let symbol = codebooks.selector.decode(&mut bits)? as usize;
+                if symbol == rle_symbol {
+                    let run = codebooks.history_rle.decode(&mut bits)?;
+                    selector_run = if run == 63 { bits.read_vlc(7)? + 3 } else { run + 3 } as usize;
+                    if selector_run > endpoints.len() {
+                        anyhow::bail!("basis selector run is longer than the slice");
+                    }
+                    selector_run -= 1;
+                    history[0]
+                } else if symbol >= selector_count {
+                    let i = symbol - selector_count;
+                    let Some(&selector) = history.get(i) else {
+                        anyhow::bail!("basis selector history index is out of range");
+                    };
+                    history.swap(i / 2, i);
+                    selector
+                } else {
+                    history[rover] = symbol;
+                    rover += 1;
+                    if rover == history.len() {
+                        rover = history.len() / 2;
+                    }
+                    symbol
+                }
+            };
+
+            out.extend_from_slice(&etc1_block(codebooks.endpoints[endpoint], codebooks.selectors[selector]));
+        }
+    }
+
+    Ok(out)
+}
+
+// every level has an rgb slice and optionally an alpha slice at offsets listed in the global data, which
+// also holds the codebooks and huffman tables all slices share. without alpha the etc1 blocks are kept,
+// with it both slices are decoded and the alpha slice's green becomes alpha
+pub fn transcode_etc1s(global_data: &[u8], levels: &[&[u8]], width: u32, height: u32) -> Result<ContainerImage> {
+    let read_u32 = |offset: usize| -> Result<usize> {
+        global_data.get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+            .ok_or_else(|| anyhow::anyhow!("basislz global data is truncated"))
+    };
+    let counts = read_u32(0)?;
+    let (endpoint_count, selector_count) = (counts & 0xFFFF, counts >> 16);
+    if endpoint_count == 0 || selector_count == 0 {
+        anyhow::bail!("basislz codebooks are empty");
+    }
+
+    let mut offset = 20 + 20 * levels.len();
+    let mut section = |length: usize| -> Result<&[u8]> {
+        let data = global_data.get(offset..offset + length).ok_or_else(|| anyhow::anyhow!("basislz global data is truncated"))?;
+        offset += length;
+        Ok(data)
+    };
+    let endpoints_data = section(read_u32(4)?)?;
+    let selectors_data = section(read_u32(8)?)?;
+    let tables_data = section(read_u32(12)?)?;
+
+    let mut tables = BitReader::new(tables_data);
+    let codebooks = Codebooks {
+        endpoints: read_endpoints(endpoints_data, endpoint_count)?,
+        selectors: read_selectors(selectors_data, selector_count)?,
+        endpoint_pred: Huffman::read(&mut tables)?,
+        delta_endpoint: Huffman::read(&mut tables)?,
+        selector: Huffman::read(&mut tables)?,
+        history_rle: Huffman::read(&mut tables)?,
+        history_size: tables.read(13) as usize
+    };
+    if codebooks.history_size == 0 {
+        anyhow::bail!("basislz selector history is empty");
+    }
+
+    let slices = levels.iter().enumerate().map(|(mip, level)| -> Result<(&[u8], Option<&[u8]>)> {
+        let desc = 20 + 20 * mip;
+        if read_u32(desc)? & 2 != 0 {
+            anyhow::bail!("basislz p-frames are for video and aren't supported");
+        }
+        let slice = |offset: usize, length: usize| level.get(offset..offset + length).ok_or_else(|| anyhow::anyhow!("basislz mip {mip} slice is out of bounds"));
+        let rgb = slice(read_u32(desc + 4)?, read_u32(desc + 8)?)?;
+        let alpha_length = read_u32(desc + 16)?;
+        let alpha = if alpha_length > 0 { Some(slice(read_u32(desc + 12)?, alpha_length)?) } else { None };
+        Ok((rgb, alpha))
+    }).collect::<Result<Vec<_>>>()?;
+
+    let has_alpha = slices.iter().any(|(_, alpha)| alpha.is_some());
+    let levels = slices.iter().enumerate().map(|(mip, (rgb, alpha))| -> Result<Vec<u8>> {
+        let (w, h) = ((width >> mip).max(1), (height >> mip).max(1));
+        let (blocks_wide, blocks_high) = (w.div_ceil(4) as usize, h.div_ceil(4) as usize);
+        let rgb = decode_slice(rgb, blocks_wide, blocks_high, &codebooks)?;
+        if !has_alpha {
+            return Ok(rgb);
+        }
+
+        let mut image = block_decode::decode(TextureFormat::Etc2Rgb8Unorm, w, h, &rgb)?;
+        if let Some(alpha) = alpha {
+            let alpha = block_decode::decode(TextureFormat::Etc2Rgb8Unorm, w, h, &decode_slice(alpha, blocks_wide, blocks_high, &codebooks)?)?;
+            for (texel, alpha) in image.pixels_mut().zip(alpha.pixels()) {
+                texel[3] = alpha[1];
+            }
+        }
+        Ok(image.into_raw())
+    }).collect::<Result<Vec<_>>>()?;
+
+    let format = if has_alpha { TextureFormat::Rgba8Unorm } else { TextureFormat::Etc2Rgb8Unorm };
+    Ok(ContainerImage { format, width, height, levels })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct BitWriter {
+        data: Vec<u8>,
+        position: usize
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: u32, count: usize) {
+            for i in 0..count {
+                if self.position / 8 == self.data.len() {
+                    self.data.push(0);
+                }
+                self.data[self.position / 8] |= (((value >> i) & 1) as u8) << (self.position % 8);
+                self.position += 1;
+            }
+        }
+
+        // every code length code is 5 bits long, so code length l is simply written as l
+        fn huffman_table(&mut self, lengths: &[u8]) {
+            self.write(lengths.len() as u32, 14);
+            if lengths.is_empty() {
+                return;
+            }
+            self.write(21, 5);
+            for _ in 0..21 {
+                self.write(5, 3);
+            }
+            for &length in lengths {
+                self.write_code(length as u32, 5);
+            }
+        }
+
+        fn write_code(&mut self, code: u32, length: usize) {
+            for bit in (0..length).rev() {
+                self.write(code >> bit, 1);
+            }
+        }
+
+        fn symbol(&mut self, lengths: &[u8], symbol: usize) {
+            let mut code = 0;
+            for length in 1..17 {
+                for (s, &l) in lengths.iter().enumerate() {
+                    if l as usize == length {
+                        if s == symbol {
+                            return self.write_code(code, length);
+                        }
+                        code += 1;
+                    }
+                }
+                code <<= 1;
+            }
+            panic!("symbol {symbol} has no code");
+        }
+    }
+
+    // the transcoder's reading in reverse with zeroed hints, also returns how many bits the block used
+    fn pack_uastc(mode_index: usize, pattern: u32, colors: &[u32], weights: &[u32]) -> ([u8; 16], usize) {
+        let mode = &UASTC_MODES[mode_index];
+        let mut out = BitWriter::default();
+        out.write(mode.code, mode.code_bits);
+        out.write(0, mode.hint_bits);
+        out.write(pattern, mode.pattern_bits);
+        if mode.dual_plane && mode.endpoint_mode != 4 {
+            out.write(3, 2);
+        }
+
+        let (base, bundle) = match mode.endpoint_levels {
+            levels if levels % 3 == 0 => (3, 5),
+            levels if levels % 5 == 0 => (5, 3),
+            _ => (1, 1)
+        };
+        let plain = (mode.endpoint_levels / base).trailing_zeros() as usize;
+        if base > 1 {
+            for chunk in colors.chunks(bundle) {
+                let packed = chunk.iter().rev().fold(0, |acc, &v| acc * base + (v >> plain));
+                out.write(packed, (u32::BITS - (base.pow(chunk.len() as u32) - 1).leading_zeros()) as usize);
+            }
+        }
+        for &value in colors {
+            out.write(value, plain);
+        }
+
+        let seeds: &[u32] = match (mode.subsets, mode_index) {
+            (1, _) => &[0],
+            (3, _) => &UASTC_PATTERNS_3,
+            (_, 7) => &UASTC_PATTERNS_2_FROM_3,
+            _ => &UASTC_PATTERNS_2
+        };
+        let subset_of = |texel: u32| if mode.subsets == 1 { 0 } else { astc::partition_4x4(seeds[pattern as usize], mode.subsets as u32, texel % 4, texel / 4) };
+        let planes = weights.len() / 16;
+        for texel in 0..16 {
+            let anchor = (0..texel).all(|t| subset_of(t) != subset_of(texel));
+            for plane in 0..planes {
+                out.write(weights[texel as usize * planes + plane], mode.weight_bits - anchor as usize);
+            }
+        }
+
+        let used = out.position;
+        out.data.resize(16, 0);
+        (out.data.try_into().unwrap(), used)
+    }
+
+    fn transcode(block: &[u8; 16]) -> [[u8; 4]; 16] {
+        let mut texels = [[0; 4]; 16];
+        astc::decode_block(&uastc_to_astc(block).unwrap(), 4, 4, &mut texels);
+        texels
+    }
+
+    #[test]
+    fn uastc_solid() {
+        let mut block = BitWriter::default();
+        block.write(UASTC_MODES[UASTC_SOLID_MODE].code, 5);
+        for c in [10, 20, 30, 40] {
+            block.write(c, 8);
+        }
+        block.data.resize(16, 0);
+        assert!(transcode(&block.data.try_into().unwrap()).iter().all(|&t| t == [10, 20, 30, 40]));
+    }
+
+    #[test]
+    fn uastc_mode_layouts() {
+        for (mode_index, mode) in UASTC_MODES.iter().enumerate().filter(|&(m, _)| m != UASTC_SOLID_MODE) {
+            let components = match mode.endpoint_mode {
+                4 => 2,
+                8 => 3,
+                _ => 4
+            };
+            let planes = if mode.dual_plane { 2 } else { 1 };
+            let (block, used) = pack_uastc(mode_index, 0, &vec![0; components * 2 * mode.subsets], &vec![0; 16 * planes]);
+            // these modes use every bit, which pins down the hint and trit and quint bundle sizes
+            if [0, 6, 10, 11, 12, 16, 18].contains(&mode_index) {
+                assert_eq!(used, 128, "uastc mode {mode_index}");
+            } else {
+                assert!(used <= 128, "uastc mode {mode_index} takes {used} bits");
+            }
+            let alpha = if components == 3 { 255 } else { 0 };
+            assert!(transcode(&block).iter().all(|&t| t == [0, 0, 0, alpha]), "uastc mode {mode_index}");
+        }
+    }
+
+    #[test]
+    fn uastc_two_endpoints() {
+        // red to blue, value 1 of a trit range is 255, weights 0, 15, 5 and 10
+        let (block, _) = pack_uastc(0, 0, &[1, 0, 0, 0, 0, 1], &[0, 15, 5, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let texels = transcode(&block);
+        assert_eq!(texels[..4], [[255, 0, 0, 255], [0, 0, 255, 255], [171, 0, 84, 255], [84, 0, 171, 255]]);
+    }
+
+    #[test]
+    fn uastc_darker_second_endpoint() {
+        // white to black would blue contract in astc, so the endpoints swap and the weights invert
+        let (block, _) = pack_uastc(1, 0, &[255, 0, 255, 0, 255, 0], &[0, 3, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let texels = transcode(&block);
+        assert_eq!(texels[..4], [[255, 255, 255, 255], [0, 0, 0, 255], [171, 171, 171, 255], [84, 84, 84, 255]]);
+    }
+
+    #[test]
+    fn uastc_two_subsets() {
+        // pattern 0 splits the left and right halves, black to white on the left and black to blue on the right
+        let weights: Vec<u32> = (0..16).map(|texel| texel % 4).collect();
+        let (block, _) = pack_uastc(2, 0, &[0, 15, 0, 15, 0, 15, 0, 0, 0, 0, 0, 15], &weights);
+        let texels = transcode(&block);
+        for row in texels.chunks(4) {
+            assert_eq!(row, [[0, 0, 0, 255], [36, 36, 36, 255], [0, 0, 72, 255], [0, 0, 108, 255]]);
+        }
+    }
+
+    // one endpoint, one selector and a single 4x4 slice
+    fn etc1s_file(alpha: bool) -> (Vec<u8>, Vec<u8>) {
+        let mut endpoints = BitWriter::default();
+        let color_deltas = {
+            let mut lengths = [0; 21];
+            lengths[8] = 1;
+            lengths[20] = 1;
+            lengths
+        };
+        endpoints.huffman_table(&[]);
+        endpoints.huffman_table(&color_deltas);
+        endpoints.huffman_table(&[]);
+        endpoints.huffman_table(&[0, 0, 0, 1]);
+        endpoints.write(0, 1);
+        // intensity 3, red 16 + 8, green and blue 16 + 20 wrapping to 4
+        endpoints.symbol(&[0, 0, 0, 1], 3);
+        endpoints.symbol(&color_deltas, 8);
+        endpoints.symbol(&color_deltas, 20);
+        endpoints.symbol(&color_deltas, 20);
+
+        // raw selectors 0 to 3 along every row
+        let mut selectors = BitWriter::default();
+        selectors.write(0b100, 3);
+        for _ in 0..4 {
+            selectors.write(0b11100100, 8);
+        }
+
+        let mut tables = BitWriter::default();
+        tables.huffman_table(&[0, 0, 0, 1]);
+        tables.huffman_table(&[1]);
+        tables.huffman_table(&[1]);
+        tables.huffman_table(&[]);
+        tables.write(8, 13);
+
+        // a delta prediction from endpoint 0 and the first selector
+        let mut slice = BitWriter::default();
+        slice.symbol(&[0, 0, 0, 1], 3);
+        slice.symbol(&[1], 0);
+        slice.symbol(&[1], 0);
+
+        let mut level = slice.data.clone();
+        let alpha_desc = if alpha {
+            level.extend_from_slice(&slice.data);
+            [slice.data.len() as u32, slice.data.len() as u32]
+        } else {
+            [0, 0]
+        };
+        let header = [1 | (1 << 16), endpoints.data.len() as u32, selectors.data.len() as u32, tables.data.len() as u32, 0];
+        let desc = [0, 0, slice.data.len() as u32, alpha_desc[0], alpha_desc[1]];
+        let mut global: Vec<u8> = header.iter().chain(&desc).flat_map(|v| v.to_le_bytes()).collect();
+        global.extend(endpoints.data.iter().chain(&selectors.data).chain(&tables.data));
+        (global, level)
+    }
+
+    #[test]
+    fn etc1s_slice() {
+        let (global, level) = etc1s_file(false);
+        let image = transcode_etc1s(&global, &[&level], 4, 4).unwrap();
+        assert_eq!(image.format, TextureFormat::Etc2Rgb8Unorm);
+        let decoded = block_decode::decode(image.format, 4, 4, &image.levels[0]).unwrap();
+        for row in decoded.rows() {
+            assert_eq!(row.map(|p| p.0).collect::<Vec<_>>(), [[156, 0, 0, 255], [185, 20, 20, 255], [211, 46, 46, 255], [240, 75, 75, 255]]);
+        }
+    }
+
+    #[test]
+    fn etc1s_alpha_slice() {
+        let (global, level) = etc1s_file(true);
+        let image = transcode_etc1s(&global, &[&level], 4, 4).unwrap();
+        assert_eq!(image.format, TextureFormat::Rgba8Unorm);
+        assert_eq!(image.levels[0][..16], [156, 0, 0, 0, 185, 20, 20, 20, 211, 46, 46, 46, 240, 75, 75, 75]);
+    }
+
+    #[test]
+    fn huffman_runs() {
+        // code length 4 for symbols 0 to 7, eleven unused symbols, then length 1
+        let mut bits = BitWriter::default();
+        bits.write(20, 14);
+        bits.write(21, 5);
+        for _ in 0..21 {
+            bits.write(5, 3);
+        }
+        bits.write_code(4, 5);
+        bits.write_code(20, 5);
+        bits.write(0, 7);
+        bits.write_code(18, 5);
+        bits.write(0, 7);
+        bits.write_code(1, 5);
+        bits.symbol(&[4, 4, 4, 4, 4, 4, 4, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1], 5);
+        bits.symbol(&[4, 4, 4, 4, 4, 4, 4, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1], 19);
+
+        let mut reader = BitReader::new(&bits.data);
+        let table = Huffman::read(&mut reader).unwrap();
+        assert_eq!(table.decode(&mut reader).unwrap(), 5);
+        assert_eq!(table.decode(&mut reader).unwrap(), 19);
+    }
+}
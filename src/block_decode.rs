@@ -0,0 +1,649 @@
+use anyhow::Result;
+use wgpu::{AstcChannel, TextureFormat};
+
+use crate::astc;
+
+// decodes one block into its texels, row by row
+type BlockDecoder = Box<dyn Fn(&[u8], &mut [[u8; 4]])>;
+
+// cpu decoders for the block compressed formats containers can hold, used when the device can't sample
+// them. every block decodes to 4x4 (or the astc footprint) rgba8 texels, one and two channel formats
+// fill red and green and leave blue at zero like the gpu would
+pub fn decode(format: TextureFormat, width: u32, height: u32, data: &[u8]) -> Result<image::RgbaImage> {
+    let format = format.remove_srgb_suffix();
+
+    match format {
+        TextureFormat::Rgba8Unorm => return image::RgbaImage::from_raw(width, height, data.to_vec()).ok_or_else(|| anyhow::anyhow!("rgba8 level is too short")),
+        TextureFormat::Bgra8Unorm => {
+            let mut rgba = data.to_vec();
+            rgba.chunks_exact_mut(4).for_each(|texel| texel.swap(0, 2));
+            return image::RgbaImage::from_raw(width, height, rgba).ok_or_else(|| anyhow::anyhow!("bgra8 level is too short"));
+        },
+        _ => ()
+    }
+
+    let (block_width, block_height) = format.block_dimensions();
+    let decode_block: BlockDecoder = match format {
+        TextureFormat::Bc1RgbaUnorm => Box::new(|block, out| decode_bc1(block, out, true)),
+        TextureFormat::Bc2RgbaUnorm => Box::new(decode_bc2),
+        TextureFormat::Bc3RgbaUnorm => Box::new(decode_bc3),
+        TextureFormat::Bc4RUnorm => Box::new(|block, out| decode_bc4_channel(block, out, 0)),
+        TextureFormat::Bc5RgUnorm => Box::new(|block, out| {
+            decode_bc4_channel(&block[..8], out, 0);
+            decode_bc4_channel(&block[8..], out, 1);
+        }),
+        TextureFormat::Bc7RgbaUnorm => Box::new(decode_bc7),
+        TextureFormat::Etc2Rgb8Unorm => Box::new(|block, out| decode_etc2_rgb(block, out, false)),
+        TextureFormat::Etc2Rgb8A1Unorm => Box::new(|block, out| decode_etc2_rgb(block, out, true)),
+        TextureFormat::Etc2Rgba8Unorm => Box::new(|block, out| {
+            decode_etc2_rgb(&block[8..], out, false);
+            decode_eac_channel(&block[..8], out, 3);
+        }),
+        TextureFormat::EacR11Unorm => Box::new(|block, out| {
+            out.iter_mut().for_each(|texel| *texel = [0, 0, 0, 255]);
+            decode_eac_channel(block, out, 0);
+        }),
+        TextureFormat::EacRg11Unorm => Box::new(|block, out| {
+            out.iter_mut().for_each(|texel| *texel = [0, 0, 0, 255]);
+            decode_eac_channel(&block[..8], out, 0);
+            decode_eac_channel(&block[8..], out, 1);
+        }),
+        TextureFormat::Astc { channel: AstcChannel::Unorm, .. } => Box::new(move |block, out| astc::decode_block(block, block_width, block_height, out)),
+        _ => anyhow::bail!("no cpu fallback for {format:?} and the device can't sample it")
+    };
+
+    let block_size = format.block_copy_size(None).unwrap_or(16) as usize;
+    let blocks_wide = width.div_ceil(block_width);
+    let blocks_high = height.div_ceil(block_height);
+    if data.len() < (blocks_wide * blocks_high) as usize * block_size {
+        anyhow::bail!("{format:?} level is {} bytes, {}x{} needs {}", data.len(), width, height, (blocks_wide * blocks_high) as usize * block_size);
+    }
+
+    let mut image = image::RgbaImage::new(width, height);
+    let mut texels = vec![[0u8; 4]; (block_width * block_height) as usize];
+    for (i, block) in data.chunks_exact(block_size).take((blocks_wide * blocks_high) as usize).enumerate() {
+        let (bx, by) = (i as u32 % blocks_wide * block_width, i as u32 / blocks_wide * block_height);
+        decode_block(block, &mut texels);
+
+        // blocks hanging over the right and bottom edges are cropped
+        for (j, texel) in texels.iter().enumerate() {
+            let (x, y) = (bx + j as u32 % block_width, by + j as u32 / block_width);
+            if x < width && y < height {
+                image.put_pixel(x, y, image::Rgba(*texel));
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+
+fn expand_565(c: u16) -> [u8; 3] {
+    let (r, g, b) = ((c >> 11) as u8 & 0x1F, (c >> 5) as u8 & 0x3F, c as u8 & 0x1F);
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)]
+}
+
+// bc2 and bc3 always use the four color mode, bc1 switches to three colors and transparent black when c0 <= c1
+fn decode_bc1(block: &[u8], out: &mut [[u8; 4]], allow_transparent: bool) {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (e0, e1) = (expand_565(c0), expand_565(c1));
+
+    let mix = |a: u8, b: u8, wa: u16, wb: u16| ((a as u16 * wa + b as u16 * wb) / (wa + wb)) as u8;
+    let palette: [[u8; 4]; 4] = if c0 > c1 || !allow_transparent {
+        [
+            [e0[0], e0[1], e0[2], 255],
+            [e1[0], e1[1], e1[2], 255],
+            [mix(e0[0], e1[0], 2, 1), mix(e0[1], e1[1], 2, 1), mix(e0[2], e1[2], 2, 1), 255],
+            [mix(e0[0], e1[0], 1, 2), mix(e0[1], e1[1], 1, 2), mix(e0[2], e1[2], 1, 2), 255]
+        ]
+    } else {
+        [
+            [e0[0], e0[1], e0[2], 255],
+            [e1[0], e1[1], e1[2], 255],
+            [mix(e0[0], e1[0], 1, 1), mix(e0[1], e1[1], 1, 1), mix(e0[2], e1[2], 1, 1), 255],
+            [0, 0, 0, 0]
+        ]
+    };
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    for (i, texel) in out.iter_mut().enumerate() {
+        *texel = palette[(indices >> (2 * i) & 3) as usize];
+    }
+}
+
+fn decode_bc2(block: &[u8], out: &mut [[u8; 4]]) {
+    decode_bc1(&block[8..], out, false);
+    let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+    for (i, texel) in out.iter_mut().enumerate() {
+        texel[3] = (alpha >> (4 * i) & 0xF) as u8 * 17;
+    }
+}
+
+fn decode_bc3(block: &[u8], out: &mut [[u8; 4]]) {
+    decode_bc1(&block[8..], out, false);
+    decode_bc4_channel(&block[..8], out, 3);
+}
+
+// two endpoints and sixteen 3 bit indices, eight interpolated values or six plus 0 and 255
+fn decode_bc4_channel(block: &[u8], out: &mut [[u8; 4]], channel: usize) {
+    let (r0, r1) = (block[0] as u32, block[1] as u32);
+    let palette: [u8; 8] = if r0 > r1 {
+        std::array::from_fn(|i| match i {
+            0 => r0 as u8,
+            1 => r1 as u8,
+            _ => (((8 - i as u32) * r0 + (i as u32 - 1) * r1) / 7) as u8
+        })
+    } else {
+        std::array::from_fn(|i| match i {
+            0 => r0 as u8,
+            1 => r1 as u8,
+            6 => 0,
+            7 => 255,
+            _ => (((6 - i as u32) * r0 + (i as u32 - 1) * r1) / 5) as u8
+        })
+    };
+
+    let mut indices = [0u8; 8];
+    indices[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(indices);
+    for (i, texel) in out.iter_mut().enumerate() {
+        if channel == 0 {
+            *texel = [0, 0, 0, 255];
+        }
+        texel[channel] = palette[(indices >> (3 * i) & 7) as usize];
+    }
+}
+
+
+// reads a bc7 block lsb first
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, count: usize) -> u32 {
+        let mut value = 0;
+        for i in 0..count {
+            let bit = self.position + i;
+            value |= (((self.data[bit / 8] >> (bit % 8)) & 1) as u32) << i;
+        }
+        self.position += count;
+        value
+    }
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: usize,
+    rotation_bits: usize,
+    index_selection_bits: usize,
+    color_bits: usize,
+    alpha_bits: usize,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: usize,
+    secondary_index_bits: usize
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode { subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, color_bits: 4, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 3, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 6, alpha_bits: 0, endpoint_pbits: false, shared_pbits: true, index_bits: 3, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 0, endpoint_pbits: false, shared_pbits: false, index_bits: 2, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 2, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, color_bits: 5, alpha_bits: 6, endpoint_pbits: false, shared_pbits: false, index_bits: 2, secondary_index_bits: 3 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, color_bits: 7, alpha_bits: 8, endpoint_pbits: false, shared_pbits: false, index_bits: 2, secondary_index_bits: 2 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 7, endpoint_pbits: true, shared_pbits: false, index_bits: 4, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 5, endpoint_pbits: true, shared_pbits: false, index_bits: 2, secondary_index_bits: 0 }
+];
+
+// subset of each texel for the 64 two and three subset shapes, shared with bc6h
+const BC7_PARTITIONS_2: [[u8; 16]; 64] = [
+    [0,0,1,1,0,0,1,1,0,0,1,1,0,0,1,1], [0,0,0,1,0,0,0,1,0,0,0,1,0,0,0,1], [0,1,1,1,0,1,1,1,0,1,1,1,0,1,1,1], [0,0,0,1,0,0,1,1,0,0,1,1,0,1,1,1],
+    [0,0,0,0,0,0,0,1,0,0,0,1,0,0,1,1], [0,0,1,1,0,1,1,1,0,1,1,1,1,1,1,1], [0,0,0,1,0,0,1,1,0,1,1,1,1,1,1,1], [0,0,0,0,0,0,0,1,0,0,1,1,0,1,1,1],
+    [0,0,0,0,0,0,0,0,0,0,0,1,0,0,1,1], [0,0,1,1,0,1,1,1,1,1,1,1,1,1,1,1], [0,0,0,0,0,0,0,1,0,1,1,1,1,1,1,1], [0,0,0,0,0,0,0,0,0,0,0,1,0,1,1,1],
+    [0,0,0,1,0,1,1,1,1,1,1,1,1,1,1,1], [0,0,0,0,0,0,0,0,1,1,1,1,1,1,1,1], [0,0,0,0,1,1,1,1,1,1,1,1,1,1,1,1], [0,0,0,0,0,0,0,0,0,0,0,0,1,1,1,1],
+    [0,0,0,0,1,0,0,0,1,1,1,0,1,1,1,1], [0,1,1,1,0,0,0,1,0,0,0,0,0,0,0,0], [0,0,0,0,0,0,0,0,1,0,0,0,1,1,1,0], [0,1,1,1,0,0,1,1,0,0,0,1,0,0,0,0],
+    [0,0,1,1,0,0,0,1,0,0,0,0,0,0,0,0], [0,0,0,0,1,0,0,0,1,1,0,0,1,1,1,0], [0,0,0,0,0,0,0,0,1,0,0,0,1,1,0,0], [0,1,1,1,0,0,1,1,0,0,1,1,0,0,0,1],
+    [0,0,1,1,0,0,0,1,0,0,0,1,0,0,0,0], [0,0,0,0,1,0,0,0,1,0,0,0,1,1,0,0], [0,1,1,0,0,1,1,0,0,1,1,0,0,1,1,0], [0,0,1,1,0,1,1,0,0,1,1,0,1,1,0,0],
+    [0,0,0,1,0,1,1,1,1,1,1,0,1,0,0,0], [0,0,0,0,1,1,1,1,1,1,1,1,0,0,0,0], [0,1,1,1,0,0,0,1,1,0,0,0,1,1,1,0], [0,0,1,1,1,0,0,1,1,0,0,1,1,1,0,0],
+    [0,1,0,1,0,1,0,1,0,1,0,1,0,1,0,1], [0,0,0,0,1,1,1,1,0,0,0,0,1,1,1,1], [0,1,0,1,1,0,1,0,0,1,0,1,1,0,1,0], [0,0,1,1,0,0,1,1,1,1,0,0,1,1,0,0],
+    [0,0,1,1,1,1,0,0,0,0,1,1,1,1,0,0], [0,1,0,1,0,1,0,1,1,0,1,0,1,0,1,0], [0,1,1,0,1,0,0,1,0,1,1,0,1,0,0,1], [0,1,0,1,1,0,1,0,1,0,1,0,0,1,0,1],
+    [0,1,1,1,0,0,1,1,1,1,0,0,1,1,1,0], [0,0,0,1,0,0,1,1,1,1,0,0,1,0,0,0], [0,0,1,1,0,0,1,0,0,1,0,0,1,1,0,0], [0,0,1,1,1,0,1,1,1,1,0,1,1,1,0,0],
+    [0,1,1,0,1,0,0,1,1,0,0,1,0,1,1,0], [0,0,1,1,1,1,0,0,1,1,0,0,0,0,1,1], [0,1,1,0,0,1,1,0,1,0,0,1,1,0,0,1], [0,0,0,0,0,1,1,0,0,1,1,0,0,0,0,0],
+    [0,1,0,0,1,1,1,0,0,1,0,0,0,0,0,0], [0,0,1,0,0,1,1,1,0,0,1,0,0,0,0,0], [0,0,0,0,0,0,1,0,0,1,1,1,0,0,1,0], [0,0,0,0,0,1,0,0,1,1,1,0,0,1,0,0],
+    [0,1,1,0,1,1,0,0,1,0,0,1,0,0,1,1], [0,0,1,1,0,1,1,0,1,1,0,0,1,0,0,1], [0,1,1,0,0,0,1,1,1,0,0,1,1,1,0,0], [0,0,1,1,1,0,0,1,1,1,0,0,0,1,1,0],
+    [0,1,1,0,1,1,0,0,1,1,0,0,1,0,0,1], [0,1,1,0,0,0,1,1,0,0,1,1,1,0,0,1], [0,1,1,1,1,1,1,0,1,0,0,0,0,0,0,1], [0,0,0,1,1,0,0,0,1,1,1,0,0,1,1,1],
+    [0,0,0,0,1,1,1,1,0,0,1,1,0,0,1,1], [0,0,1,1,0,0,1,1,1,1,1,1,0,0,0,0], [0,0,1,0,0,0,1,0,1,1,1,0,1,1,1,0], [0,1,0,0,0,1,0,0,0,1,1,1,0,1,1,1]
+];
+
+const BC7_PARTITIONS_3: [[u8; 16]; 64] = [
+    [0,0,1,1,0,0,1,1,0,2,2,1,2,2,2,2], [0,0,0,1,0,0,1,1,2,2,1,1,2,2,2,1], [0,0,0,0,2,0,0,1,2,2,1,1,2,2,1,1], [0,2,2,2,0,0,2,2,0,0,1,1,0,1,1,1],
+    [0,0,0,0,0,0,0,0,1,1,2,2,1,1,2,2], [0,0,1,1,0,0,1,1,0,0,2,2,0,0,2,2], [0,0,2,2,0,0,2,2,1,1,1,1,1,1,1,1], [0,0,1,1,0,0,1,1,2,2,1,1,2,2,1,1],
+    [0,0,0,0,0,0,0,0,1,1,1,1,2,2,2,2], [0,0,0,0,1,1,1,1,1,1,1,1,2,2,2,2], [0,0,0,0,1,1,1,1,2,2,2,2,2,2,2,2], [0,0,1,2,0,0,1,2,0,0,1,2,0,0,1,2],
+    [0,1,1,2,0,1,1,2,0,1,1,2,0,1,1,2], [0,1,2,2,0,1,2,2,0,1,2,2,0,1,2,2], [0,0,1,1,0,1,1,2,1,1,2,2,1,2,2,2], [0,0,1,1,2,0,0,1,2,2,0,0,2,2,2,0],
+    [0,0,0,1,0,0,1,1,0,1,1,2,1,1,2,2], [0,1,1,1,0,0,1,1,2,0,0,1,2,2,0,0], [0,0,0,0,1,1,2,2,1,1,2,2,1,1,2,2], [0,0,2,2,0,0,2,2,0,0,2,2,1,1,1,1],
+    [0,1,1,1,0,1,1,1,0,2,2,2,0,2,2,2], [0,0,0,1,0,0,0,1,2,2,2,1,2,2,2,1], [0,0,0,0,0,0,1,1,0,1,2,2,0,1,2,2], [0,0,0,0,1,1,0,0,2,2,1,0,2,2,1,0],
+    [0,1,2,2,0,1,2,2,0,0,1,1,0,0,0,0], [0,0,1,2,0,0,1,2,1,1,2,2,2,2,2,2], [0,1,1,0,1,2,2,1,1,2,2,1,0,1,1,0], [0,0,0,0,0,1,1,0,1,2,2,1,1,2,2,1],
+    [0,0,2,2,1,1,0,2,1,1,0,2,0,0,2,2], [0,1,1,0,0,1,1,0,2,0,0,2,2,2,2,2], [0,0,1,1,0,1,2,2,0,1,2,2,0,0,1,1], [0,0,0,0,2,0,0,0,2,2,1,1,2,2,2,1],
+    [0,0,0,0,0,0,0,2,1,1,2,2,1,2,2,2], [0,2,2,2,0,0,2,2,0,0,1,2,0,0,1,1], [0,0,1,1,0,0,1,2,0,0,2,2,0,2,2,2], [0,1,2,0,0,1,2,0,0,1,2,0,0,1,2,0],
+    [0,0,0,0,1,1,1,1,2,2,2,2,0,0,0,0], [0,1,2,0,1,2,0,1,2,0,1,2,0,1,2,0], [0,1,2,0,2,0,1,2,1,2,0,1,0,1,2,0], [0,0,1,1,2,2,0,0,1,1,2,2,0,0,1,1],
+    [0,0,1,1,1,1,2,2,2,2,0,0,0,0,1,1], [0,1,0,1,0,1,0,1,2,2,2,2,2,2,2,2], [0,0,0,0,0,0,0,0,2,1,2,1,2,1,2,1], [0,0,2,2,1,1,2,2,0,0,2,2,1,1,2,2],
+    [0,0,2,2,0,0,1,1,0,0,2,2,0,0,1,1], [0,2,2,0,1,2,2,1,0,2,2,0,1,2,2,1], [0,1,0,1,2,2,2,2,2,2,2,2,0,1,0,1], [0,0,0,0,2,1,2,1,2,1,2,1,2,1,2,1],
+    [0,1,0,1,0,1,0,1,0,1,0,1,2,2,2,2], [0,2,2,2,0,1,1,1,0,2,2,2,0,1,1,1], [0,0,0,2,1,1,1,2,0,0,0,2,1,1,1,2], [0,0,0,0,2,1,1,2,2,1,1,2,2,1,1,2],
+    [0,2,2,2,0,1,1,1,0,1,1,1,0,2,2,2], [0,0,0,2,1,1,1,2,1,1,1,2,0,0,0,2], [0,1,1,0,0,1,1,0,0,1,1,0,2,2,2,2], [0,0,0,0,0,0,0,0,2,1,1,2,2,1,1,2],
+    [0,1,1,0,0,1,1,0,2,2,2,2,2,2,2,2], [0,0,2,2,0,0,1,1,0,0,1,1,0,0,2,2], [0,0,2,2,1,1,2,2,1,1,2,2,0,0,2,2], [0,0,0,0,0,0,0,0,0,0,0,0,2,1,1,2],
+    [0,0,0,2,0,0,0,1,0,0,0,2,0,0,0,1], [0,2,2,2,1,2,2,2,0,2,2,2,1,2,2,2], [0,1,0,1,2,2,2,2,2,2,2,2,2,2,2,2], [0,1,1,1,2,0,1,1,2,2,0,1,2,2,2,0]
+];
+
+// the texel whose index drops its top bit, subset 0 always anchors at texel 0
+const BC7_ANCHORS_2: [u8; 64] = [
+    15,15,15,15,15,15,15,15, 15,15,15,15,15,15,15,15, 15, 2, 8, 2, 2, 8, 8,15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15,15, 6, 8, 2, 8,15,15, 2, 8, 2, 2, 2,15,15, 6, 6, 2, 6, 8,15,15, 2, 2, 15,15,15,15,15, 2, 2,15
+];
+
+const BC7_ANCHORS_3_SECOND: [u8; 64] = [
+     3, 3,15,15, 8, 3,15,15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8,15, 3, 3, 6,10, 5, 8, 8, 6, 8, 5,15,15,
+     8,15, 3, 5, 6,10, 8,15, 15, 3,15, 5,15,15,15,15, 3,15, 5, 5, 5, 8, 5,10, 5,10, 8,13,15,12, 3, 3
+];
+
+const BC7_ANCHORS_3_THIRD: [u8; 64] = [
+    15, 8, 8, 3,15,15, 3, 8, 15,15,15,15,15,15,15, 8, 15, 8,15, 3,15, 8,15, 8, 3,15, 6,10,15,15,10, 8,
+    15, 3,15,10,10, 8, 9,10, 6,15, 8,15, 3, 6, 6, 8, 15, 3,15,15,15,15,15,15, 15,15,15,15, 3,15,15, 8
+];
+
+const BC7_WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const BC7_WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC7_WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn bc7_interpolate(e0: u8, e1: u8, index: u32, bits: usize) -> u8 {
+    let weight = match bits {
+        2 => BC7_WEIGHTS_2[index as usize],
+        3 => BC7_WEIGHTS_3[index as usize],
+        _ => BC7_WEIGHTS_4[index as usize]
+    };
+    (((64 - weight) * e0 as u32 + weight * e1 as u32 + 32) >> 6) as u8
+}
+
+fn decode_bc7(block: &[u8], out: &mut [[u8; 4]]) {
+    let Some(mode_index) = (0..8).find(|&i| block[0] & (1 << i) != 0) else {
+        // the reserved mode decodes to transparent black
+        out.iter_mut().for_each(|texel| *texel = [0; 4]);
+        return;
+    };
+    let mode = &BC7_MODES[mode_index];
+
+    let mut bits = BitReader { data: block, position: mode_index + 1 };
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    // [endpoint][channel], two endpoints per subset
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            endpoint[channel] = bits.read(mode.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        endpoint[3] = if mode.alpha_bits > 0 { bits.read(mode.alpha_bits) } else { 255 };
+    }
+
+    let mut color_bits = mode.color_bits;
+    let mut alpha_bits = mode.alpha_bits;
+    if mode.endpoint_pbits || mode.shared_pbits {
+        let pbits = if mode.endpoint_pbits {
+            (0..endpoint_count).map(|_| bits.read(1)).collect::<Vec<_>>()
+        } else {
+            (0..mode.subsets).flat_map(|_| { let p = bits.read(1); [p, p] }).collect()
+        };
+        for (endpoint, pbit) in endpoints.iter_mut().zip(pbits) {
+            for channel in endpoint.iter_mut().take(3) {
+                *channel = (*channel << 1) | pbit;
+            }
+            if mode.alpha_bits > 0 {
+                endpoint[3] = (endpoint[3] << 1) | pbit;
+            }
+        }
+        color_bits += 1;
+        if mode.alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+
+    let expand = |value: u32, bits: usize| -> u8 {
+        let value = value << (8 - bits);
+        (value | (value >> bits)) as u8
+    };
+    let endpoints = endpoints.map(|e| [
+        expand(e[0], color_bits),
+        expand(e[1], color_bits),
+        expand(e[2], color_bits),
+        if alpha_bits > 0 { expand(e[3], alpha_bits) } else { 255 }
+    ]);
+
+    let subset_of = |texel: usize| -> usize {
+        match mode.subsets {
+            2 => BC7_PARTITIONS_2[partition][texel] as usize,
+            3 => BC7_PARTITIONS_3[partition][texel] as usize,
+            _ => 0
+        }
+    };
+    let is_anchor = |texel: usize| -> bool {
+        texel == 0 || match mode.subsets {
+            2 => texel == BC7_ANCHORS_2[partition] as usize,
+            3 => texel == BC7_ANCHORS_3_SECOND[partition] as usize || texel == BC7_ANCHORS_3_THIRD[partition] as usize,
+            _ => false
+        }
+    };
+
+    let primary: [u32; 16] = std::array::from_fn(|texel| bits.read(mode.index_bits - is_anchor(texel) as usize));
+    let secondary: [u32; 16] = std::array::from_fn(|texel| {
+        if mode.secondary_index_bits > 0 { bits.read(mode.secondary_index_bits - (texel == 0) as usize) } else { 0 }
+    });
+
+    for (texel, out) in out.iter_mut().enumerate() {
+        let subset = subset_of(texel);
+        let (e0, e1) = (endpoints[2 * subset], endpoints[2 * subset + 1]);
+
+        let (color_index, color_index_bits, alpha_index, alpha_index_bits) = match (mode.secondary_index_bits, index_selection) {
+            (0, _) => (primary[texel], mode.index_bits, primary[texel], mode.index_bits),
+            (_, 0) => (primary[texel], mode.index_bits, secondary[texel], mode.secondary_index_bits),
+            _ => (secondary[texel], mode.secondary_index_bits, primary[texel], mode.index_bits)
+        };
+
+        let mut color = [
+            bc7_interpolate(e0[0], e1[0], color_index, color_index_bits),
+            bc7_interpolate(e0[1], e1[1], color_index, color_index_bits),
+            bc7_interpolate(e0[2], e1[2], color_index, color_index_bits),
+            bc7_interpolate(e0[3], e1[3], alpha_index, alpha_index_bits)
+        ];
+        match rotation {
+            1 => color.swap(0, 3),
+            2 => color.swap(1, 3),
+            3 => color.swap(2, 3),
+            _ => ()
+        }
+        *out = color;
+    }
+}
+
+
+const ETC_MODIFIERS: [[i32; 2]; 8] = [[2, 8], [5, 17], [9, 29], [13, 42], [18, 60], [24, 80], [33, 106], [47, 183]];
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+// etc texels are numbered down each column first
+fn etc_texel_index(x: usize, y: usize) -> usize {
+    x * 4 + y
+}
+
+fn extend_4(c: u8) -> i32 {
+    ((c << 4) | c) as i32
+}
+
+fn extend_5(c: u8) -> i32 {
+    ((c << 3) | (c >> 2)) as i32
+}
+
+// etc1 individual and differential blocks plus the t, h and planar modes etc2 hides in overflowing differentials.
+// with punch through alpha the differential bit is the opaque flag instead
+fn decode_etc2_rgb(block: &[u8], out: &mut [[u8; 4]], punch_through: bool) {
+    let b = block;
+    let diff = b[3] & 2 != 0;
+    let flip = b[3] & 1 != 0;
+    let opaque = !punch_through || diff;
+    let indices = u32::from_be_bytes([b[4], b[5], b[6], b[7]]);
+    let pixel_index = |texel: usize| -> usize { ((indices >> (texel + 15) & 2) | (indices >> texel & 1)) as usize };
+    let clamp = |c: i32| c.clamp(0, 255) as u8;
+
+    // the punch through variant has no individual mode
+    if !diff && !punch_through {
+        let base = [
+            [extend_4(b[0] >> 4), extend_4(b[1] >> 4), extend_4(b[2] >> 4)],
+            [extend_4(b[0] & 0xF), extend_4(b[1] & 0xF), extend_4(b[2] & 0xF)]
+        ];
+        return decode_etc_subblocks(b, out, base, flip, true);
+    }
+
+    let delta = |byte: u8| -> (i32, i32) {
+        let base = (byte >> 3) as i32;
+        let delta = ((byte & 7) as i32) << 29 >> 29;
+        (base, base + delta)
+    };
+    let (r, r2) = delta(b[0]);
+    let (g, g2) = delta(b[1]);
+    let (bl, b2) = delta(b[2]);
+
+    if !(0..32).contains(&r2) {
+        // t mode
+        let c0 = [extend_4(((b[0] >> 1) & 0xC) | (b[0] & 3)), extend_4(b[1] >> 4), extend_4(b[1] & 0xF)];
+        let c1 = [extend_4(b[2] >> 4), extend_4(b[2] & 0xF), extend_4(b[3] >> 4)];
+        let d = ETC_DISTANCES[(((b[3] >> 1) & 6) | (b[3] & 1)) as usize];
+        let paint = [c0, c1.map(|c| c + d), c1, c1.map(|c| c - d)];
+        return decode_etc_paint(out, paint, pixel_index, opaque);
+    }
+
+    if !(0..32).contains(&g2) {
+        // h mode
+        let c0 = [(b[0] >> 3) & 0xF, ((b[0] & 7) << 1) | ((b[1] >> 4) & 1), (b[1] & 8) | ((b[1] & 3) << 1) | (b[2] >> 7)];
+        let c1 = [(b[2] >> 3) & 0xF, ((b[2] & 7) << 1) | (b[3] >> 7), (b[3] >> 3) & 0xF];
+        let order = |c: [u8; 3]| ((c[0] as u32) << 8) | ((c[1] as u32) << 4) | c[2] as u32;
+        let da = (b[3] & 4) | ((b[3] & 1) << 1) | (order(c0) >= order(c1)) as u8;
+        let d = ETC_DISTANCES[da as usize];
+        let (c0, c1) = (c0.map(extend_4), c1.map(extend_4));
+        let paint = [c0.map(|c| c + d), c0.map(|c| c - d), c1.map(|c| c + d), c1.map(|c| c - d)];
+        return decode_etc_paint(out, paint, pixel_index, opaque);
+    }
+
+    if !(0..32).contains(&b2) {
+        // planar mode, three colors at the corners blended across the block
+        let extend_6 = |c: u8| ((c << 2) | (c >> 4)) as i32;
+        let extend_7 = |c: u8| ((c << 1) | (c >> 6)) as i32;
+        let o = [
+            extend_6((b[0] >> 1) & 0x3F),
+            extend_7(((b[0] & 1) << 6) | ((b[1] >> 1) & 0x3F)),
+            extend_6(((b[1] & 1) << 5) | (b[2] & 0x18) | ((b[2] & 3) << 1) | (b[3] >> 7))
+        ];
+        let h = [
+            extend_6((((b[3] >> 2) & 0x1F) << 1) | (b[3] & 1)),
+            extend_7(b[4] >> 1),
+            extend_6(((b[4] & 1) << 5) | (b[5] >> 3))
+        ];
+        let v = [
+            extend_6(((b[5] & 7) << 3) | (b[6] >> 5)),
+            extend_7(((b[6] & 0x1F) << 2) | (b[7] >> 6)),
+            extend_6(b[7] & 0x3F)
+        ];
+        for y in 0..4 {
+            for x in 0..4 {
+                let c = |i: usize| clamp((x as i32 * (h[i] - o[i]) + y as i32 * (v[i] - o[i]) + 4 * o[i] + 2) >> 2);
+                out[y * 4 + x] = [c(0), c(1), c(2), 255];
+            }
+        }
+        return;
+    }
+
+    let base = [[extend_5(r as u8), extend_5(g as u8), extend_5(bl as u8)], [extend_5(r2 as u8), extend_5(g2 as u8), extend_5(b2 as u8)]];
+    decode_etc_subblocks(b, out, base, flip, opaque);
+}
+
+// two half blocks each with a base color and an intensity table
+fn decode_etc_subblocks(b: &[u8], out: &mut [[u8; 4]], base: [[i32; 3]; 2], flip: bool, opaque: bool) {
+    let tables = [(b[3] >> 5) as usize, ((b[3] >> 2) & 7) as usize];
+    let indices = u32::from_be_bytes([b[4], b[5], b[6], b[7]]);
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let subblock = if flip { (y >= 2) as usize } else { (x >= 2) as usize };
+            let texel = etc_texel_index(x, y);
+            let msb = (indices >> (texel + 16)) & 1;
+            let lsb = (indices >> texel) & 1;
+            let [small, large] = ETC_MODIFIERS[tables[subblock]];
+
+            let modifier = match (msb, lsb, opaque) {
+                (1, 0, false) => {
+                    out[y * 4 + x] = [0; 4];
+                    continue;
+                },
+                (0, 0, false) => 0,
+                (0, 0, true) => small,
+                (0, _, _) => large,
+                (_, 0, _) => -small,
+                _ => -large
+            };
+            let c = base[subblock].map(|c| (c + modifier).clamp(0, 255) as u8);
+            out[y * 4 + x] = [c[0], c[1], c[2], 255];
+        }
+    }
+}
+
+fn decode_etc_paint(out: &mut [[u8; 4]], paint: [[i32; 3]; 4], pixel_index: impl Fn(usize) -> usize, opaque: bool) {
+    for y in 0..4 {
+        for x in 0..4 {
+            let index = pixel_index(etc_texel_index(x, y));
+            out[y * 4 + x] = if !opaque && index == 2 {
+                [0; 4]
+            } else {
+                let c = paint[index].map(|c| c.clamp(0, 255) as u8);
+                [c[0], c[1], c[2], 255]
+            };
+        }
+    }
+}
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14], [-3, -7, -10, -13, 2, 6, 9, 12], [-2, -5, -8, -13, 1, 4, 7, 12], [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11], [-3, -7, -9, -11, 2, 6, 8, 10], [-4, -7, -8, -11, 3, 6, 7, 10], [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9], [-2, -5, -8, -10, 1, 4, 7, 9], [-2, -4, -8, -10, 1, 3, 7, 9], [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9], [-1, -2, -3, -10, 0, 1, 2, 9], [-4, -6, -8, -9, 3, 5, 7, 8], [-3, -5, -7, -9, 2, 4, 6, 8]
+];
+
+// the 8 bit alpha of etc2 rgba8, also used for r11 and rg11 at 8 bit precision
+fn decode_eac_channel(block: &[u8], out: &mut [[u8; 4]], channel: usize) {
+    let base = block[0] as i32;
+    let multiplier = (block[1] >> 4) as i32;
+    let table = &EAC_MODIFIERS[(block[1] & 0xF) as usize];
+    let indices = u64::from_be_bytes(block[..8].try_into().unwrap()) & 0xFFFF_FFFF_FFFF;
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let texel = etc_texel_index(x, y);
+            let index = (indices >> (45 - 3 * texel)) & 7;
+            out[y * 4 + x][channel] = if channel == 3 {
+                (base + table[index as usize] * multiplier).clamp(0, 255) as u8
+            } else {
+                // r11 and rg11 work in 11 bits with a zero multiplier meaning an eighth
+                let multiplier = if multiplier == 0 { 1 } else { multiplier * 8 };
+                let value = (base * 8 + 4 + table[index as usize] * multiplier).clamp(0, 2047);
+                ((value * 255 + 1023) / 2047) as u8
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_block(format: TextureFormat, block: &[u8]) -> Vec<[u8; 4]> {
+        decode(format, 4, 4, block).unwrap().pixels().map(|p| p.0).collect()
+    }
+
+    #[test]
+    fn bc1_solid() {
+        let texels = decode_block(TextureFormat::Bc1RgbaUnorm, &[0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0]);
+        assert!(texels.iter().all(|&t| t == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn bc1_four_colors() {
+        let texels = decode_block(TextureFormat::Bc1RgbaUnorm, &[0xFF, 0xFF, 0x00, 0x00, 0xE4, 0, 0, 0]);
+        assert_eq!(texels[..5], [[255, 255, 255, 255], [0, 0, 0, 255], [170, 170, 170, 255], [85, 85, 85, 255], [255, 255, 255, 255]]);
+    }
+
+    #[test]
+    fn bc1_three_colors_and_transparent() {
+        let texels = decode_block(TextureFormat::Bc1RgbaUnorm, &[0x00, 0x00, 0xFF, 0xFF, 0xE4, 0, 0, 0]);
+        assert_eq!(texels[..4], [[0, 0, 0, 255], [255, 255, 255, 255], [127, 127, 127, 255], [0, 0, 0, 0]]);
+    }
+
+    #[test]
+    fn bc3_alpha() {
+        let block = [0xFF, 0x00, 0x88, 0x0E, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0];
+        let texels = decode_block(TextureFormat::Bc3RgbaUnorm, &block);
+        assert_eq!(texels[..5].iter().map(|t| t[3]).collect::<Vec<_>>(), [255, 0, 218, 36, 255]);
+        assert!(texels.iter().all(|t| t[..3] == [255, 255, 255]));
+    }
+
+    #[test]
+    fn bc4_eight_values() {
+        let texels = decode_block(TextureFormat::Bc4RUnorm, &[200, 100, 0x88, 0x0E, 0, 0, 0, 0]);
+        assert_eq!(texels[..5], [[200, 0, 0, 255], [100, 0, 0, 255], [185, 0, 0, 255], [114, 0, 0, 255], [200, 0, 0, 255]]);
+    }
+
+    #[test]
+    fn bc4_six_values() {
+        // indices 0, 2, 6, 7
+        let texels = decode_block(TextureFormat::Bc4RUnorm, &[50, 150, 0x90, 0x0F, 0, 0, 0, 0]);
+        assert_eq!(texels[..4].iter().map(|t| t[0]).collect::<Vec<_>>(), [50, 70, 0, 255]);
+    }
+
+    #[test]
+    fn bc5_two_channels() {
+        let block = [200, 100, 0x88, 0x0E, 0, 0, 0, 0, 40, 40, 0, 0, 0, 0, 0, 0];
+        let texels = decode_block(TextureFormat::Bc5RgUnorm, &block);
+        assert_eq!(texels[..4], [[200, 40, 0, 255], [100, 40, 0, 255], [185, 40, 0, 255], [114, 40, 0, 255]]);
+    }
+
+    #[test]
+    fn bc7_mode_6() {
+        // white and transparent black endpoints, indices 0, 15, 8 and 4 along the first row
+        let block = [0xC0, 0x3F, 0xE0, 0x0F, 0xF8, 0x03, 0xFE, 0x80, 0xF0, 0x48, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let texels = decode_block(TextureFormat::Bc7RgbaUnorm, &block);
+        assert_eq!(texels[..5], [[255; 4], [0; 4], [120; 4], [187; 4], [255; 4]]);
+    }
+
+    #[test]
+    fn bc7_reserved_mode() {
+        let texels = decode_block(TextureFormat::Bc7RgbaUnorm, &[0; 16]);
+        assert!(texels.iter().all(|&t| t == [0; 4]));
+    }
+
+    #[test]
+    fn etc1_individual() {
+        // left half (15, 8, 0) with table 0, right half (0, 0, 8) with table 7
+        let block = [0xF0, 0x80, 0x08, 0x1C, 0x80, 0x01, 0x00, 0x01];
+        let texels = decode_block(TextureFormat::Etc2Rgb8Unorm, &block);
+        assert_eq!(texels[0], [247, 128, 0, 255]);
+        assert_eq!(texels[1], [255, 138, 2, 255]);
+        assert_eq!(texels[2], [47, 47, 183, 255]);
+        assert_eq!(texels[12], [255, 138, 2, 255]);
+        assert_eq!(texels[15], [0, 0, 89, 255]);
+    }
+
+    #[test]
+    fn etc1_differential_flipped() {
+        // top half (16, 0, 31), bottom half adds (3, 0, -1)
+        let block = [0x83, 0x00, 0xFF, 0x03, 0, 0, 0, 0];
+        let texels = decode_block(TextureFormat::Etc2Rgb8Unorm, &block);
+        assert!(texels[..8].iter().all(|&t| t == [134, 2, 255, 255]));
+        assert!(texels[8..].iter().all(|&t| t == [158, 2, 249, 255]));
+    }
+
+    #[test]
+    fn eac_alpha() {
+        // base 128, multiplier 2, table 13, the first column starts with indices 3 and 7
+        let block = [0x80, 0x2D, 0x7E, 0x49, 0x24, 0x92, 0x49, 0x24, 0, 0, 0, 0, 0, 0, 0, 0];
+        let texels = decode_block(TextureFormat::Etc2Rgba8Unorm, &block);
+        assert_eq!(texels[0], [2, 2, 2, 108]);
+        assert_eq!(texels[4], [2, 2, 2, 146]);
+        assert!(texels.iter().enumerate().filter(|&(i, _)| i != 0 && i != 4).all(|(_, &t)| t == [2, 2, 2, 128]));
+    }
+
+    #[test]
+    fn short_level() {
+        assert!(decode(TextureFormat::Bc1RgbaUnorm, 8, 4, &[0; 8]).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, *};
+
+use crate::{mipmap, postprocess::FullscreenPass, texture::Texture, tonemap::Tonemap};
+
+#[derive(Clone, Copy, Debug)]
+pub struct BloomSettings {
+    pub intensity: f32,     // share of the blurred image mixed into the scene
+    pub threshold: f32,     // brightness below which nothing blooms, 0 blooms everything and keeps it energy conserving
+    pub soft_knee: f32,     // fraction of the threshold faded in below it instead of cutting off
+    pub scatter: f32,       // how much of the wider blur levels reaches the final image
+    pub radius: f32,        // upsample filter radius in texels
+    pub mip_count: u32      // blur levels below half resolution, only read when the chain is created
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            intensity: 0.04,
+            threshold: 0.0,
+            soft_knee: 0.5,
+            scatter: 0.7,
+            radius: 1.0,
+            mip_count: 6
+        }
+    }
+}
+
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniform {
+    threshold: f32,
+    knee: f32,
+    radius: f32,
+    _padding: f32
+}
+
+
+// the hdr image downsampled into a half resolution mip chain and blurred back up it, then mixed over the hdr
+// target. the first downsample weights samples by brightness so single hot pixels don't bloom into blotches
+pub struct Bloom {
+    prefilter: FullscreenPass,
+    downsample: FullscreenPass,
+    upsample: FullscreenPass,
+    composite: FullscreenPass,
+    uniform_buffer: Buffer,
+    params: BindGroup,
+
+    mip_count: u32,
+    mip_views: Vec<TextureView>,
+    mip_bind_groups: Vec<BindGroup>,
+    source_bind_group: BindGroup    // the hdr target
+}
+
+
+impl Bloom {
+    pub fn new(device: &Device, vertex_shader: &ShaderModule, input_layout: &BindGroupLayout, sampler: &Sampler, hdr: &Texture, settings: BloomSettings) -> Self {
+        let shader = device.create_shader_module(include_wgsl!("bloom.wgsl"));
+
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Bloom Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[bloom_uniform(&settings)]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+        });
+
+        let params_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Bloom Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None
+                    },
+                    count: None
+                }
+            ]
+        });
+
+        let params = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Bloom Bind Group"),
+            layout: &params_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding()
+                }
+            ]
+        });
+
+        // mixes by the blend constant, the target keeps 1 - constant of what it had
+        let mix = Some(BlendState {
+            color: BlendComponent {
+                src_factor: BlendFactor::Constant,
+                dst_factor: BlendFactor::OneMinusConstant,
+                operation: BlendOperation::Add
+            },
+            alpha: BlendComponent {
+                src_factor: BlendFactor::Zero,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add
+            }
+        });
+
+        let pass = |label, entry_point, blend| FullscreenPass::new(device, label, vertex_shader, (&shader, entry_point), &[input_layout, &params_layout], Tonemap::HDR_FORMAT, blend);
+
+        let mut bloom = Self {
+            prefilter: pass("Bloom Prefilter Pipeline", "prefilter", None),
+            downsample: pass("Bloom Downsample Pipeline", "downsample", None),
+            upsample: pass("Bloom Upsample Pipeline", "upsample", mix),
+            composite: pass("Bloom Composite Pipeline", "composite", mix),
+            uniform_buffer,
+            params,
+            mip_count: settings.mip_count.max(1),
+            mip_views: Vec::new(),
+            mip_bind_groups: Vec::new(),
+            source_bind_group: FullscreenPass::input_bind_group(device, input_layout, &hdr.view, sampler)
+        };
+        bloom.resize(device, input_layout, sampler, hdr);
+        bloom
+    }
+
+    pub fn resize(&mut self, device: &Device, input_layout: &BindGroupLayout, sampler: &Sampler, hdr: &Texture) {
+        let size = hdr.texture.size();
+        let width = (size.width / 2).max(1);
+        let height = (size.height / 2).max(1);
+        let mip_level_count = self.mip_count.min(mipmap::mip_level_count(width, height));
+
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Bloom Mip Chain"),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: Tonemap::HDR_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[]
+        });
+
+        self.mip_views = (0..mip_level_count).map(|mip| texture.create_view(&TextureViewDescriptor {
+            label: Some("Bloom Mip"),
+            base_mip_level: mip,
+            mip_level_count: Some(1),
+            ..Default::default()
+        })).collect();
+        self.mip_bind_groups = self.mip_views.iter().map(|view| FullscreenPass::input_bind_group(device, input_layout, view, sampler)).collect();
+        self.source_bind_group = FullscreenPass::input_bind_group(device, input_layout, &hdr.view, sampler);
+    }
+
+    pub fn update(&self, queue: &Queue, settings: &BloomSettings) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[bloom_uniform(settings)]));
+    }
+
+    // blurs the hdr target and mixes the blur back into it, `scatter` is baked into the upsample blend
+    pub fn draw(&self, encoder: &mut CommandEncoder, hdr: &TextureView, intensity: f32, scatter: f32) {
+        self.prefilter.draw(encoder, &self.mip_views[0], &[&self.source_bind_group, &self.params], 0.0);
+
+        for mip in 1..self.mip_views.len() {
+            self.downsample.draw(encoder, &self.mip_views[mip], &[&self.mip_bind_groups[mip - 1], &self.params], 0.0);
+        }
+
+        for mip in (1..self.mip_views.len()).rev() {
+            self.upsample.draw(encoder, &self.mip_views[mip - 1], &[&self.mip_bind_groups[mip], &self.params], scatter as f64);
+        }
+
+        self.composite.draw(encoder, hdr, &[&self.mip_bind_groups[0], &self.params], intensity as f64);
+    }
+}
+
+
+fn bloom_uniform(settings: &BloomSettings) -> BloomUniform {
+    BloomUniform {
+        threshold: settings.threshold.max(0.0),
+        knee: settings.threshold.max(0.0) * settings.soft_knee.clamp(0.0, 1.0),
+        radius: settings.radius,
+        _padding: 0.0
+    }
+}
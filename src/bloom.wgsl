@@ -0,0 +1,113 @@
+// the blur chain behind bloom, a 13 tap downsample and a 9 tap tent upsample as in jimenez's
+// "next generation post processing in call of duty: advanced warfare"
+
+struct BloomUniform {
+    threshold: f32,
+    knee: f32,      // threshold times the soft knee fraction
+    radius: f32,    // upsample radius in texels of the smaller mip
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> bloom: BloomUniform;
+
+fn tap(uv: vec2<f32>, offset: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+    return textureSampleLevel(source, source_sampler, uv + offset * texel, 0.0).rgb;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// karis average, each group of four is weighted down by its brightness
+fn karis(a: vec3<f32>, b: vec3<f32>, c: vec3<f32>, d: vec3<f32>) -> vec4<f32> {
+    let average = (a + b + c + d) * 0.25;
+    let weight = 1.0 / (1.0 + luminance(average));
+    return vec4<f32>(average * weight, weight);
+}
+
+// a soft cutoff below the threshold, everything passes through when the threshold is 0
+fn threshold(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    var soft = clamp(brightness - bloom.threshold + bloom.knee, 0.0, 2.0 * bloom.knee);
+    soft = soft * soft / (4.0 * bloom.knee + 1e-4);
+    return color * max(soft, brightness - bloom.threshold) / max(brightness, 1e-4);
+}
+
+// the first downsample, reading straight from the hdr target
+@fragment
+fn prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    let a = tap(in.uv, vec2<f32>(-2.0, -2.0));
+    let b = tap(in.uv, vec2<f32>(0.0, -2.0));
+    let c = tap(in.uv, vec2<f32>(2.0, -2.0));
+    let d = tap(in.uv, vec2<f32>(-2.0, 0.0));
+    let e = tap(in.uv, vec2<f32>(0.0, 0.0));
+    let f = tap(in.uv, vec2<f32>(2.0, 0.0));
+    let g = tap(in.uv, vec2<f32>(-2.0, 2.0));
+    let h = tap(in.uv, vec2<f32>(0.0, 2.0));
+    let i = tap(in.uv, vec2<f32>(2.0, 2.0));
+    let j = tap(in.uv, vec2<f32>(-1.0, -1.0));
+    let k = tap(in.uv, vec2<f32>(1.0, -1.0));
+    let l = tap(in.uv, vec2<f32>(-1.0, 1.0));
+    let m = tap(in.uv, vec2<f32>(1.0, 1.0));
+
+    let sum = karis(j, k, l, m) * 0.5
+        + karis(a, b, d, e) * 0.125
+        + karis(b, c, e, f) * 0.125
+        + karis(d, e, g, h) * 0.125
+        + karis(e, f, h, i) * 0.125;
+
+    return vec4<f32>(threshold(sum.rgb / sum.a), 1.0);
+}
+
+@fragment
+fn downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let a = tap(in.uv, vec2<f32>(-2.0, -2.0));
+    let b = tap(in.uv, vec2<f32>(0.0, -2.0));
+    let c = tap(in.uv, vec2<f32>(2.0, -2.0));
+    let d = tap(in.uv, vec2<f32>(-2.0, 0.0));
+    let e = tap(in.uv, vec2<f32>(0.0, 0.0));
+    let f = tap(in.uv, vec2<f32>(2.0, 0.0));
+    let g = tap(in.uv, vec2<f32>(-2.0, 2.0));
+    let h = tap(in.uv, vec2<f32>(0.0, 2.0));
+    let i = tap(in.uv, vec2<f32>(2.0, 2.0));
+    let j = tap(in.uv, vec2<f32>(-1.0, -1.0));
+    let k = tap(in.uv, vec2<f32>(1.0, -1.0));
+    let l = tap(in.uv, vec2<f32>(-1.0, 1.0));
+    let m = tap(in.uv, vec2<f32>(1.0, 1.0));
+
+    let color = e * 0.125
+        + (a + c + g + i) * 0.03125
+        + (b + d + f + h) * 0.0625
+        + (j + k + l + m) * 0.125;
+
+    return vec4<f32>(color, 1.0);
+}
+
+// blended over the next larger mip by the scatter constant
+@fragment
+fn upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let r = bloom.radius;
+    let color = tap(in.uv, vec2<f32>(0.0, 0.0)) * 4.0
+        + (tap(in.uv, vec2<f32>(0.0, -r)) + tap(in.uv, vec2<f32>(-r, 0.0)) + tap(in.uv, vec2<f32>(r, 0.0)) + tap(in.uv, vec2<f32>(0.0, r))) * 2.0
+        + tap(in.uv, vec2<f32>(-r, -r)) + tap(in.uv, vec2<f32>(r, -r)) + tap(in.uv, vec2<f32>(-r, r)) + tap(in.uv, vec2<f32>(r, r));
+
+    return vec4<f32>(color / 16.0, 1.0);
+}
+
+// blended over the hdr target by the intensity constant
+@fragment
+fn composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(tap(in.uv, vec2<f32>(0.0, 0.0)), 1.0);
+}
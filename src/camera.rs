@@ -9,90 +9,477 @@ pub struct CameraController {
     pub d: bool,
     pub e: bool,
     pub q: bool,
+    pub space: bool,
+    pub shift: bool,
+    pub ctrl: bool,
+    pub mouse_delta: (f32, f32),
+}
+
+
+// the part of the camera that moves, kept so fixed timestep updates can be blended between steps
+#[derive(Clone, Copy, Debug)]
+pub struct CameraPose {
+    pub sphericals: Vector3<f32>,
+    pub target: Point3<f32>,
+    pub position: Point3<f32>,
+    pub yaw: f32,
+    pub pitch: f32
+}
+
+impl CameraPose {
+    pub fn lerp(&self, other: &CameraPose, t: f32) -> CameraPose {
+        CameraPose {
+            sphericals: self.sphericals.lerp(&other.sphericals, t),
+            target: self.target.coords.lerp(&other.target.coords, t).into(),
+            position: self.position.coords.lerp(&other.position.coords, t).into(),
+            yaw: self.yaw + (other.yaw - self.yaw) * t,
+            pitch: self.pitch + (other.pitch - self.pitch) * t
+        }
+    }
+}
+
+
+// ortho projections need a finite depth range, this stands in for an infinite far plane
+const ORTHO_INFINITE_FAR: f32 = 10_000.0;
+
+// projection settings, the matrices map straight to wgpu's 0..1 clip depth
+#[derive(Clone, Copy, Debug)]
+pub struct Projection {
+    pub fovy_degrees: f32,
+    pub znear: f32,
+    pub zfar: Option<f32>,  // None puts the far plane at infinity
+    pub reverse_z: bool     // near maps to 1 and far to 0, keeps far depth precision with a float buffer
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Self {
+            fovy_degrees: 45.0,
+            znear: 0.1,
+            zfar: Some(100.0),
+            reverse_z: false
+        }
+    }
+}
+
+impl Projection {
+    pub fn fovy_radians(&self) -> f32 {
+        self.fovy_degrees.to_radians()
+    }
+
+    #[allow(dead_code)]
+    pub fn set_fovy_radians(&mut self, fovy: f32) {
+        self.fovy_degrees = fovy.to_degrees();
+    }
+
+    pub fn depth_compare(&self) -> CompareFunction {
+        if self.reverse_z { CompareFunction::Greater } else { CompareFunction::Less }
+    }
+
+    // for comparison samplers reading this depth, closer or equal passes
+    pub fn depth_sample_compare(&self) -> CompareFunction {
+        if self.reverse_z { CompareFunction::GreaterEqual } else { CompareFunction::LessEqual }
+    }
+
+    pub fn depth_clear_value(&self) -> f32 {
+        if self.reverse_z { 0.0 } else { 1.0 }
+    }
+
+    pub fn perspective_matrix(&self, aspect_ratio: f32) -> Matrix4<f32> {
+        let f = 1.0 / (self.fovy_radians() / 2.0).tan();
+        let n = self.znear;
+
+        // view space looks down -z, w = -z_view
+        let (m22, m23) = match (self.zfar, self.reverse_z) {
+            (Some(far), false) => (far / (n - far), n * far / (n - far)),
+            (Some(far), true) => (n / (far - n), n * far / (far - n)),
+            (None, false) => (-1.0, -n),
+            (None, true) => (0.0, n)
+        };
+
+        Matrix4::new(
+            f / aspect_ratio, 0.0, 0.0, 0.0,
+            0.0, f, 0.0, 0.0,
+            0.0, 0.0, m22, m23,
+            0.0, 0.0, -1.0, 0.0,
+        )
+    }
+
+    pub fn orthographic_matrix(&self, half_width: f32, half_height: f32) -> Matrix4<f32> {
+        let n = self.znear;
+        let far = self.zfar.unwrap_or(ORTHO_INFINITE_FAR);
+
+        let (m22, m23) = if self.reverse_z {
+            (1.0 / (far - n), far / (far - n))
+        } else {
+            (-1.0 / (far - n), -n / (far - n))
+        };
+
+        Matrix4::new(
+            1.0 / half_width, 0.0, 0.0, 0.0,
+            0.0, 1.0 / half_height, 0.0, 0.0,
+            0.0, 0.0, m22, m23,
+            0.0, 0.0, 0.0, 1.0,
+        )
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProjectionMode {
+    Perspective,
+    Orthographic
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresetView {
+    Front,
+    Back,
+    Left,
+    Right,
+    Top,
+    Bottom
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    Orbit,
+    Fly
 }
 
 
 pub struct Camera {
+    pub mode: CameraMode,
+    pub projection_mode: ProjectionMode,
+
+    // orbit mode, (radius, theta, phi) around target
     pub sphericals: Vector3<f32>,
     pub target: Point3<f32>,
+
+    // fly mode, angles in radians with yaw 0 looking down +x
+    pub position: Point3<f32>,
+    pub yaw: f32,
+    pub pitch: f32,
+
+    // keyboard speeds, radians and units per second
+    pub orbit_speed: f32,
+    pub fly_speed: f32,
+    pub fast_multiplier: f32,
+    pub mouse_sensitivity: f32,
+
+    // orbit mode mouse controls, radians per pixel, target units per pixel per unit of radius, and per wheel line
+    pub orbit_sensitivity: f32,
+    pub pan_sensitivity: f32,
+    pub zoom_sensitivity: f32,
+
     pub up: Vector3<f32>,
     pub aspect_ratio: f32,
-    pub fovy: f32,
-    pub znear: f32,
-    pub zfar: f32,
-    pub cam_controller: CameraController
+    pub projection: Projection,
+    pub cam_controller: CameraController,
+
+    // sub-pixel offset in ndc added to the projection, taa moves it every frame
+    pub jitter: Vector2<f32>,
+    previous_view_proj: Option<Matrix4<f32>>   // unjittered, what the last frame was drawn with
 }
 
 
 impl Camera {
     pub fn from_dimensions(width: u32, height: u32) -> Self {
         Camera {
+            mode: CameraMode::Orbit,
+            projection_mode: ProjectionMode::Perspective,
             sphericals: [4.0, PI / 4.0, PI / 4.0].into(),
             target: [0.0, 0.0, 0.0].into(),
+            position: [0.0, 0.0, 0.0].into(),
+            yaw: 0.0,
+            pitch: 0.0,
+            orbit_speed: 0.6,
+            fly_speed: 3.0,
+            fast_multiplier: 4.0,
+            mouse_sensitivity: 0.002,
+            orbit_sensitivity: 0.005,
+            pan_sensitivity: 0.0015,
+            zoom_sensitivity: 0.1,
             up: Vector3::y(),
             aspect_ratio: width as f32 / height as f32,
-            fovy: 45.0,
-            zfar: 100.0,
-            znear: 0.1,
+            projection: Projection::default(),
             cam_controller: CameraController {
                 w: false,
                 a: false,
                 d: false,
                 e: false,
                 q: false,
-                s: false
+                s: false,
+                space: false,
+                shift: false,
+                ctrl: false,
+                mouse_delta: (0.0, 0.0)
+            },
+            jitter: Vector2::zeros(),
+            previous_view_proj: None
+        }
+    }
+
+    pub fn eye(&self) -> Point3<f32> {
+        match self.mode {
+            CameraMode::Orbit => self.target + spherical_to_cartesian(self.sphericals.x, self.sphericals.y, self.sphericals.z).coords,
+            CameraMode::Fly => self.position
+        }
+    }
+
+    // screen space up, for the orbit camera this is the direction of decreasing phi so it never
+    // lines up with forward, even when looking straight down from a top view
+    pub fn view_up(&self) -> Vector3<f32> {
+        match self.mode {
+            CameraMode::Orbit => {
+                let (theta, phi) = (self.sphericals.y, self.sphericals.z);
+                Vector3::new(-phi.cos() * theta.cos(), phi.sin(), -phi.cos() * theta.sin())
+            },
+            CameraMode::Fly => self.up
+        }
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        match self.mode {
+            CameraMode::Orbit => (self.target - self.eye()).normalize(),
+            CameraMode::Fly => Vector3::new(self.pitch.cos() * self.yaw.cos(), self.pitch.sin(), self.pitch.cos() * self.yaw.sin())
+        }
+    }
+
+    // switching keeps the view where it is, the orbit target is placed radius units ahead of the fly camera
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if mode == self.mode {
+            return;
+        }
+
+        let eye = self.eye();
+        let forward = self.forward();
+
+        match mode {
+            CameraMode::Fly => {
+                self.position = eye;
+                self.yaw = forward.z.atan2(forward.x);
+                self.pitch = forward.y.clamp(-1.0, 1.0).asin();
+            },
+            CameraMode::Orbit => {
+                self.target = eye + forward * self.sphericals.x;
+                // eye - target points back along forward, invert spherical_to_cartesian on it
+                self.sphericals.y = (-forward.z).atan2(-forward.x);
+                self.sphericals.z = (-forward.y).clamp(-1.0, 1.0).acos();
             }
         }
+
+        self.mode = mode;
+        self.cam_controller.mouse_delta = (0.0, 0.0);
     }
 
+    pub fn orbit(&mut self, dx: f32, dy: f32) {
+        self.sphericals.y += dx * self.orbit_sensitivity;
+        self.sphericals.z -= dy * self.orbit_sensitivity;
+        self.sphericals.z = self.sphericals.z.clamp(0.0, PI);
+    }
+
+    // moves the target in the view plane so whatever is under the cursor follows it
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        let forward = self.forward();
+        let right = forward.cross(&self.view_up()).normalize();
+        let screen_up = right.cross(&forward);
+        let scale = self.pan_sensitivity * self.sphericals.x;
+
+        self.target += (screen_up * dy - right * dx) * scale;
+    }
+
+    // exponential so each wheel step feels the same no matter how far out the camera is
+    pub fn zoom(&mut self, lines: f32) {
+        self.sphericals.x *= (1.0 - self.zoom_sensitivity).powf(lines);
+        self.sphericals.x = self.sphericals.x.clamp(0.1, 50.0);
+    }
+
+    pub fn toggle_projection(&mut self) {
+        self.projection_mode = match self.projection_mode {
+            ProjectionMode::Perspective => ProjectionMode::Orthographic,
+            ProjectionMode::Orthographic => ProjectionMode::Perspective
+        };
+    }
+
+    // axis aligned orbit around the current target, switches to orthographic like a CAD viewer would
+    pub fn set_preset_view(&mut self, view: PresetView) {
+        let (theta, phi) = match view {
+            PresetView::Front => (PI / 2.0, PI / 2.0),
+            PresetView::Back => (-PI / 2.0, PI / 2.0),
+            PresetView::Right => (0.0, PI / 2.0),
+            PresetView::Left => (PI, PI / 2.0),
+            PresetView::Top => (PI / 2.0, 0.0),
+            PresetView::Bottom => (-PI / 2.0, PI)
+        };
+
+        self.set_mode(CameraMode::Orbit);
+        self.sphericals.y = theta;
+        self.sphericals.z = phi;
+        self.projection_mode = ProjectionMode::Orthographic;
+    }
+
+    // shifted by the jitter after the perspective divide, so the offset is the same fraction of a pixel at every depth
     pub fn build_view_proj_matrix(&self) -> Matrix4<f32>{
-        let eye = spherical_to_cartesian(self.sphericals.x, self.sphericals.y, self.sphericals.z);
-        let view = Matrix4::look_at_rh(&eye, &self.target, &self.up);
+        Matrix4::new_translation(&Vector3::new(self.jitter.x, self.jitter.y, 0.0)) * self.build_unjittered_view_proj_matrix()
+    }
 
-        let persp = Perspective3::new(self.aspect_ratio, self.fovy, self.znear, self.zfar);
-        let proj = persp.to_homogeneous();
+    pub fn build_unjittered_view_proj_matrix(&self) -> Matrix4<f32>{
+        let eye = self.eye();
+        let view = Matrix4::look_at_rh(&eye, &(eye + self.forward()), &self.view_up());
 
-        let opengl_to_wgpu = Matrix4::new(
-            1.0, 0.0, 0.0, 0.0,
-            0.0, 1.0, 0.0, 0.0,
-            0.0, 0.0, 0.5, 0.0,
-            0.0, 0.0, 0.5, 1.0,
-        );
+        let proj = match self.projection_mode {
+            ProjectionMode::Perspective => self.projection.perspective_matrix(self.aspect_ratio),
+            ProjectionMode::Orthographic => {
+                // sized to match the perspective framing at the target, so the radius still acts as zoom
+                let half_height = self.sphericals.x * (self.projection.fovy_radians() / 2.0).tan();
+                let half_width = half_height * self.aspect_ratio;
+                self.projection.orthographic_matrix(half_width, half_height)
+            }
+        };
+
+        proj * view
+    }
+
+    // world space corners of the view volume between two distances along forward, near four then far four
+    pub fn frustum_corners(&self, near: f32, far: f32) -> [Point3<f32>; 8] {
+        let eye = self.eye();
+        let forward = self.forward();
+        let right = forward.cross(&self.view_up()).normalize();
+        let up = right.cross(&forward);
 
-        let view_proj = proj * view;
+        let tan_half_fovy = (self.projection.fovy_radians() / 2.0).tan();
+        let half_height = |distance: f32| match self.projection_mode {
+            ProjectionMode::Perspective => distance * tan_half_fovy,
+            ProjectionMode::Orthographic => self.sphericals.x * tan_half_fovy
+        };
 
-        opengl_to_wgpu * view_proj
+        let mut corners = [Point3::origin(); 8];
+        for (i, distance) in [near, far].into_iter().enumerate() {
+            let half_height = half_height(distance);
+            let half_width = half_height * self.aspect_ratio;
+            let center = eye + forward * distance;
+
+            for (j, (x, y)) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].into_iter().enumerate() {
+                corners[i * 4 + j] = center + right * (x * half_width) + up * (y * half_height);
+            }
+        }
+        corners
+    }
+
+    pub fn pose(&self) -> CameraPose {
+        CameraPose {
+            sphericals: self.sphericals,
+            target: self.target,
+            position: self.position,
+            yaw: self.yaw,
+            pitch: self.pitch
+        }
+    }
+
+    pub fn set_pose(&mut self, pose: &CameraPose) {
+        self.sphericals = pose.sphericals;
+        self.target = pose.target;
+        self.position = pose.position;
+        self.yaw = pose.yaw;
+        self.pitch = pose.pitch;
+    }
+
+    // dt in seconds
+    pub fn update(&mut self, dt: f32) {
+        match self.mode {
+            CameraMode::Orbit => self.update_orbit(dt),
+            CameraMode::Fly => self.update_fly(dt)
+        }
     }
 
-    pub fn update(&mut self) {
+    fn update_orbit(&mut self, dt: f32) {
+        let step = self.orbit_speed * dt;
+
         if self.cam_controller.e {
-            self.sphericals.x += 0.01;
+            self.sphericals.x += step;
         }
         if self.cam_controller.q {
-            self.sphericals.x -= 0.01;
+            self.sphericals.x -= step;
         }
         if self.cam_controller.d {
-            self.sphericals.y += 0.01;
+            self.sphericals.y += step;
         }
         if self.cam_controller.a {
-            self.sphericals.y -= 0.01;
+            self.sphericals.y -= step;
         }
         if self.cam_controller.w {
-            self.sphericals.z += 0.01
+            self.sphericals.z += step
         }
         if self.cam_controller.s {
-            self.sphericals.z -= 0.01
+            self.sphericals.z -= step
         }
 
         self.sphericals.x = self.sphericals.x.clamp(0.1, 50.0);
-        self.sphericals.z = self.sphericals.z.clamp(PI/10.0, 9.0 * PI/10.0);
+        self.sphericals.z = self.sphericals.z.clamp(0.0, PI);
+    }
+
+    fn update_fly(&mut self, dt: f32) {
+        let (dx, dy) = std::mem::take(&mut self.cam_controller.mouse_delta);
+        self.yaw += dx * self.mouse_sensitivity;
+        self.pitch -= dy * self.mouse_sensitivity;
+        self.pitch = self.pitch.clamp(-PI / 2.0 + 0.01, PI / 2.0 - 0.01);
+
+        let forward = self.forward();
+        let right = forward.cross(&self.up).normalize();
+
+        let mut movement = Vector3::zeros();
+        if self.cam_controller.w {
+            movement += forward;
+        }
+        if self.cam_controller.s {
+            movement -= forward;
+        }
+        if self.cam_controller.d {
+            movement += right;
+        }
+        if self.cam_controller.a {
+            movement -= right;
+        }
+        if self.cam_controller.space {
+            movement += self.up;
+        }
+        if self.cam_controller.shift {
+            movement -= self.up;
+        }
+
+        let speed = if self.cam_controller.ctrl { self.fly_speed * self.fast_multiplier } else { self.fly_speed };
+        if let Some(direction) = movement.try_normalize(1e-6) {
+            self.position += direction * speed * dt;
+        }
     }
 
     pub fn get_uniform(&self) -> CameraUniform {
         let mut camera_uniform = CameraUniform::new();
-        camera_uniform.update_view_proj(&self);
+        camera_uniform.update_view_proj(self);
         camera_uniform
     }
+
+    // uniform for a pose between `previous` and the current one, without disturbing the simulated state
+    pub fn get_interpolated_uniform(&mut self, previous: &CameraPose, alpha: f32) -> CameraUniform {
+        let current = self.pose();
+        self.set_pose(&previous.lerp(&current, alpha));
+        let camera_uniform = self.get_uniform();
+        self.set_pose(&current);
+        camera_uniform
+    }
+
+    // motion vectors of the next frame are measured from the view projection this uniform was built with
+    pub fn store_previous_view_proj(&mut self, uniform: &CameraUniform) {
+        self.previous_view_proj = Some(uniform.unjittered_view_proj.into());
+    }
+
+    // after a cut, so the next frame doesn't report motion from a view it never saw
+    pub fn reset_previous_view_proj(&mut self) {
+        self.previous_view_proj = None;
+    }
 }
 
 
@@ -103,18 +490,29 @@ use wgpu::{util::{BufferInitDescriptor, DeviceExt}, *};
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
-    view_proj: [[f32; 4]; 4]
+    view_proj: [[f32; 4]; 4],               // jittered, what the scene is rasterized with
+    view_pos: [f32; 4],
+    unjittered_view_proj: [[f32; 4]; 4],    // motion vectors are measured between this and the previous one
+    previous_view_proj: [[f32; 4]; 4]
 }
 
 impl CameraUniform {
     pub fn new() -> Self {
         Self {
-            view_proj: Matrix4::identity().into()
+            view_proj: Matrix4::identity().into(),
+            view_pos: [0.0; 4],
+            unjittered_view_proj: Matrix4::identity().into(),
+            previous_view_proj: Matrix4::identity().into()
         }
     }
 
+    // without a previous frame the previous view projection is this one, so nothing moves
     pub fn update_view_proj(&mut self, camera: &Camera) {
+        let unjittered = camera.build_unjittered_view_proj_matrix();
         self.view_proj = camera.build_view_proj_matrix().into();
+        self.view_pos = camera.eye().to_homogeneous().into();
+        self.unjittered_view_proj = unjittered.into();
+        self.previous_view_proj = camera.previous_view_proj.unwrap_or(unjittered).into();
     }
 
 
@@ -133,7 +531,7 @@ impl CameraUniform {
                 entries: &[
                     BindGroupLayoutEntry {
                         binding: 0,
-                        visibility: ShaderStages::VERTEX,
+                        visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
                         count: None,
                         ty: BindingType::Buffer { 
                             ty: BufferBindingType::Uniform, 
@@ -172,3 +570,61 @@ fn spherical_to_cartesian(radius: f32, theta: f32, phi: f32) -> Point3<f32> {
     )
 }
 
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // clip depth of a point straight ahead at `distance`
+    fn depth(projection: &Projection, distance: f32) -> f32 {
+        let clip = projection.perspective_matrix(1.5) * Vector4::new(0.0, 0.0, -distance, 1.0);
+        clip.z / clip.w
+    }
+
+    fn projection(zfar: Option<f32>, reverse_z: bool) -> Projection {
+        Projection { zfar, reverse_z, ..Default::default() }
+    }
+
+    fn assert_depth(projection: &Projection, distance: f32, expected: f32) {
+        let depth = depth(projection, distance);
+        assert!((depth - expected).abs() < 1e-5, "{projection:?} puts {distance} at depth {depth}, expected {expected}");
+    }
+
+    #[test]
+    fn perspective_finite() {
+        let projection = projection(Some(100.0), false);
+        assert_depth(&projection, 0.1, 0.0);
+        assert_depth(&projection, 100.0, 1.0);
+    }
+
+    #[test]
+    fn perspective_finite_reverse_z() {
+        let projection = projection(Some(100.0), true);
+        assert_depth(&projection, 0.1, 1.0);
+        assert_depth(&projection, 100.0, 0.0);
+    }
+
+    #[test]
+    fn perspective_infinite() {
+        let projection = projection(None, false);
+        assert_depth(&projection, 0.1, 0.0);
+        assert_depth(&projection, 1e6, 1.0);
+    }
+
+    #[test]
+    fn perspective_infinite_reverse_z() {
+        let projection = projection(None, true);
+        assert_depth(&projection, 0.1, 1.0);
+        assert_depth(&projection, 1e6, 0.0);
+    }
+
+    #[test]
+    fn orthographic_reverse_z() {
+        let projection = projection(Some(100.0), true);
+        let matrix = projection.orthographic_matrix(2.0, 2.0);
+        let depth = |distance: f32| (matrix * Vector4::new(0.0, 0.0, -distance, 1.0)).z;
+        assert!((depth(0.1) - 1.0).abs() < 1e-5);
+        assert!(depth(100.0).abs() < 1e-5);
+    }
+}
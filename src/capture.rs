@@ -0,0 +1,177 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use wgpu::*;
+
+// texture to buffer copies need every row padded out to COPY_BYTES_PER_ROW_ALIGNMENT
+pub fn padded_bytes_per_row(width: u32) -> u32 {
+    let unpadded = width * 4;
+    unpadded.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT
+}
+
+// records a copy of an 8 bit rgba/bgra texture into a new mappable buffer
+pub fn copy_texture_to_buffer(device: &Device, encoder: &mut CommandEncoder, texture: &wgpu::Texture) -> Buffer {
+    let (width, height) = (texture.width(), texture.height());
+    let bytes_per_row = padded_bytes_per_row(width);
+
+    let buffer = device.create_buffer(
+        &BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (bytes_per_row * height) as BufferAddress,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false
+        }
+    );
+
+    encoder.copy_texture_to_buffer(
+        TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All
+        },
+        TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(height)
+            }
+        },
+        texture.size()
+    );
+
+    buffer
+}
+
+// strips the row padding from a mapped readback buffer, swizzling bgra surfaces to rgba
+pub fn buffer_to_image(buffer: &Buffer, width: u32, height: u32, format: TextureFormat) -> Result<image::RgbaImage> {
+    let bytes_per_row = padded_bytes_per_row(width) as usize;
+    let row_len = width as usize * 4;
+    let is_bgra = matches!(format, TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb);
+
+    let mut pixels = Vec::with_capacity(row_len * height as usize);
+    {
+        let data = buffer.slice(..).get_mapped_range();
+        for row in data.chunks_exact(bytes_per_row).take(height as usize) {
+            pixels.extend_from_slice(&row[..row_len]);
+        }
+    }
+    buffer.unmap();
+
+    if is_bgra {
+        pixels.chunks_exact_mut(4).for_each(|p| p.swap(0, 2));
+    }
+
+    image::RgbaImage::from_raw(width, height, pixels).context("readback buffer is smaller than the image")
+}
+
+// blocks until the gpu has finished writing the buffer, native only since the browser can't block
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_buffer_blocking(device: &Device, buffer: &Buffer, width: u32, height: u32, format: TextureFormat) -> Result<image::RgbaImage> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    buffer.slice(..).map_async(MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(PollType::Wait)?;
+    receiver.recv()??;
+
+    buffer_to_image(buffer, width, height, format)
+}
+
+
+
+// a readback that finishes on its own, poll `try_take_image` each frame instead of blocking
+pub struct PendingCapture {
+    buffer: Buffer,
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+    result: Arc<Mutex<Option<Result<(), BufferAsyncError>>>>
+}
+
+impl PendingCapture {
+    // the copy into `buffer` must already be submitted, mapping a buffer with queued work is an error
+    pub fn new(buffer: Buffer, width: u32, height: u32, format: TextureFormat) -> Self {
+        let result = Arc::new(Mutex::new(None));
+        let callback_result = result.clone();
+        buffer.slice(..).map_async(MapMode::Read, move |r| {
+            *callback_result.lock().unwrap() = Some(r);
+        });
+
+        Self { buffer, width, height, format, result }
+    }
+
+    pub fn try_take_image(&self) -> Option<Result<image::RgbaImage>> {
+        let result = self.result.lock().unwrap().take()?;
+        Some(result.map_err(anyhow::Error::from).and_then(|_| buffer_to_image(&self.buffer, self.width, self.height, self.format)))
+    }
+}
+
+
+// png encoding is slow in debug builds, do it off the event loop
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_screenshot(image: image::RgbaImage) {
+    let file = format!("screenshot-{}.png", timestamp());
+    std::thread::spawn(move || {
+        match image.save(&file) {
+            Ok(_) => log::info!("Saved {}", file),
+            Err(e) => log::error!("Unable to save {}: {}", file, e)
+        }
+    });
+}
+
+// there's no filesystem in the browser, hand the png to the user as a download instead
+#[cfg(target_arch = "wasm32")]
+pub fn save_screenshot(image: image::RgbaImage) {
+    use wasm_bindgen::JsCast;
+
+    let file = format!("screenshot-{}.png", timestamp());
+    let mut png = Vec::new();
+    if let Err(e) = image.write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png) {
+        log::error!("Unable to encode {}: {}", file, e);
+        return;
+    }
+
+    let download = || -> Result<(), wasm_bindgen::JsValue> {
+        let bytes = js_sys::Uint8Array::from(png.as_slice());
+        let parts = js_sys::Array::of1(&bytes);
+        let options = web_sys::BlobPropertyBag::new();
+        options.set_type("image/png");
+        let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+        let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+
+        let document = web_sys::window().and_then(|w| w.document()).ok_or("no document")?;
+        let anchor = document.create_element("a")?.unchecked_into::<web_sys::HtmlAnchorElement>();
+        anchor.set_href(&url);
+        anchor.set_download(&file);
+        anchor.click();
+
+        web_sys::Url::revoke_object_url(&url)
+    };
+
+    if let Err(e) = download() {
+        log::error!("Unable to download {}: {:?}", file, e);
+    }
+}
+
+// UTC timestamp like 20251017-215302, days to civil date from Howard Hinnant's algorithm
+fn timestamp() -> String {
+    let secs = web_time::SystemTime::now()
+        .duration_since(web_time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0) as i64;
+
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}", year, month, day, rem / 3_600, rem % 3_600 / 60, rem % 60)
+}
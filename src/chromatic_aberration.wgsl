@@ -0,0 +1,29 @@
+// red and blue pulled apart along the line from the center, growing toward the edges like a cheap lens
+
+struct ChromaticAberrationUniform {
+    strength: f32,  // offset at the corners, in uv
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> aberration: ChromaticAberrationUniform;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let offset = (in.uv - 0.5) * 2.0 * aberration.strength;
+
+    let r = textureSampleLevel(source, source_sampler, in.uv + offset, 0.0).r;
+    let g = textureSampleLevel(source, source_sampler, in.uv, 0.0).g;
+    let b = textureSampleLevel(source, source_sampler, in.uv - offset, 0.0).b;
+    return vec4<f32>(r, g, b, 1.0);
+}
@@ -0,0 +1,170 @@
+#[cfg(target_arch="wasm32")]
+use web_time::Instant;
+
+#[cfg(not(target_arch="wasm32"))]
+use std::time::Instant;
+
+// frame timing, everything that animates should be scaled by `delta`. the camera follows input and so real time,
+// `unscaled_delta` or the fixed steps when `fixed_timestep` is set
+pub struct Clock {
+    last_tick: Instant,
+
+    pub delta: f32,             // scaled seconds since the last tick, 0 while paused
+    pub unscaled_delta: f32,    // real seconds since the last tick
+    pub total: f32,             // scaled seconds since start, stops while paused
+
+    pub paused: bool,
+    pub time_scale: f32,
+
+    // when set the camera advances in steps of exactly this many real seconds, pausing and scaling don't stop it
+    pub fixed_timestep: Option<f32>,
+    accumulator: f32,
+
+    // a long stall (window drag, breakpoint) shouldn't fast forward the simulation
+    pub max_delta: f32
+}
+
+
+impl Clock {
+    pub fn new() -> Self {
+        Self {
+            last_tick: Instant::now(),
+            delta: 0.0,
+            unscaled_delta: 0.0,
+            total: 0.0,
+            paused: false,
+            time_scale: 1.0,
+            fixed_timestep: None,
+            accumulator: 0.0,
+            max_delta: 0.25
+        }
+    }
+
+    // call once per frame, returns how many fixed steps to simulate (always 0 without a fixed timestep)
+    pub fn tick(&mut self) -> u32 {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_tick).as_secs_f32();
+        self.last_tick = now;
+        self.advance(elapsed)
+    }
+
+    // tick with the real time taken out, so the tests can pick it
+    fn advance(&mut self, elapsed: f32) -> u32 {
+        self.unscaled_delta = elapsed.min(self.max_delta);
+        self.delta = if self.paused { 0.0 } else { self.unscaled_delta * self.time_scale };
+        self.total += self.delta;
+
+        match self.fixed_timestep {
+            Some(step) => {
+                self.accumulator += self.unscaled_delta;
+                let steps = (self.accumulator / step).floor();
+                self.accumulator -= steps * step;
+                steps as u32
+            },
+            None => 0
+        }
+    }
+
+    // how far the frame is between the last two fixed steps, for blending their states
+    pub fn alpha(&self) -> f32 {
+        match self.fixed_timestep {
+            Some(step) => (self.accumulator / step).clamp(0.0, 1.0),
+            None => 1.0
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn set_fixed_timestep(&mut self, step: Option<f32>) {
+        self.fixed_timestep = step;
+        self.accumulator = 0.0;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn pause_stops_scaled_time() {
+        let mut clock = Clock::new();
+        clock.advance(0.1);
+        clock.toggle_pause();
+        clock.advance(0.1);
+        assert_eq!(clock.delta, 0.0);
+        assert_close(clock.unscaled_delta, 0.1);
+        assert_close(clock.total, 0.1);
+
+        clock.toggle_pause();
+        clock.advance(0.1);
+        assert_close(clock.total, 0.2);
+    }
+
+    #[test]
+    fn time_scale_scales_delta_not_real_time() {
+        let mut clock = Clock::new();
+        clock.time_scale = 0.5;
+        clock.advance(0.1);
+        assert_close(clock.delta, 0.05);
+        assert_close(clock.unscaled_delta, 0.1);
+        assert_close(clock.total, 0.05);
+    }
+
+    #[test]
+    fn long_stalls_are_clamped() {
+        let mut clock = Clock::new();
+        clock.advance(3.0);
+        assert_close(clock.unscaled_delta, clock.max_delta);
+        assert_close(clock.delta, clock.max_delta);
+    }
+
+    #[test]
+    fn no_fixed_steps_without_a_timestep() {
+        let mut clock = Clock::new();
+        assert_eq!(clock.advance(0.1), 0);
+        assert_eq!(clock.alpha(), 1.0);
+    }
+
+    // steps and deltas that are exact in binary, so the step boundaries don't round either way
+    #[test]
+    fn fixed_timestep_carries_the_remainder() {
+        let mut clock = Clock::new();
+        clock.set_fixed_timestep(Some(0.0625));
+
+        assert_eq!(clock.advance(0.15625), 2);
+        assert_eq!(clock.alpha(), 0.5);
+
+        // the half step left over plus this one make a whole step
+        assert_eq!(clock.advance(0.03125), 1);
+        assert_eq!(clock.alpha(), 0.0);
+
+        assert_eq!(clock.advance(0.015625), 0);
+        assert_eq!(clock.alpha(), 0.25);
+    }
+
+    #[test]
+    fn fixed_steps_ignore_pause_and_time_scale() {
+        let mut clock = Clock::new();
+        clock.set_fixed_timestep(Some(0.0625));
+        clock.paused = true;
+        clock.time_scale = 0.0;
+        assert_eq!(clock.advance(0.125), 2);
+    }
+
+    #[test]
+    fn changing_the_timestep_drops_the_remainder() {
+        let mut clock = Clock::new();
+        clock.set_fixed_timestep(Some(0.0625));
+        clock.advance(0.03125);
+        clock.set_fixed_timestep(Some(0.0625));
+        assert_eq!(clock.alpha(), 0.0);
+        assert_eq!(clock.advance(0.03125), 0);
+    }
+}
@@ -0,0 +1,114 @@
+use wgpu::*;
+
+// decides which formats carry color so every backend does the same conversions. shaders only ever see
+// linear values: color textures are sampled through srgb formats (decoded by the sampler) and frames are
+// written through an srgb view (encoded by the blend unit), so native, webgpu and gl produce the same bytes
+
+pub fn texture_format(srgb: bool) -> TextureFormat {
+    if srgb { TextureFormat::Rgba8UnormSrgb } else { TextureFormat::Rgba8Unorm }
+}
+
+// the format to configure the surface with plus the view formats to allow on it. browsers only hand out
+// linear canvas formats, those get an srgb view to render through instead
+pub fn surface_formats(capabilities: &SurfaceCapabilities, downlevel: &DownlevelCapabilities) -> (TextureFormat, Vec<TextureFormat>) {
+    let preferred = capabilities.formats[0];
+    let srgb = preferred.add_srgb_suffix();
+
+    if preferred.is_srgb() {
+        return (preferred, vec![]);
+    }
+    if capabilities.formats.contains(&srgb) {
+        return (srgb, vec![]);
+    }
+    if srgb != preferred && downlevel.flags.contains(DownlevelFlags::SURFACE_VIEW_FORMATS) {
+        return (preferred, vec![srgb]);
+    }
+    if let Some(format) = capabilities.formats.iter().find(|f| f.is_srgb()) {
+        return (*format, vec![]);
+    }
+
+    log::warn!("Surface has no sRGB format or view, colors will look too dark");
+    (preferred, vec![])
+}
+
+// the format pipelines target and surface textures are viewed as
+pub fn render_format(config: &SurfaceConfiguration) -> TextureFormat {
+    config.view_formats.first().copied().unwrap_or(config.format)
+}
+
+pub fn surface_view(texture: &Texture, config: &SurfaceConfiguration) -> TextureView {
+    texture.create_view(&TextureViewDescriptor {
+        format: Some(render_format(config)),
+        ..Default::default()
+    })
+}
+
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::{capture, helper, postprocess::FullscreenPass};
+
+    const GRAY: &str = "
+        @fragment
+        fn fs_main() -> @location(0) vec4<f32> {
+            return vec4<f32>(0.214, 0.214, 0.214, 1.0);
+        }
+    ";
+
+    // draws linear 0.214 into a texture of `format` through a view of `view_format` and reads back the stored bytes
+    fn render_gray(device: &Device, queue: &Queue, format: TextureFormat, view_format: TextureFormat) -> image::RgbaImage {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Color Test Target"),
+            size: Extent3d { width: 4, height: 4, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            view_formats: if view_format == format { &[] } else { std::slice::from_ref(&view_format) }
+        });
+        let view = texture.create_view(&TextureViewDescriptor {
+            format: Some(view_format),
+            ..Default::default()
+        });
+
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Color Test Shader"),
+            source: ShaderSource::Wgsl(GRAY.into())
+        });
+        let pass = FullscreenPass::new(device, "Color Test Pipeline", &FullscreenPass::vertex_shader(device), (&shader, "fs_main"), &[], view_format, None);
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: Some("Color Test Encoder") });
+        pass.draw(&mut encoder, &view, &[], 0.0);
+        let buffer = capture::copy_texture_to_buffer(device, &mut encoder, &texture);
+        queue.submit([encoder.finish()]);
+
+        capture::read_buffer_blocking(device, &buffer, 4, 4, format).unwrap()
+    }
+
+    fn assert_encoded(image: &image::RgbaImage) {
+        for pixel in image.pixels() {
+            for &channel in &pixel.0[..3] {
+                assert!(channel.abs_diff(127) <= 1, "linear 0.214 stored as {channel}, expected srgb 127");
+            }
+        }
+    }
+
+    // these need a gpu adapter, run them with cargo test -- --ignored
+    #[test]
+    #[ignore = "needs a gpu adapter"]
+    fn srgb_target_encodes_output() {
+        let (_, device, queue) = pollster::block_on(helper::create_headless_device()).expect("no gpu adapter");
+        assert_encoded(&render_gray(&device, &queue, texture_format(true), texture_format(true)));
+    }
+
+    // gl can't reinterpret a texture through another format, so this one needs vulkan, metal or dx12
+    #[test]
+    #[ignore = "needs a gpu adapter with view format support"]
+    fn srgb_view_of_linear_target_encodes_output() {
+        let (adapter, device, queue) = pollster::block_on(helper::create_headless_device()).expect("no gpu adapter");
+        assert!(adapter.get_downlevel_capabilities().flags.contains(DownlevelFlags::VIEW_FORMATS), "{} can't create srgb views of linear textures", adapter.get_info().name);
+        assert_encoded(&render_gray(&device, &queue, texture_format(false), texture_format(true)));
+    }
+}
@@ -0,0 +1,52 @@
+// looks the color up in a 3d table, the table is indexed and filled with srgb encoded values like the
+// strips grading tools export
+
+struct ColorGradingUniform {
+    contribution: f32,
+    lut_size: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> grading: ColorGradingUniform;
+
+@group(1) @binding(1)
+var lut: texture_3d<f32>;
+
+@group(1) @binding(2)
+var lut_sampler: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(source, source_sampler, in.uv, 0.0).rgb;
+
+    // texel centers, so 0 and 1 land on the first and last entries
+    let scale = (grading.lut_size - 1.0) / grading.lut_size;
+    let offset = 0.5 / grading.lut_size;
+    let encoded = linear_to_srgb(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)));
+    let graded = textureSampleLevel(lut, lut_sampler, encoded * scale + offset, 0.0).rgb;
+
+    return vec4<f32>(mix(color, srgb_to_linear(graded), grading.contribution), 1.0);
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
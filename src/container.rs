@@ -0,0 +1,221 @@
+use anyhow::Result;
+use wgpu::{AstcBlock, AstcChannel, TextureFormat};
+
+use crate::basis;
+
+const KTX2_MAGIC: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const DDS_MAGIC: [u8; 4] = *b"DDS ";
+
+// a 2d texture as stored in a ktx2 or dds file, levels are tightly packed blocks from the full size down
+pub struct ContainerImage {
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>
+}
+
+pub fn is_container(bytes: &[u8]) -> bool {
+    bytes.starts_with(&KTX2_MAGIC) || bytes.starts_with(&DDS_MAGIC)
+}
+
+pub fn parse(bytes: &[u8]) -> Result<ContainerImage> {
+    if bytes.starts_with(&KTX2_MAGIC) {
+        parse_ktx2(bytes)
+    } else if bytes.starts_with(&DDS_MAGIC) {
+        parse_dds(bytes)
+    } else {
+        anyhow::bail!("not a ktx2 or dds file")
+    }
+}
+
+fn parse_ktx2(bytes: &[u8]) -> Result<ContainerImage> {
+    let reader = ktx2::Reader::new(bytes).map_err(|e| anyhow::anyhow!("invalid ktx2: {e:?}"))?;
+    let header = reader.header();
+
+    if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+        anyhow::bail!("only 2d ktx2 textures are supported");
+    }
+    let (width, height) = (header.pixel_width, header.pixel_height.max(1));
+
+    // basis universal (etc1s in basislz or uastc) has no vk format and is transcoded first
+    let color_model = reader.dfd_blocks()
+        .find_map(|block| ktx2::DfdBlockBasic::parse(block.data).ok())
+        .and_then(|block| block.header.color_model);
+    if header.supercompression_scheme == Some(ktx2::SupercompressionScheme::BasisLZ) || color_model == Some(ktx2::ColorModel::ETC1S) {
+        if header.supercompression_scheme != Some(ktx2::SupercompressionScheme::BasisLZ) {
+            anyhow::bail!("etc1s ktx2 textures have to be basislz supercompressed");
+        }
+        let levels: Vec<&[u8]> = reader.levels().map(|level| level.data).collect();
+        return basis::transcode_etc1s(reader.supercompression_global_data(), &levels, width, height);
+    }
+    let uastc = color_model == Some(ktx2::ColorModel::UASTC);
+
+    let format = if uastc {
+        basis::UASTC_FORMAT
+    } else {
+        header.format
+            .and_then(ktx2_format)
+            .ok_or_else(|| anyhow::anyhow!("unsupported ktx2 format {:?}", header.format))?
+    };
+
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_copy_size(None).unwrap() as usize;
+
+    let levels = reader.levels().enumerate().map(|(mip, level)| -> Result<Vec<u8>> {
+        let data = match header.supercompression_scheme {
+            None => level.data.to_vec(),
+            Some(ktx2::SupercompressionScheme::Zstandard) => {
+                let mut data = Vec::new();
+                let mut decoder = ruzstd::decoding::StreamingDecoder::new(level.data).map_err(|e| anyhow::anyhow!("invalid zstd level: {e}"))?;
+                std::io::Read::read_to_end(&mut decoder, &mut data)?;
+                data
+            },
+            Some(ktx2::SupercompressionScheme::ZLIB) => miniz_oxide::inflate::decompress_to_vec_zlib(level.data)
+                .map_err(|e| anyhow::anyhow!("invalid zlib level: {e:?}"))?,
+            Some(scheme) => anyhow::bail!("unsupported ktx2 supercompression {scheme:?}")
+        };
+        if header.supercompression_scheme.is_some() && data.len() as u64 != level.uncompressed_byte_length {
+            anyhow::bail!("ktx2 mip {mip} inflates to {} bytes, the level index says {}", data.len(), level.uncompressed_byte_length);
+        }
+
+        // every block of the level has to be there before it goes anywhere near write_texture
+        let (w, h) = ((width >> mip).max(1), (height >> mip).max(1));
+        let size = (w.div_ceil(block_width) * h.div_ceil(block_height)) as usize * block_size;
+        let Some(level_data) = data.get(..size) else {
+            anyhow::bail!("ktx2 mip {mip} is truncated, {} bytes of {size}", data.len());
+        };
+        if uastc { basis::transcode_uastc(level_data) } else { Ok(level_data.to_vec()) }
+    }).collect::<Result<Vec<_>>>()?;
+
+    Ok(ContainerImage { format, width, height, levels })
+}
+
+fn ktx2_format(format: ktx2::Format) -> Option<TextureFormat> {
+    use ktx2::Format as F;
+
+    let astc = |block, channel| TextureFormat::Astc { block, channel };
+    Some(match format {
+        F::R8G8B8A8_UNORM => TextureFormat::Rgba8Unorm,
+        F::R8G8B8A8_SRGB => TextureFormat::Rgba8UnormSrgb,
+        F::B8G8R8A8_UNORM => TextureFormat::Bgra8Unorm,
+        F::B8G8R8A8_SRGB => TextureFormat::Bgra8UnormSrgb,
+        // bc1 rgb decodes opaque either way
+        F::BC1_RGB_UNORM_BLOCK | F::BC1_RGBA_UNORM_BLOCK => TextureFormat::Bc1RgbaUnorm,
+        F::BC1_RGB_SRGB_BLOCK | F::BC1_RGBA_SRGB_BLOCK => TextureFormat::Bc1RgbaUnormSrgb,
+        F::BC2_UNORM_BLOCK => TextureFormat::Bc2RgbaUnorm,
+        F::BC2_SRGB_BLOCK => TextureFormat::Bc2RgbaUnormSrgb,
+        F::BC3_UNORM_BLOCK => TextureFormat::Bc3RgbaUnorm,
+        F::BC3_SRGB_BLOCK => TextureFormat::Bc3RgbaUnormSrgb,
+        F::BC4_UNORM_BLOCK => TextureFormat::Bc4RUnorm,
+        F::BC5_UNORM_BLOCK => TextureFormat::Bc5RgUnorm,
+        F::BC7_UNORM_BLOCK => TextureFormat::Bc7RgbaUnorm,
+        F::BC7_SRGB_BLOCK => TextureFormat::Bc7RgbaUnormSrgb,
+        F::ETC2_R8G8B8_UNORM_BLOCK => TextureFormat::Etc2Rgb8Unorm,
+        F::ETC2_R8G8B8_SRGB_BLOCK => TextureFormat::Etc2Rgb8UnormSrgb,
+        F::ETC2_R8G8B8A1_UNORM_BLOCK => TextureFormat::Etc2Rgb8A1Unorm,
+        F::ETC2_R8G8B8A1_SRGB_BLOCK => TextureFormat::Etc2Rgb8A1UnormSrgb,
+        F::ETC2_R8G8B8A8_UNORM_BLOCK => TextureFormat::Etc2Rgba8Unorm,
+        F::ETC2_R8G8B8A8_SRGB_BLOCK => TextureFormat::Etc2Rgba8UnormSrgb,
+        F::EAC_R11_UNORM_BLOCK => TextureFormat::EacR11Unorm,
+        F::EAC_R11G11_UNORM_BLOCK => TextureFormat::EacRg11Unorm,
+        F::ASTC_4x4_UNORM_BLOCK => astc(AstcBlock::B4x4, AstcChannel::Unorm),
+        F::ASTC_4x4_SRGB_BLOCK => astc(AstcBlock::B4x4, AstcChannel::UnormSrgb),
+        F::ASTC_5x4_UNORM_BLOCK => astc(AstcBlock::B5x4, AstcChannel::Unorm),
+        F::ASTC_5x4_SRGB_BLOCK => astc(AstcBlock::B5x4, AstcChannel::UnormSrgb),
+        F::ASTC_5x5_UNORM_BLOCK => astc(AstcBlock::B5x5, AstcChannel::Unorm),
+        F::ASTC_5x5_SRGB_BLOCK => astc(AstcBlock::B5x5, AstcChannel::UnormSrgb),
+        F::ASTC_6x5_UNORM_BLOCK => astc(AstcBlock::B6x5, AstcChannel::Unorm),
+        F::ASTC_6x5_SRGB_BLOCK => astc(AstcBlock::B6x5, AstcChannel::UnormSrgb),
+        F::ASTC_6x6_UNORM_BLOCK => astc(AstcBlock::B6x6, AstcChannel::Unorm),
+        F::ASTC_6x6_SRGB_BLOCK => astc(AstcBlock::B6x6, AstcChannel::UnormSrgb),
+        F::ASTC_8x5_UNORM_BLOCK => astc(AstcBlock::B8x5, AstcChannel::Unorm),
+        F::ASTC_8x5_SRGB_BLOCK => astc(AstcBlock::B8x5, AstcChannel::UnormSrgb),
+        F::ASTC_8x6_UNORM_BLOCK => astc(AstcBlock::B8x6, AstcChannel::Unorm),
+        F::ASTC_8x6_SRGB_BLOCK => astc(AstcBlock::B8x6, AstcChannel::UnormSrgb),
+        F::ASTC_8x8_UNORM_BLOCK => astc(AstcBlock::B8x8, AstcChannel::Unorm),
+        F::ASTC_8x8_SRGB_BLOCK => astc(AstcBlock::B8x8, AstcChannel::UnormSrgb),
+        F::ASTC_10x5_UNORM_BLOCK => astc(AstcBlock::B10x5, AstcChannel::Unorm),
+        F::ASTC_10x5_SRGB_BLOCK => astc(AstcBlock::B10x5, AstcChannel::UnormSrgb),
+        F::ASTC_10x6_UNORM_BLOCK => astc(AstcBlock::B10x6, AstcChannel::Unorm),
+        F::ASTC_10x6_SRGB_BLOCK => astc(AstcBlock::B10x6, AstcChannel::UnormSrgb),
+        F::ASTC_10x8_UNORM_BLOCK => astc(AstcBlock::B10x8, AstcChannel::Unorm),
+        F::ASTC_10x8_SRGB_BLOCK => astc(AstcBlock::B10x8, AstcChannel::UnormSrgb),
+        F::ASTC_10x10_UNORM_BLOCK => astc(AstcBlock::B10x10, AstcChannel::Unorm),
+        F::ASTC_10x10_SRGB_BLOCK => astc(AstcBlock::B10x10, AstcChannel::UnormSrgb),
+        F::ASTC_12x10_UNORM_BLOCK => astc(AstcBlock::B12x10, AstcChannel::Unorm),
+        F::ASTC_12x10_SRGB_BLOCK => astc(AstcBlock::B12x10, AstcChannel::UnormSrgb),
+        F::ASTC_12x12_UNORM_BLOCK => astc(AstcBlock::B12x12, AstcChannel::Unorm),
+        F::ASTC_12x12_SRGB_BLOCK => astc(AstcBlock::B12x12, AstcChannel::UnormSrgb),
+        _ => return None
+    })
+}
+
+fn parse_dds(bytes: &[u8]) -> Result<ContainerImage> {
+    let dds = ddsfile::Dds::read(bytes).map_err(|e| anyhow::anyhow!("invalid dds: {e}"))?;
+
+    if dds.header.caps2.contains(ddsfile::Caps2::CUBEMAP) || dds.get_depth() > 1 || dds.get_num_array_layers() > 1 {
+        anyhow::bail!("only 2d dds textures are supported");
+    }
+
+    let format = match (dds.get_dxgi_format(), dds.get_d3d_format()) {
+        (Some(format), _) => dxgi_format(format),
+        (None, Some(format)) => d3d_format(format),
+        _ => None
+    }.ok_or_else(|| anyhow::anyhow!("unsupported dds format {:?}", dds.get_dxgi_format().map(|f| format!("{f:?}")).or(dds.get_d3d_format().map(|f| format!("{f:?}")))))?;
+
+    let (width, height) = (dds.get_width(), dds.get_height());
+    let data = dds.get_data(0).map_err(|e| anyhow::anyhow!("invalid dds data: {e}"))?;
+
+    // dds stores the mip chain back to back without a level index
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_copy_size(None).unwrap() as usize;
+    let mut levels = Vec::new();
+    let mut offset = 0;
+    for level in 0..dds.get_num_mipmap_levels().max(1) {
+        let (w, h) = ((width >> level).max(1), (height >> level).max(1));
+        let size = (w.div_ceil(block_width) * h.div_ceil(block_height)) as usize * block_size;
+        let Some(level_data) = data.get(offset..offset + size) else {
+            anyhow::bail!("dds mip {level} is truncated");
+        };
+        levels.push(level_data.to_vec());
+        offset += size;
+    }
+
+    Ok(ContainerImage { format, width, height, levels })
+}
+
+fn dxgi_format(format: ddsfile::DxgiFormat) -> Option<TextureFormat> {
+    use ddsfile::DxgiFormat as F;
+
+    Some(match format {
+        F::R8G8B8A8_UNorm => TextureFormat::Rgba8Unorm,
+        F::R8G8B8A8_UNorm_sRGB => TextureFormat::Rgba8UnormSrgb,
+        F::B8G8R8A8_UNorm => TextureFormat::Bgra8Unorm,
+        F::B8G8R8A8_UNorm_sRGB => TextureFormat::Bgra8UnormSrgb,
+        F::BC1_UNorm => TextureFormat::Bc1RgbaUnorm,
+        F::BC1_UNorm_sRGB => TextureFormat::Bc1RgbaUnormSrgb,
+        F::BC2_UNorm => TextureFormat::Bc2RgbaUnorm,
+        F::BC2_UNorm_sRGB => TextureFormat::Bc2RgbaUnormSrgb,
+        F::BC3_UNorm => TextureFormat::Bc3RgbaUnorm,
+        F::BC3_UNorm_sRGB => TextureFormat::Bc3RgbaUnormSrgb,
+        F::BC4_UNorm => TextureFormat::Bc4RUnorm,
+        F::BC5_UNorm => TextureFormat::Bc5RgUnorm,
+        F::BC7_UNorm => TextureFormat::Bc7RgbaUnorm,
+        F::BC7_UNorm_sRGB => TextureFormat::Bc7RgbaUnormSrgb,
+        _ => return None
+    })
+}
+
+fn d3d_format(format: ddsfile::D3DFormat) -> Option<TextureFormat> {
+    use ddsfile::D3DFormat as F;
+
+    // the d3d names list channels from the most significant byte
+    Some(match format {
+        F::A8B8G8R8 => TextureFormat::Rgba8Unorm,
+        F::A8R8G8B8 => TextureFormat::Bgra8Unorm,
+        F::DXT1 => TextureFormat::Bc1RgbaUnorm,
+        F::DXT3 => TextureFormat::Bc2RgbaUnorm,
+        F::DXT5 => TextureFormat::Bc3RgbaUnorm,
+        _ => return None
+    })
+}
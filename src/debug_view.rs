@@ -0,0 +1,328 @@
+use wgpu::*;
+
+use crate::{camera::Camera, instance::InstanceRaw, model::Aabb, postprocess::FullscreenPass, render::MAIN_PASS_FORMATS, scene::{DrawScene, Scene}, shader_structs::Vertex, texture::Texture};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugView {
+    Lit,
+    Albedo,
+    Normals,
+    Uvs,
+    Depth,
+    Overdraw,
+    Wireframe
+}
+
+impl DebugView {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Lit => Self::Albedo,
+            Self::Albedo => Self::Normals,
+            Self::Normals => Self::Uvs,
+            Self::Uvs => Self::Depth,
+            Self::Depth => Self::Overdraw,
+            Self::Overdraw => Self::Wireframe,
+            Self::Wireframe => Self::Lit
+        }
+    }
+
+    // matches the DEBUG_ constants in shader.wgsl and debug_view.wgsl
+    fn index(self) -> u32 {
+        match self {
+            Self::Lit => 0,
+            Self::Albedo => 1,
+            Self::Normals => 2,
+            Self::Uvs => 3,
+            Self::Depth => 4,
+            Self::Overdraw => 5,
+            Self::Wireframe => 6
+        }
+    }
+}
+
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DebugUniform {
+    view: u32,
+    depth_near: f32,    // view distances the depth view maps to black and white, around the scene
+    depth_far: f32,
+    _padding: u32
+}
+
+// what a debug pipeline changes from the lit one
+struct PipelineOptions<'a> {
+    label: &'a str,
+    shader: &'a ShaderModule,
+    fragment_entry: &'a str,
+    position_only: bool,        // reads Mesh::wireframe_buffer instead of the shared vertices
+    blend: BlendState,
+    cull_mode: Option<Face>,
+    polygon_mode: PolygonMode,
+    depth_test: bool            // off draws and counts every fragment, hidden or not
+}
+
+
+// the views the main pass can draw instead of the lit scene. they share fs_debug in shader.wgsl, only overdraw and
+// wireframe need pipelines of their own, and all of them skip taa, exposure and post processing
+pub struct DebugViews {
+    view: DebugView,
+    pub uniform_buffer: Buffer,     // bound with the frame uniforms for fs_debug
+    fill_pipeline: RenderPipeline,
+    overdraw_pipeline: RenderPipeline,
+    wireframe_pipeline: RenderPipeline,
+    line_mode: bool,                // POLYGON_MODE_LINE is there, otherwise barycentric.wgsl draws the edges
+
+    // kept to rebuild the pipelines when the sample count changes
+    layout: PipelineLayout,
+    shader: ShaderModule,
+    wireframe_shader: ShaderModule,
+    depth_compare: CompareFunction,
+
+    resolve: FullscreenPass,
+    input_layout: BindGroupLayout,
+    sampler: Sampler,
+    input_bind_group: BindGroup,    // the hdr target
+    params_bind_group: BindGroup
+}
+
+
+impl DebugViews {
+    // `layout` and `shader` are the lit pipeline's, fs_debug lives next to fs_main
+    pub fn new(device: &Device, (layout, shader): (&PipelineLayout, &ShaderModule), vertex_shader: &ShaderModule, depth_compare: CompareFunction, sample_count: u32, hdr: &Texture, output_format: TextureFormat) -> Self {
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Debug View Uniform Buffer"),
+            size: std::mem::size_of::<DebugUniform>() as BufferAddress,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let wireframe_shader = device.create_shader_module(include_wgsl!("barycentric.wgsl"));
+        let line_mode = device.features().contains(Features::POLYGON_MODE_LINE);
+        if !line_mode {
+            log::info!("POLYGON_MODE_LINE unavailable, the wireframe view draws barycentric edges");
+        }
+
+        let [fill_pipeline, overdraw_pipeline, wireframe_pipeline] = Self::create_pipelines(device, layout, shader, &wireframe_shader, line_mode, depth_compare, sample_count);
+
+        let resolve_shader = device.create_shader_module(include_wgsl!("debug_view.wgsl"));
+        let input_layout = FullscreenPass::input_layout(device);
+        let sampler = FullscreenPass::create_sampler(device);
+
+        let params_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Debug View Bind Group Layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None
+                },
+                count: None
+            }]
+        });
+
+        let params_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Debug View Bind Group"),
+            layout: &params_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding()
+            }]
+        });
+
+        let resolve = FullscreenPass::new(device, "Debug View Resolve Pipeline", vertex_shader, (&resolve_shader, "fs_main"), &[&input_layout, &params_layout], output_format, None);
+
+        Self {
+            view: DebugView::Lit,
+            uniform_buffer,
+            fill_pipeline,
+            overdraw_pipeline,
+            wireframe_pipeline,
+            line_mode,
+            layout: layout.clone(),
+            shader: shader.clone(),
+            wireframe_shader,
+            depth_compare,
+            resolve,
+            input_bind_group: FullscreenPass::input_bind_group(device, &input_layout, &hdr.view, &sampler),
+            input_layout,
+            sampler,
+            params_bind_group
+        }
+    }
+
+    fn create_pipelines(device: &Device, layout: &PipelineLayout, shader: &ShaderModule, wireframe_shader: &ShaderModule, line_mode: bool, depth_compare: CompareFunction, sample_count: u32) -> [RenderPipeline; 3] {
+        let fill = PipelineOptions {
+            label: "Debug View Pipeline",
+            shader,
+            fragment_entry: "fs_debug",
+            position_only: false,
+            blend: BlendState::REPLACE,
+            cull_mode: Some(Face::Back),
+            polygon_mode: PolygonMode::Fill,
+            depth_test: true
+        };
+
+        let additive = BlendComponent {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add
+        };
+        let overdraw = PipelineOptions {
+            label: "Overdraw Pipeline",
+            blend: BlendState { color: additive, alpha: additive },
+            depth_test: false,
+            ..fill
+        };
+
+        // both sides of every triangle, seen through the ones in front
+        let wireframe = if line_mode {
+            PipelineOptions {
+                label: "Wireframe Pipeline",
+                blend: BlendState::ALPHA_BLENDING,
+                cull_mode: None,
+                polygon_mode: PolygonMode::Line,
+                depth_test: false,
+                ..fill
+            }
+        } else {
+            PipelineOptions {
+                label: "Wireframe Pipeline",
+                shader: wireframe_shader,
+                fragment_entry: "fs_main",
+                position_only: true,
+                blend: BlendState::ALPHA_BLENDING,
+                cull_mode: None,
+                polygon_mode: PolygonMode::Fill,
+                depth_test: false
+            }
+        };
+
+        [fill, overdraw, wireframe].map(|options| create_pipeline(device, layout, &options, depth_compare, sample_count))
+    }
+
+    pub fn view(&self) -> DebugView {
+        self.view
+    }
+
+    // the wireframe view without POLYGON_MODE_LINE draws buffers the scene builds only now
+    pub fn select(&mut self, device: &Device, scene: &mut Scene, view: DebugView) {
+        if view == DebugView::Wireframe && !self.line_mode {
+            scene.build_wireframes(device);
+        }
+        self.view = view;
+    }
+
+    pub fn set_sample_count(&mut self, device: &Device, sample_count: u32) {
+        [self.fill_pipeline, self.overdraw_pipeline, self.wireframe_pipeline] = Self::create_pipelines(device, &self.layout, &self.shader, &self.wireframe_shader, self.line_mode, self.depth_compare, sample_count);
+    }
+
+    // call after the hdr target was recreated
+    pub fn resize(&mut self, device: &Device, hdr: &Texture) {
+        self.input_bind_group = FullscreenPass::input_bind_group(device, &self.input_layout, &hdr.view, &self.sampler);
+    }
+
+    // `bounds` are the scene's, the depth view spreads its range over them instead of the whole clip range
+    pub fn update(&self, queue: &Queue, camera: &Camera, bounds: Option<Aabb>) {
+        let projection = &camera.projection;
+        let (near, far) = match bounds {
+            Some(bounds) => {
+                let distance = (bounds.center() - camera.eye()).dot(&camera.forward());
+                let radius = bounds.half_extents().norm();
+                (distance - radius, distance + radius)
+            },
+            None => (projection.znear, projection.zfar.unwrap_or(projection.znear + 1.0))
+        };
+
+        let depth_near = near.max(projection.znear);
+        let uniform = DebugUniform {
+            view: self.view.index(),
+            depth_near,
+            depth_far: projection.zfar.map_or(far, |zfar| far.min(zfar)).max(depth_near + 1e-3),
+            _padding: 0
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // inside the main pass in place of the lit scene, with the camera, frame and environment groups already bound
+    pub fn draw(&self, render_pass: &mut RenderPass, scene: &Scene) {
+        match self.view {
+            DebugView::Lit => (),
+            DebugView::Overdraw => {
+                render_pass.set_pipeline(&self.overdraw_pipeline);
+                render_pass.draw_scene(scene);
+            },
+            DebugView::Wireframe => {
+                render_pass.set_pipeline(&self.wireframe_pipeline);
+                if self.line_mode {
+                    render_pass.draw_scene(scene);
+                } else {
+                    render_pass.draw_scene_wireframe(scene);
+                }
+            },
+            _ => {
+                render_pass.set_pipeline(&self.fill_pipeline);
+                render_pass.draw_scene(scene);
+            }
+        }
+    }
+
+    // the hdr target onto the output, instead of the tonemap and post processing
+    pub fn resolve(&self, encoder: &mut CommandEncoder, output: &TextureView) {
+        self.resolve.draw(encoder, output, &[&self.input_bind_group, &self.params_bind_group], 0.0);
+    }
+}
+
+
+fn create_pipeline(device: &Device, layout: &PipelineLayout, options: &PipelineOptions, depth_compare: CompareFunction, sample_count: u32) -> RenderPipeline {
+    let position_layout = VertexBufferLayout {
+        array_stride: std::mem::size_of::<[f32; 3]>() as BufferAddress,
+        step_mode: VertexStepMode::Vertex,
+        attributes: &vertex_attr_array![0 => Float32x3]
+    };
+    let vertex_buffer_layout = if options.position_only { position_layout } else { Vertex::desc() };
+
+    // the same targets as the lit pipeline, only the color is blended
+    let targets = MAIN_PASS_FORMATS.iter().enumerate().map(|(i, &format)| Some(ColorTargetState {
+        format,
+        blend: (i == 0).then_some(options.blend),
+        write_mask: ColorWrites::ALL
+    })).collect::<Vec<_>>();
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some(options.label),
+        layout: Some(layout),
+        vertex: VertexState {
+            module: options.shader,
+            entry_point: Some("vs_main"),
+            compilation_options: PipelineCompilationOptions::default(),
+            buffers: &[vertex_buffer_layout, InstanceRaw::desc()]
+        },
+        fragment: Some(FragmentState {
+            module: options.shader,
+            entry_point: Some(options.fragment_entry),
+            compilation_options: PipelineCompilationOptions::default(),
+            targets: &targets
+        }),
+        primitive: PrimitiveState {
+            cull_mode: options.cull_mode,
+            polygon_mode: options.polygon_mode,
+            ..Default::default()
+        },
+        depth_stencil: Some(DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: options.depth_test,
+            depth_compare: if options.depth_test { depth_compare } else { CompareFunction::Always },
+            stencil: StencilState::default(),
+            bias: DepthBiasState::default()
+        }),
+        multisample: MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+        cache: None
+    })
+}
@@ -0,0 +1,57 @@
+// puts a debug view on screen in place of the tonemap and post processing. the main pass already wrote it in
+// display colors, except overdraw which it counted and which gets its heatmap here
+
+// matches debug_view::DebugUniform
+struct DebugView {
+    view: u32,
+    depth_near: f32,
+    depth_far: f32,
+    _padding: u32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> debug_view: DebugView;
+
+const DEBUG_OVERDRAW = 5u;
+const MAX_OVERDRAW = 8.0;   // fragments per pixel shown as the hottest color
+
+// black, blue, green, yellow, red, white as the count goes up
+fn heatmap(t: f32) -> vec3<f32> {
+    var colors = array<vec3<f32>, 6>(
+        vec3<f32>(0.0, 0.0, 0.0),
+        vec3<f32>(0.0, 0.0, 1.0),
+        vec3<f32>(0.0, 1.0, 0.0),
+        vec3<f32>(1.0, 1.0, 0.0),
+        vec3<f32>(1.0, 0.0, 0.0),
+        vec3<f32>(1.0, 1.0, 1.0),
+    );
+    let scaled = saturate(t) * 5.0;
+    let index = min(u32(scaled), 4u);
+    return mix(colors[index], colors[index + 1u], scaled - f32(index));
+}
+
+fn srgb_to_linear(srgb_color: vec3<f32>) -> vec3<f32> {
+    let low = srgb_color / 12.92;
+    let high = pow((srgb_color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, srgb_color <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(source, source_sampler, in.uv, 0.0).rgb;
+    if debug_view.view == DEBUG_OVERDRAW {
+        return vec4<f32>(srgb_to_linear(heatmap(color.r / MAX_OVERDRAW)), 1.0);
+    }
+    return vec4<f32>(color, 1.0);
+}
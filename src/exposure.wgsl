@@ -0,0 +1,82 @@
+// auto exposure, a log luminance histogram of the hdr target reduced to one adapted average on the gpu
+
+struct TonemapUniform {
+    tonemapper: u32,
+    auto_exposure: u32,
+    exposure: f32,
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    adaptation: f32,        // share of this frame's average blended into the adapted luminance
+}
+
+@group(0) @binding(0)
+var hdr: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<uniform> tonemap: TonemapUniform;
+
+// 256 bins, bin 0 counts pixels too dark to meter and the rest split the log range evenly
+@group(0) @binding(2)
+var<storage, read_write> histogram: array<atomic<u32>, 256>;
+
+@group(0) @binding(3)
+var<storage, read_write> adapted_luminance: f32;
+
+var<workgroup> bins: array<atomic<u32>, 256>;
+var<workgroup> weighted: array<f32, 256>;
+
+fn luminance_bin(color: vec3<f32>) -> u32 {
+    let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    if luminance < exp2(tonemap.min_log_luminance) {
+        return 0u;
+    }
+    let t = clamp((log2(luminance) - tonemap.min_log_luminance) / tonemap.log_luminance_range, 0.0, 1.0);
+    return u32(t * 254.0) + 1u;
+}
+
+// each workgroup counts into shared memory first so the global atomics only see one add per bin
+@compute @workgroup_size(16, 16)
+fn build_histogram(@builtin(global_invocation_id) id: vec3<u32>, @builtin(local_invocation_index) index: u32) {
+    atomicStore(&bins[index], 0u);
+    workgroupBarrier();
+
+    if all(id.xy < textureDimensions(hdr)) {
+        atomicAdd(&bins[luminance_bin(textureLoad(hdr, id.xy, 0).rgb)], 1u);
+    }
+    workgroupBarrier();
+
+    atomicAdd(&histogram[index], atomicLoad(&bins[index]));
+}
+
+// a single workgroup, one thread per bin. clears the histogram for the next frame as it reads it
+@compute @workgroup_size(256)
+fn average_luminance(@builtin(local_invocation_index) index: u32) {
+    let size = textureDimensions(hdr);
+    let pixels = f32(size.x * size.y);
+
+    // summed as shares of the frame, count * index in u32 overflows past ~16 million pixels
+    let count = atomicExchange(&histogram[index], 0u);
+    weighted[index] = f32(count) / pixels * f32(index);
+    workgroupBarrier();
+
+    for (var stride = 128u; stride > 0u; stride >>= 1u) {
+        if index < stride {
+            weighted[index] += weighted[index + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if index == 0u {
+        let metered = 1.0 - f32(count) / pixels;
+        if metered <= 0.0 {
+            return;
+        }
+
+        // mean bin back to log luminance. bin i spans steps i - 1 to i of the range, so half a bin comes off to land in its middle
+        let mean_bin = weighted[0] / metered - 0.5;
+        let log_luminance = mean_bin / 254.0 * tonemap.log_luminance_range + tonemap.min_log_luminance;
+        let luminance = exp2(log_luminance);
+
+        adapted_luminance = mix(adapted_luminance, luminance, tonemap.adaptation);
+    }
+}
@@ -0,0 +1,42 @@
+// animated noise added in a roughly perceptual space so the shadows don't drown in it
+
+struct FilmGrainUniform {
+    intensity: f32,
+    size: f32,      // grain size in pixels
+    time: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> grain: FilmGrainUniform;
+
+// pcg, one random value per grain cell and frame
+fn hash(v: vec3<u32>) -> f32 {
+    var state = v.x * 747796405u + v.y * 2891336453u + v.z * 277803737u;
+    state = state * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return f32((word >> 22u) ^ word) / 4294967295.0;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(source, source_sampler, in.uv, 0.0).rgb;
+
+    let cell = vec2<u32>(in.clip_position.xy / grain.size);
+    let frame = u32(grain.time * 24.0);
+    let noise = hash(vec3<u32>(cell, frame)) - 0.5;
+
+    let encoded = sqrt(max(color, vec3<f32>(0.0)));
+    let grained = max(encoded + noise * grain.intensity, vec3<f32>(0.0));
+    return vec4<f32>(grained * grained, 1.0);
+}
@@ -0,0 +1,136 @@
+// fxaa after lottes' 3.11 quality preset. finds edges by local contrast in luma, walks along each edge to its ends
+// and blends across it by how far the pixel is from the nearer end
+
+struct FxaaUniform {
+    subpixel: f32,              // how much single pixel detail is smoothed, 0 to 1
+    edge_threshold: f32,        // contrast needed to count as an edge, relative to the brightest neighbor
+    edge_threshold_min: f32,    // and absolute, keeps dark noise from being treated as edges
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> fxaa: FxaaUniform;
+
+const SEARCH_STEPS: i32 = 10;
+
+// colors are linear, the square root is close enough to the gamma curve the thresholds were tuned for
+fn luma(uv: vec2<f32>) -> f32 {
+    let color = textureSampleLevel(source, source_sampler, uv, 0.0).rgb;
+    return sqrt(dot(color, vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+fn search_step(step: i32) -> f32 {
+    var steps = array<f32, 10>(1.0, 1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 4.0, 8.0);
+    return steps[step];
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+    let color = textureSampleLevel(source, source_sampler, in.uv, 0.0);
+
+    let center = luma(in.uv);
+    let north = luma(in.uv + vec2<f32>(0.0, -texel.y));
+    let south = luma(in.uv + vec2<f32>(0.0, texel.y));
+    let west = luma(in.uv + vec2<f32>(-texel.x, 0.0));
+    let east = luma(in.uv + vec2<f32>(texel.x, 0.0));
+
+    let luma_min = min(center, min(min(north, south), min(west, east)));
+    let luma_max = max(center, max(max(north, south), max(west, east)));
+    let range = luma_max - luma_min;
+    if range < max(fxaa.edge_threshold_min, luma_max * fxaa.edge_threshold) {
+        return color;
+    }
+
+    let north_west = luma(in.uv + vec2<f32>(-texel.x, -texel.y));
+    let north_east = luma(in.uv + vec2<f32>(texel.x, -texel.y));
+    let south_west = luma(in.uv + vec2<f32>(-texel.x, texel.y));
+    let south_east = luma(in.uv + vec2<f32>(texel.x, texel.y));
+
+    // a horizontal edge changes most from north to south
+    let horizontal = abs(north_west - 2.0 * west + south_west) + 2.0 * abs(north - 2.0 * center + south) + abs(north_east - 2.0 * east + south_east);
+    let vertical = abs(north_west - 2.0 * north + north_east) + 2.0 * abs(west - 2.0 * center + east) + abs(south_west - 2.0 * south + south_east);
+    let is_horizontal = horizontal >= vertical;
+
+    // which side of the pixel the edge is on
+    let negative = select(west, north, is_horizontal);
+    let positive = select(east, south, is_horizontal);
+    let gradient_negative = abs(negative - center);
+    let gradient_positive = abs(positive - center);
+    let negative_steeper = gradient_negative >= gradient_positive;
+
+    var step_length = select(texel.x, texel.y, is_horizontal);
+    var local_average = 0.5 * (positive + center);
+    if negative_steeper {
+        step_length = -step_length;
+        local_average = 0.5 * (negative + center);
+    }
+    let gradient_scaled = 0.25 * max(gradient_negative, gradient_positive);
+
+    // start half a pixel over, on the edge itself
+    var edge_uv = in.uv;
+    if is_horizontal {
+        edge_uv.y += step_length * 0.5;
+    } else {
+        edge_uv.x += step_length * 0.5;
+    }
+    let offset = select(vec2<f32>(0.0, texel.y), vec2<f32>(texel.x, 0.0), is_horizontal);
+
+    // walk both ways until the luma along the edge stops matching it
+    var uv_negative = edge_uv - offset;
+    var uv_positive = edge_uv + offset;
+    var end_negative = luma(uv_negative) - local_average;
+    var end_positive = luma(uv_positive) - local_average;
+    var done_negative = abs(end_negative) >= gradient_scaled;
+    var done_positive = abs(end_positive) >= gradient_scaled;
+
+    for (var i = 1; i < SEARCH_STEPS && !(done_negative && done_positive); i++) {
+        if !done_negative {
+            uv_negative -= offset * search_step(i);
+            end_negative = luma(uv_negative) - local_average;
+            done_negative = abs(end_negative) >= gradient_scaled;
+        }
+        if !done_positive {
+            uv_positive += offset * search_step(i);
+            end_positive = luma(uv_positive) - local_average;
+            done_positive = abs(end_positive) >= gradient_scaled;
+        }
+    }
+
+    let distance_negative = select(in.uv.y - uv_negative.y, in.uv.x - uv_negative.x, is_horizontal);
+    let distance_positive = select(uv_positive.y - in.uv.y, uv_positive.x - in.uv.x, is_horizontal);
+    let nearer_negative = distance_negative < distance_positive;
+    let nearest = min(distance_negative, distance_positive);
+    let edge_length = distance_negative + distance_positive;
+
+    // only blend when the pixel is on the side of the edge its nearer end bends toward
+    let center_smaller = center < local_average;
+    let end = select(end_positive, end_negative, nearer_negative);
+    let correct_variation = (end < 0.0) != center_smaller;
+    let edge_offset = select(0.0, -nearest / edge_length + 0.5, correct_variation);
+
+    // single pixel features get smoothed by how much they stand out from the 3x3 average
+    let average = (2.0 * (north + south + west + east) + north_west + north_east + south_west + south_east) / 12.0;
+    let subpixel_contrast = clamp(abs(average - center) / range, 0.0, 1.0);
+    let subpixel_blend = smoothstep(0.0, 1.0, subpixel_contrast);
+    let subpixel_offset = subpixel_blend * subpixel_blend * fxaa.subpixel;
+
+    let blend = max(edge_offset, subpixel_offset);
+    var final_uv = in.uv;
+    if is_horizontal {
+        final_uv.y += blend * step_length;
+    } else {
+        final_uv.x += blend * step_length;
+    }
+    return vec4<f32>(textureSampleLevel(source, source_sampler, final_uv, 0.0).rgb, 1.0);
+}
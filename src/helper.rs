@@ -1,44 +1,55 @@
 use std::sync::Arc;
 use winit::window::Window;
 use wgpu::*;
-use crate::{instance::InstanceRaw, shader_structs::Vertex, texture};
+use crate::{color, instance::InstanceRaw, shader_structs::Vertex, texture};
 
+// requested whenever the adapter has them, textures in formats the device lacks are decoded on the cpu
+const TEXTURE_COMPRESSION_FEATURES: Features = Features::TEXTURE_COMPRESSION_BC
+    .union(Features::TEXTURE_COMPRESSION_ETC2)
+    .union(Features::TEXTURE_COMPRESSION_ASTC);
+
+// lets msaa use the sample counts the adapter has beyond the ones webgpu guarantees, and the wireframe debug view
+// draw real lines
+const OPTIONAL_FEATURES: Features = TEXTURE_COMPRESSION_FEATURES
+    .union(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+    .union(Features::POLYGON_MODE_LINE);
 
 pub fn with_default_render_pass<F>(
     encoder: &mut wgpu::CommandEncoder,
-    view: &wgpu::TextureView,
+    (view, resolve_target): (&wgpu::TextureView, Option<&wgpu::TextureView>),  // a multisampled view is resolved into the second one
+    clear_color: Color,
+    aux_views: &[(&wgpu::TextureView, Option<&wgpu::TextureView>)],  // color targets after the first, cleared to zero
     depth_stencil_attachment: Option<&texture::Texture>,
+    depth_clear_value: f32,
     draw_fn: F,
 ) 
 where
     F: FnOnce(&mut wgpu::RenderPass),
 {
+    let color_attachments = std::iter::once(((view, resolve_target), clear_color))
+        .chain(aux_views.iter().map(|&attachment| (attachment, Color::TRANSPARENT)))
+        .map(|((view, resolve_target), clear)| Some(
+            RenderPassColorAttachment { 
+                view, 
+                resolve_target, 
+                ops: Operations { 
+                    load: LoadOp::Clear(clear), 
+                    store: if resolve_target.is_some() { StoreOp::Discard } else { StoreOp::Store }
+                },
+                depth_slice: None, 
+            }
+        ))
+        .collect::<Vec<_>>();
+
     let mut render_pass = encoder.begin_render_pass(
         &RenderPassDescriptor { 
             label: Some("Render Pass"), 
-            color_attachments: &[Some(
-                RenderPassColorAttachment { 
-                    view: &view, 
-                    resolve_target: None, 
-                    ops: Operations { 
-                        load: LoadOp::Clear(
-                            Color { 
-                                r: 0.0, 
-                                g: 0.0, 
-                                b: 0.0, 
-                                a: 1.0 
-                            }
-                        ), 
-                        store: StoreOp::Store
-                    },
-                    depth_slice: None, 
-                }
-            )], 
+            color_attachments: &color_attachments, 
             depth_stencil_attachment: depth_stencil_attachment.map(|d| {
                 RenderPassDepthStencilAttachment { 
                     view: &d.view, 
                     depth_ops: Some(Operations { 
-                        load: LoadOp::Clear(1.0), 
+                        load: LoadOp::Clear(depth_clear_value), 
                         store: StoreOp::Store 
                     }), 
                     stencil_ops: None
@@ -52,11 +63,17 @@ where
     draw_fn(&mut render_pass);
 }
 
-//helper fn for render pipeline descriptors
-pub fn make_pipeline_desc_from_shader(device: &Device, layout: &PipelineLayout, shader: &ShaderModule, fmt: TextureFormat) -> RenderPipeline {
+//helper fn for render pipeline descriptors, fs_main writes `fmts[i]` to location i and only the first is blended
+pub fn make_pipeline_desc_from_shader(device: &Device, layout: &PipelineLayout, shader: &ShaderModule, fmts: &[TextureFormat], depth_compare: CompareFunction, sample_count: u32) -> RenderPipeline {
     let vertex_buffer_layout = Vertex::desc();
     let instance_buffer_layout = InstanceRaw::desc();
 
+    let targets = fmts.iter().enumerate().map(|(i, &format)| Some(ColorTargetState { 
+        format, 
+        blend: (i == 0).then_some(BlendState::REPLACE), 
+        write_mask: ColorWrites::ALL 
+    })).collect::<Vec<_>>();
+
     device.create_render_pipeline(
         &RenderPipelineDescriptor { 
             label: Some("Render Pipeline"), 
@@ -71,11 +88,7 @@ pub fn make_pipeline_desc_from_shader(device: &Device, layout: &PipelineLayout,
                 module: shader,
                 entry_point: Some("fs_main"),
                 compilation_options: PipelineCompilationOptions::default(), 
-                targets: &[Some(ColorTargetState { 
-                    format: fmt, 
-                    blend: Some(BlendState::REPLACE), 
-                    write_mask: ColorWrites::ALL 
-                })]
+                targets: &targets
             }),
             primitive: PrimitiveState { 
                 topology: PrimitiveTopology::TriangleList, 
@@ -89,14 +102,14 @@ pub fn make_pipeline_desc_from_shader(device: &Device, layout: &PipelineLayout,
             depth_stencil: Some(DepthStencilState { 
                 format: texture::Texture::DEPTH_FORMAT, 
                 depth_write_enabled: true, 
-                depth_compare: CompareFunction::Less, 
+                depth_compare, 
                 stencil: StencilState::default(), 
                 bias: DepthBiasState::default() 
             }),
             multiview: None, 
             cache: None,
             multisample: MultisampleState { 
-                count: 1, 
+                count: sample_count, 
                 mask: !0, 
                 alpha_to_coverage_enabled: false 
             }, 
@@ -104,7 +117,7 @@ pub fn make_pipeline_desc_from_shader(device: &Device, layout: &PipelineLayout,
     )
 }
 
-pub async fn configure_surface(window: Arc<Window>) -> anyhow::Result<(Surface<'static>, SurfaceConfiguration, Device, Queue)> {
+pub async fn configure_surface(window: Arc<Window>) -> anyhow::Result<(Surface<'static>, SurfaceConfiguration, Adapter, Device, Queue)> {
     let size = window.inner_size();
 
     let instance_descriptor = 
@@ -133,12 +146,12 @@ pub async fn configure_surface(window: Arc<Window>) -> anyhow::Result<(Surface<'
     )
     .await?;
 
-    println!("GPU: {}", adapter.get_info().name);
+    log::info!("GPU: {}", adapter.get_info().name);
 
     let (device, queue) = adapter.request_device(
         &DeviceDescriptor { 
             label: None, 
-            required_features: Features::empty(), 
+            required_features: adapter.features() & OPTIONAL_FEATURES, 
             required_limits: 
                 if cfg!(target_arch = "wasm32") {
                     Limits::downlevel_defaults()
@@ -154,10 +167,7 @@ pub async fn configure_surface(window: Arc<Window>) -> anyhow::Result<(Surface<'
 
     let surface_caps = surface.get_capabilities(&adapter);
 
-    let surface_fmt = surface_caps.formats.iter()
-        .find(|f| f.is_srgb())
-        .copied()
-        .unwrap_or(surface_caps.formats[0]);
+    let (surface_fmt, view_formats) = color::surface_formats(&surface_caps, &adapter.get_downlevel_capabilities());
 
     let config = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -175,9 +185,52 @@ pub async fn configure_surface(window: Arc<Window>) -> anyhow::Result<(Surface<'
 
         present_mode: surface_caps.present_modes[0],
         alpha_mode: surface_caps.alpha_modes[0],
-        view_formats: vec![],
+        view_formats,
         desired_maximum_frame_latency: 2,
     };
 
-    Ok((surface, config, device, queue))
-}
\ No newline at end of file
+    Ok((surface, config, adapter, device, queue))
+}
+
+
+
+// no window or surface, so any adapter will do including a software one (llvmpipe, warp, swiftshader)
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn create_headless_device() -> anyhow::Result<(Adapter, Device, Queue)> {
+    let instance = Instance::new(
+        &InstanceDescriptor {
+            backends: Backends::all(),
+            ..Default::default()
+        }
+    );
+
+    let mut adapter_options = RequestAdapterOptions { 
+        power_preference: PowerPreference::default(), 
+        force_fallback_adapter: false, 
+        compatible_surface: None
+    };
+
+    let adapter = match instance.request_adapter(&adapter_options).await {
+        Ok(adapter) => adapter,
+        Err(_) => {
+            adapter_options.force_fallback_adapter = true;
+            instance.request_adapter(&adapter_options).await?
+        }
+    };
+
+    log::info!("GPU: {} ({:?})", adapter.get_info().name, adapter.get_info().backend);
+
+    let (device, queue) = adapter.request_device(
+        &DeviceDescriptor { 
+            label: None, 
+            required_features: adapter.features() & OPTIONAL_FEATURES, 
+            required_limits: Limits::downlevel_defaults().using_resolution(adapter.limits()), 
+            memory_hints: Default::default(), 
+            trace: Trace::Off 
+        }
+    )
+    .await?;
+
+    Ok((adapter, device, queue))
+}
+
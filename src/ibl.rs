@@ -0,0 +1,453 @@
+use anyhow::Result;
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, *};
+
+use crate::{postprocess::FullscreenPass, texture::Texture};
+
+#[derive(Clone, Copy, Debug)]
+pub struct EnvironmentSettings {
+    pub cube_size: u32,
+    pub irradiance_size: u32,
+    pub prefiltered_size: u32,
+    pub prefiltered_mips: u32,     // roughness 0 to 1 spread evenly over the mips
+    pub prefilter_samples: u32,
+    pub brdf_lut_size: u32,
+    pub intensity: f32
+}
+
+impl Default for EnvironmentSettings {
+    fn default() -> Self {
+        Self {
+            cube_size: 512,
+            irradiance_size: 32,
+            prefiltered_size: 128,
+            prefiltered_mips: 5,
+            prefilter_samples: 128,
+            brdf_lut_size: 256,
+            intensity: 1.0
+        }
+    }
+}
+
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct EnvironmentUniform {
+    intensity: f32,
+    max_lod: f32,   // mip of the prefiltered map holding roughness 1
+    _padding: [f32; 2]
+}
+
+// matches BakeParams in ibl.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BakeParams {
+    face: u32,
+    roughness: f32,
+    sample_count: u32,
+    source_size: f32
+}
+
+
+// everything image based lighting samples, baked once from an equirectangular panorama
+pub struct Environment {
+    #[allow(unused)]
+    pub settings: EnvironmentSettings,
+    pub cubemap: Texture,       // the environment itself with a full mip chain
+    pub irradiance: Texture,
+    pub prefiltered: Texture,
+    pub brdf_lut: Texture,
+    pub uniform_buffer: Buffer
+}
+
+
+impl Environment {
+    pub const FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+    const BRDF_LUT_FORMAT: TextureFormat = TextureFormat::Rg16Float;
+
+    // Radiance .hdr or OpenEXR, told apart by their magic bytes
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_file(device: &Device, queue: &Queue, path: impl AsRef<std::path::Path>, settings: EnvironmentSettings) -> Result<Self> {
+        use anyhow::Context;
+
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        let image = image::load_from_memory(&bytes).with_context(|| format!("decoding environment map {}", path.display()))?.to_rgba32f();
+        Self::from_equirect(device, queue, &image, settings)
+    }
+
+    // a plain gradient sky over dark ground, for when no environment map is given
+    pub fn procedural_sky(device: &Device, queue: &Queue, settings: EnvironmentSettings) -> Result<Self> {
+        const ZENITH: [f32; 3] = [0.08, 0.14, 0.30];
+        const HORIZON: [f32; 3] = [0.35, 0.38, 0.42];
+        const GROUND: [f32; 3] = [0.04, 0.035, 0.03];
+
+        let image = image::Rgba32FImage::from_fn(256, 128, |_, y| {
+            let up = 1.0 - 2.0 * (y as f32 + 0.5) / 128.0;
+            let color = if up >= 0.0 {
+                let t = up.sqrt();
+                [0, 1, 2].map(|i| HORIZON[i] + (ZENITH[i] - HORIZON[i]) * t)
+            } else {
+                let t = (-up * 8.0).min(1.0);
+                [0, 1, 2].map(|i| HORIZON[i] * 0.5 + (GROUND[i] - HORIZON[i] * 0.5) * t)
+            };
+            image::Rgba([color[0], color[1], color[2], 1.0])
+        });
+
+        Self::from_equirect(device, queue, &image, settings)
+    }
+
+    pub fn from_equirect(device: &Device, queue: &Queue, image: &image::Rgba32FImage, settings: EnvironmentSettings) -> Result<Self> {
+        let equirect = upload_equirect(device, queue, image);
+        let shaders = BakeShaders::new(device);
+        let sampler = bake_sampler(device);
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Environment Bake Encoder")
+        });
+
+        let cube_size = settings.cube_size.max(1);
+        let equirect_view = equirect.create_view(&TextureViewDescriptor::default());
+        let cubemap = bake_cubemap(device, &mut encoder, &shaders, &sampler, &equirect_view, cube_size, "Environment Cubemap");
+
+        let irradiance = Texture::create_cubemap(device, settings.irradiance_size.max(1), 1, Self::FORMAT, "Irradiance Map");
+        let pipeline = bake_pipeline(device, &shaders, "fs_irradiance", Self::FORMAT);
+        for face in 0..6 {
+            let params = BakeParams { face, roughness: 0.0, sample_count: 0, source_size: cube_size as f32 };
+            let bind_group = bake_bind_group(device, &pipeline, 0, &cubemap.view, &sampler, params);
+            bake(&mut encoder, &pipeline, Some(&bind_group), &face_view(&irradiance, face, 0));
+        }
+
+        let prefiltered_size = settings.prefiltered_size.max(1);
+        let prefiltered_mips = settings.prefiltered_mips.clamp(1, prefiltered_size.ilog2() + 1);
+        let prefiltered = Texture::create_cubemap(device, prefiltered_size, prefiltered_mips, Self::FORMAT, "Prefiltered Environment");
+        let pipeline = bake_pipeline(device, &shaders, "fs_prefilter", Self::FORMAT);
+        for mip in 0..prefiltered_mips {
+            let roughness = if prefiltered_mips > 1 { mip as f32 / (prefiltered_mips - 1) as f32 } else { 0.0 };
+            for face in 0..6 {
+                let params = BakeParams { face, roughness, sample_count: settings.prefilter_samples.max(1), source_size: cube_size as f32 };
+                let bind_group = bake_bind_group(device, &pipeline, 0, &cubemap.view, &sampler, params);
+                bake(&mut encoder, &pipeline, Some(&bind_group), &face_view(&prefiltered, face, mip));
+            }
+        }
+
+        let lut_size = settings.brdf_lut_size.max(1);
+        let brdf_lut = Texture::create_render_target(device, lut_size, lut_size, Self::BRDF_LUT_FORMAT, "BRDF LUT");
+        let pipeline = bake_pipeline(device, &shaders, "fs_brdf_lut", Self::BRDF_LUT_FORMAT);
+        bake(&mut encoder, &pipeline, None, &brdf_lut.view);
+
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Environment Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[EnvironmentUniform {
+                intensity: settings.intensity,
+                max_lod: (prefiltered_mips - 1) as f32,
+                _padding: [0.0; 2]
+            }]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+        });
+
+        Ok(Self {
+            settings,
+            cubemap,
+            irradiance,
+            prefiltered,
+            brdf_lut,
+            uniform_buffer
+        })
+    }
+
+    pub fn bind_group_layout(device: &Device) -> BindGroupLayout {
+        let cube = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::Cube,
+                multisampled: false
+            },
+            count: None
+        };
+
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Environment Bind Group Layout"),
+            entries: &[
+                cube(0),
+                cube(1),
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false
+                    },
+                    count: None
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None
+                    },
+                    count: None
+                }
+            ]
+        })
+    }
+
+    pub fn bind_group(&self, device: &Device, layout: &BindGroupLayout) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Environment Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&self.irradiance.view)
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&self.prefiltered.view)
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&self.brdf_lut.view)
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::Sampler(&self.prefiltered.sampler)
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: self.uniform_buffer.as_entire_binding()
+                }
+            ]
+        })
+    }
+}
+
+
+// a panorama onto a cube with a full mip chain, the same bake the environment starts from. --skybox panoramas load
+// through it too
+#[cfg(not(target_arch = "wasm32"))]
+pub fn cubemap_from_equirect(device: &Device, queue: &Queue, image: &image::Rgba32FImage, size: u32, label: &str) -> Texture {
+    let equirect = upload_equirect(device, queue, image);
+    let shaders = BakeShaders::new(device);
+    let sampler = bake_sampler(device);
+
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Cubemap Bake Encoder")
+    });
+    let cubemap = bake_cubemap(device, &mut encoder, &shaders, &sampler, &equirect.create_view(&TextureViewDescriptor::default()), size.max(1), label);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    cubemap
+}
+
+fn bake_cubemap(device: &Device, encoder: &mut CommandEncoder, shaders: &BakeShaders, sampler: &Sampler, equirect: &TextureView, size: u32, label: &str) -> Texture {
+    let mips = size.ilog2() + 1;
+    let cubemap = Texture::create_cubemap(device, size, mips, Environment::FORMAT, label);
+
+    let pipeline = bake_pipeline(device, shaders, "fs_equirect_to_cube", Environment::FORMAT);
+    for face in 0..6 {
+        let params = BakeParams { face, roughness: 0.0, sample_count: 0, source_size: 0.0 };
+        let bind_group = bake_bind_group(device, &pipeline, 3, equirect, sampler, params);
+        bake(encoder, &pipeline, Some(&bind_group), &face_view(&cubemap, face, 0));
+    }
+
+    // each mip drawn from the one above it
+    let pipeline = bake_pipeline(device, shaders, "fs_downsample", Environment::FORMAT);
+    for mip in 1..mips {
+        let source = cubemap.texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::Cube),
+            base_mip_level: mip - 1,
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+        for face in 0..6 {
+            let params = BakeParams { face, roughness: 0.0, sample_count: 0, source_size: 0.0 };
+            let bind_group = bake_bind_group(device, &pipeline, 0, &source, sampler, params);
+            bake(encoder, &pipeline, Some(&bind_group), &face_view(&cubemap, face, mip));
+        }
+    }
+
+    cubemap
+}
+
+fn bake_sampler(device: &Device) -> Sampler {
+    device.create_sampler(&SamplerDescriptor {
+        label: Some("Environment Bake Sampler"),
+        address_mode_u: AddressMode::Repeat,
+        address_mode_v: AddressMode::ClampToEdge,
+        address_mode_w: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        mipmap_filter: FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+// stored as half floats so it can be filtered without the FLOAT32_FILTERABLE feature
+fn upload_equirect(device: &Device, queue: &Queue, image: &image::Rgba32FImage) -> wgpu::Texture {
+    let max_size = device.limits().max_texture_dimension_2d;
+    let resized;
+    let image = if image.width() > max_size || image.height() > max_size {
+        let scale = max_size as f32 / image.width().max(image.height()) as f32;
+        let (width, height) = (((image.width() as f32 * scale) as u32).max(1), ((image.height() as f32 * scale) as u32).max(1));
+        resized = image::imageops::resize(image, width, height, image::imageops::FilterType::Triangle);
+        &resized
+    } else {
+        image
+    };
+
+    let size = Extent3d {
+        width: image.width(),
+        height: image.height(),
+        depth_or_array_layers: 1
+    };
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some("Environment Equirect"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: Environment::FORMAT,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[]
+    });
+
+    let texels = image.as_raw().iter().map(|&c| half::f16::from_f32(c).to_bits()).collect::<Vec<u16>>();
+    queue.write_texture(
+        TexelCopyTextureInfo {
+            texture: &texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All
+        },
+        bytemuck::cast_slice(&texels),
+        TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(8 * image.width()),
+            rows_per_image: Some(image.height())
+        },
+        size
+    );
+
+    texture
+}
+
+fn face_view(cubemap: &Texture, face: u32, mip: u32) -> TextureView {
+    cubemap.texture.create_view(&TextureViewDescriptor {
+        label: Some("Cubemap Face"),
+        dimension: Some(TextureViewDimension::D2),
+        base_mip_level: mip,
+        mip_level_count: Some(1),
+        base_array_layer: face,
+        array_layer_count: Some(1),
+        ..Default::default()
+    })
+}
+
+// the fragment entry points in ibl.wgsl, drawn over fullscreen.wgsl's triangle
+struct BakeShaders {
+    vertex: ShaderModule,
+    fragment: ShaderModule
+}
+
+impl BakeShaders {
+    fn new(device: &Device) -> Self {
+        Self {
+            vertex: FullscreenPass::vertex_shader(device),
+            fragment: device.create_shader_module(include_wgsl!("ibl.wgsl"))
+        }
+    }
+}
+
+// layouts come from the entry point, so each only asks for the bindings it uses
+fn bake_pipeline(device: &Device, shaders: &BakeShaders, fragment_entry: &str, format: TextureFormat) -> RenderPipeline {
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some(fragment_entry),
+        layout: None,
+        vertex: VertexState {
+            module: &shaders.vertex,
+            entry_point: Some("vs_main"),
+            compilation_options: PipelineCompilationOptions::default(),
+            buffers: &[]
+        },
+        fragment: Some(FragmentState {
+            module: &shaders.fragment,
+            entry_point: Some(fragment_entry),
+            compilation_options: PipelineCompilationOptions::default(),
+            targets: &[Some(ColorTargetState {
+                format,
+                blend: None,
+                write_mask: ColorWrites::ALL
+            })]
+        }),
+        primitive: PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        multiview: None,
+        cache: None
+    })
+}
+
+// the equirect pass reads its source at binding 3, the cube passes at binding 0
+fn bake_bind_group(device: &Device, pipeline: &RenderPipeline, source_binding: u32, source: &TextureView, sampler: &Sampler, params: BakeParams) -> BindGroup {
+    let params_buffer = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Bake Params"),
+        contents: bytemuck::cast_slice(&[params]),
+        usage: BufferUsages::UNIFORM
+    });
+
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Bake Bind Group"),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            BindGroupEntry {
+                binding: source_binding,
+                resource: BindingResource::TextureView(source)
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(sampler)
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: params_buffer.as_entire_binding()
+            }
+        ]
+    })
+}
+
+fn bake(encoder: &mut CommandEncoder, pipeline: &RenderPipeline, bind_group: Option<&BindGroup>, target: &TextureView) {
+    let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+        label: Some("Environment Bake Pass"),
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: Operations {
+                load: LoadOp::Clear(Color::BLACK),
+                store: StoreOp::Store
+            },
+            depth_slice: None
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None
+    });
+
+    render_pass.set_pipeline(pipeline);
+    if let Some(bind_group) = bind_group {
+        render_pass.set_bind_group(0, bind_group, &[]);
+    }
+    render_pass.draw(0..3, 0..1);
+}
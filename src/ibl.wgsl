@@ -0,0 +1,172 @@
+// one shot passes that turn an equirectangular environment into the cubemaps and lut used for image based lighting,
+// each draws fullscreen.wgsl's triangle into one face (and mip) of the target
+
+const PI = 3.14159265359;
+
+struct BakeParams {
+    face: u32,
+    roughness: f32,
+    sample_count: u32,
+    source_size: f32,   // face size of the source cubemap's base level
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,     // 0,0 is the top left of the target
+}
+
+@group(0) @binding(0)
+var source: texture_cube<f32>;
+
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+@group(0) @binding(2)
+var<uniform> params: BakeParams;
+
+@group(0) @binding(3)
+var equirect: texture_2d<f32>;
+
+// world direction through a texel of a cube face, following the usual +x -x +y -y +z -z layer order
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let u = uv.x * 2.0 - 1.0;
+    let v = uv.y * 2.0 - 1.0;
+
+    switch face {
+        case 0u: { return normalize(vec3<f32>(1.0, -v, -u)); }
+        case 1u: { return normalize(vec3<f32>(-1.0, -v, u)); }
+        case 2u: { return normalize(vec3<f32>(u, 1.0, v)); }
+        case 3u: { return normalize(vec3<f32>(u, -1.0, -v)); }
+        case 4u: { return normalize(vec3<f32>(u, -v, 1.0)); }
+        default: { return normalize(vec3<f32>(-u, -v, -1.0)); }
+    }
+}
+
+fn tangent_frame(N: vec3<f32>) -> mat3x3<f32> {
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(N.y) > 0.999 {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let right = normalize(cross(up, N));
+    return mat3x3<f32>(right, cross(N, right), N);
+}
+
+@fragment
+fn fs_equirect_to_cube(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = face_direction(params.face, in.uv);
+    let uv = vec2<f32>(atan2(dir.z, dir.x) / (2.0 * PI) + 0.5, acos(clamp(dir.y, -1.0, 1.0)) / PI);
+    return vec4<f32>(textureSampleLevel(equirect, source_sampler, uv, 0.0).rgb, 1.0);
+}
+
+// the source view is the previous mip alone, bilinear filtering averages the four texels under each output texel
+@fragment
+fn fs_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = face_direction(params.face, in.uv);
+    return vec4<f32>(textureSampleLevel(source, source_sampler, dir, 0.0).rgb, 1.0);
+}
+
+// cosine weighted hemisphere integral, read from a small mip so the coarse grid of samples doesn't alias
+@fragment
+fn fs_irradiance(in: VertexOutput) -> @location(0) vec4<f32> {
+    let N = face_direction(params.face, in.uv);
+    let frame = tangent_frame(N);
+    let lod = max(log2(params.source_size / 32.0), 0.0);
+
+    const STEP = 0.05;
+    var irradiance = vec3<f32>(0.0);
+    var count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi += STEP) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += STEP) {
+            let tangent_dir = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            irradiance += textureSampleLevel(source, source_sampler, frame * tangent_dir, lod).rgb * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+
+    return vec4<f32>(PI * irradiance / count, 1.0);
+}
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// half vector around N distributed like the ggx lobe, roughness squared is alpha as in shader.wgsl
+fn importance_sample_ggx(xi: vec2<f32>, N: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return normalize(tangent_frame(N) * vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta));
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// split sum prefiltering with N = V = R, samples read a mip matching their solid angle to keep bright spots from sparkling
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    let N = face_direction(params.face, in.uv);
+    let texel_solid_angle = 4.0 * PI / (6.0 * params.source_size * params.source_size);
+
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < params.sample_count; i++) {
+        let H = importance_sample_ggx(hammersley(i, params.sample_count), N, params.roughness);
+        let L = normalize(2.0 * dot(N, H) * H - N);
+
+        let n_dot_l = dot(N, L);
+        if n_dot_l > 0.0 {
+            let n_dot_h = max(dot(N, H), 0.0);
+            let pdf = distribution_ggx(n_dot_h, params.roughness) / 4.0 + 1e-4;
+            let sample_solid_angle = 1.0 / (f32(params.sample_count) * pdf + 1e-4);
+            let lod = select(0.5 * log2(sample_solid_angle / texel_solid_angle), 0.0, params.roughness == 0.0);
+
+            color += textureSampleLevel(source, source_sampler, L, max(lod, 0.0)).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+
+    return vec4<f32>(color / max(weight, 1e-4), 1.0);
+}
+
+fn geometry_schlick_ggx_ibl(n_dot: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    return n_dot / (n_dot * (1.0 - k) + k);
+}
+
+// scale and bias applied to f0 in the split sum, x is n_dot_v and y roughness
+@fragment
+fn fs_brdf_lut(in: VertexOutput) -> @location(0) vec4<f32> {
+    const SAMPLE_COUNT = 512u;
+
+    let n_dot_v = max(in.uv.x, 1e-3);
+    let roughness = in.uv.y;
+    let V = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let N = vec3<f32>(0.0, 0.0, 1.0);
+
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        let H = importance_sample_ggx(hammersley(i, SAMPLE_COUNT), N, roughness);
+        let L = normalize(2.0 * dot(V, H) * H - V);
+
+        let n_dot_l = max(L.z, 0.0);
+        let n_dot_h = max(H.z, 0.0);
+        let v_dot_h = max(dot(V, H), 0.0);
+
+        if n_dot_l > 0.0 {
+            let G = geometry_schlick_ggx_ibl(n_dot_v, roughness) * geometry_schlick_ggx_ibl(n_dot_l, roughness);
+            let G_vis = G * v_dot_h / (n_dot_h * n_dot_v);
+            let Fc = pow(1.0 - v_dot_h, 5.0);
+
+            scale += (1.0 - Fc) * G_vis;
+            bias += Fc * G_vis;
+        }
+    }
+
+    return vec4<f32>(scale / f32(SAMPLE_COUNT), bias / f32(SAMPLE_COUNT), 0.0, 1.0);
+}
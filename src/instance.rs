@@ -3,31 +3,72 @@ use wgpu::{VertexAttribute, VertexBufferLayout, vertex_attr_array};
 
 pub struct Instance  {
     pub position: Vector3<f32>,
-    pub rotation: Quaternion<f32>
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct InstanceRaw {
-    model: [[f32; 4]; 4]
+    model: [[f32; 4]; 4],
+    normal: [[f32; 3]; 3]
+}
+
+
+impl Default for Instance {
+    fn default() -> Self {
+        Self {
+            position: Vector3::zeros(),
+            rotation: Quaternion::identity(),
+            scale: Vector3::repeat(1.0)
+        }
+    }
 }
 
 
 impl Instance {
+    pub fn to_matrix(&self) -> Matrix4<f32> {
+        Matrix4::new_translation(&self.position) * UnitQuaternion::from_quaternion(self.rotation).to_rotation_matrix().to_homogeneous() * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+
     pub fn to_raw(&self) -> InstanceRaw {
+        let model = self.to_matrix();
+
+        // inverse transpose keeps normals perpendicular under non uniform scale
+        let linear = model.fixed_view::<3, 3>(0, 0).into_owned();
+        let normal = linear.try_inverse().map(|m| m.transpose()).unwrap_or(linear);
+
         InstanceRaw {
-            model: (Matrix4::new_translation(&self.position) * UnitQuaternion::from_quaternion(self.rotation).to_rotation_matrix().to_homogeneous()).into(), 
+            model: model.into(), 
+            normal: normal.into()
+        }
+    }
+
+    // splits an affine transform back into translation, rotation and scale, dropping any shear
+    pub fn from_matrix(m: &Matrix4<f32>) -> Self {
+        let position = m.fixed_view::<3, 1>(0, 3).into_owned();
+        let linear = m.fixed_view::<3, 3>(0, 0).into_owned();
+        let scale = Vector3::new(linear.column(0).norm(), linear.column(1).norm(), linear.column(2).norm());
+        let rotation = Rotation3::from_matrix(&(linear * Matrix3::from_diagonal(&scale.map(|s| if s > 0.0 { 1.0 / s } else { 0.0 }))));
+
+        Self {
+            position,
+            rotation: *UnitQuaternion::from_rotation_matrix(&rotation).quaternion(),
+            scale
         }
     }
 }
 
 
 impl InstanceRaw {
-    const ATTRIBS : [VertexAttribute; 4] = vertex_attr_array![
+    const ATTRIBS : [VertexAttribute; 7] = vertex_attr_array![
         5 => Float32x4,
         6 => Float32x4,
         7 => Float32x4,
         8 => Float32x4,
+        9 => Float32x3,
+        10 => Float32x3,
+        11 => Float32x3,
     ];
 
 
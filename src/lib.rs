@@ -2,13 +2,40 @@ mod window;
 mod render;
 mod shader_structs;
 mod texture;
+mod astc;
+mod basis;
+mod block_decode;
+mod bloom;
 mod camera;
+mod capture;
+mod clock;
+mod color;
+mod container;
+mod debug_view;
 mod helper;
+mod ibl;
 mod instance;
+mod light;
+mod material;
+mod mipmap;
+mod model;
+mod msaa;
+mod options;
+mod postprocess;
+mod probes;
+mod scene;
+mod shadow;
+mod skybox;
+mod taa;
+mod tonemap;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::wasm_bindgen;
 
+// what main.rs runs with --headless, natively the library can render offscreen the same way
+#[cfg(not(target_arch = "wasm32"))]
+pub use {options::Options, render::run_headless};
+
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen(start)]
 pub fn run_web() -> Result<(), wasm_bindgen::JsValue> {
@@ -17,7 +44,7 @@ pub fn run_web() -> Result<(), wasm_bindgen::JsValue> {
 
 
     console_error_panic_hook::set_once();
-    run().unwrap_throw();
+    run(options::Options::from_args().unwrap_throw()).unwrap_throw();
 
     Ok(())
 }
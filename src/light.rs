@@ -0,0 +1,117 @@
+use bytemuck::Zeroable;
+use nalgebra::*;
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, *};
+
+// must match the array size in shader.wgsl, a uniform array keeps this working on WebGL
+pub const MAX_LIGHTS: usize = 16;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum LightKind {
+    Directional {
+        direction: Vector3<f32>
+    },
+    Point {
+        position: Point3<f32>,
+        range: f32
+    },
+    // cone angles are half angles in radians, the falloff runs from inner to outer
+    Spot {
+        position: Point3<f32>,
+        direction: Vector3<f32>,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+    pub cast_shadows: bool  // only directional and spot lights get a shadow map
+}
+
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightRaw {
+    position: [f32; 3],
+    kind: u32,
+    direction: [f32; 3],
+    range: f32,
+    color: [f32; 3],
+    intensity: f32,
+    inner_cos: f32,
+    outer_cos: f32,
+    shadow_index: i32,  // layer in the shadow map array, -1 when unshadowed
+    _padding: f32
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightsUniform {
+    lights: [LightRaw; MAX_LIGHTS],
+    count: u32,
+    _padding: [u32; 3]
+}
+
+
+impl Light {
+    const DIRECTIONAL: u32 = 0;
+    const POINT: u32 = 1;
+    const SPOT: u32 = 2;
+
+    pub fn to_raw(&self, shadow_index: i32) -> LightRaw {
+        let (kind, position, direction, range, inner_cos, outer_cos) = match self.kind {
+            LightKind::Directional { direction } => (Self::DIRECTIONAL, Point3::origin(), direction, 0.0, 0.0, 0.0),
+            LightKind::Point { position, range } => (Self::POINT, position, Vector3::zeros(), range, 0.0, 0.0),
+            LightKind::Spot { position, direction, range, inner_angle, outer_angle } => (Self::SPOT, position, direction, range, inner_angle.cos(), outer_angle.cos())
+        };
+
+        LightRaw {
+            position: position.into(),
+            kind,
+            direction: direction.try_normalize(1e-6).unwrap_or(-Vector3::y()).into(),
+            range,
+            color: self.color.into(),
+            intensity: self.intensity,
+            inner_cos,
+            outer_cos,
+            shadow_index,
+            _padding: 0.0
+        }
+    }
+}
+
+
+impl LightsUniform {
+    // `shadow_indices` lines up with `lights`, missing entries are unshadowed
+    pub fn from_lights(lights: &[Light], shadow_indices: &[i32]) -> Self {
+        if lights.len() > MAX_LIGHTS {
+            log::warn!("{} lights in the scene, only the first {} are used", lights.len(), MAX_LIGHTS);
+        }
+
+        let mut uniform = Self::zeroed();
+        for (i, (raw, light)) in uniform.lights.iter_mut().zip(lights).enumerate() {
+            *raw = light.to_raw(shadow_indices.get(i).copied().unwrap_or(-1));
+        }
+        uniform.count = lights.len().min(MAX_LIGHTS) as u32;
+        uniform
+    }
+
+    pub fn create_buffer(lights: &[Light], shadow_indices: &[i32], device: &Device) -> Buffer {
+        device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some("Light Uniform Buffer"),
+                contents: bytemuck::cast_slice(&[Self::from_lights(lights, shadow_indices)]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        )
+    }
+
+    pub fn write_buffer(lights: &[Light], shadow_indices: &[i32], queue: &Queue, buffer: &Buffer) {
+        queue.write_buffer(buffer, 0, bytemuck::cast_slice(&[Self::from_lights(lights, shadow_indices)]));
+    }
+}
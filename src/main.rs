@@ -1,11 +1,50 @@
+use std::process::ExitCode;
+
 mod window;
 mod render;
 mod shader_structs;
 mod texture;
+mod astc;
+mod basis;
+mod block_decode;
+mod bloom;
 mod camera;
+mod capture;
+mod clock;
+mod color;
+mod container;
+mod debug_view;
 mod helper;
+mod ibl;
 mod instance;
+mod light;
+mod material;
+mod mipmap;
+mod model;
+mod msaa;
+mod options;
+mod postprocess;
+mod probes;
+mod scene;
+mod shadow;
+mod skybox;
+mod taa;
+mod tonemap;
+
+fn main() -> ExitCode {
+    let result = options::Options::from_args().and_then(|options| {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(output) = &options.headless {
+            return render::run_headless(output, &options);
+        }
+        window::run(options)
+    });
 
-fn main() {
-    window::run().unwrap();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,176 @@
+use anyhow::Result;
+use nalgebra::{Vector3, Vector4};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, *};
+
+use crate::texture::Texture;
+
+// glTF style metallic-roughness factors, each one multiplies its texture
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialFactors {
+    pub base_color: Vector4<f32>,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    pub emissive: Vector3<f32>
+}
+
+impl Default for MaterialFactors {
+    fn default() -> Self {
+        Self {
+            base_color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            metallic: 0.0,
+            roughness: 0.5,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            emissive: Vector3::zeros()
+        }
+    }
+}
+
+// metallic in blue and roughness in green like glTF, empty slots get a 1x1 texture that leaves the factor as is
+#[derive(Default)]
+pub struct MaterialTextures {
+    pub base_color: Option<Texture>,
+    pub metallic_roughness: Option<Texture>,
+    pub normal: Option<Texture>,
+    pub occlusion: Option<Texture>,
+    pub emissive: Option<Texture>
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialUniform {
+    base_color: [f32; 4],
+    emissive: [f32; 3],
+    metallic: f32,
+    roughness: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    _padding: f32
+}
+
+#[allow(unused)]
+pub struct Material {
+    pub name: String,
+    pub factors: MaterialFactors,
+    pub base_color_texture: Texture,
+    pub metallic_roughness_texture: Texture,
+    pub normal_texture: Texture,
+    pub occlusion_texture: Texture,
+    pub emissive_texture: Texture,
+    pub factor_buffer: Buffer,
+    pub bind_group: BindGroup
+}
+
+
+impl Material {
+    const TEXTURE_SLOTS: u32 = 5;
+
+    pub fn new(device: &Device, queue: &Queue, layout: &BindGroupLayout, name: &str, textures: MaterialTextures, factors: MaterialFactors) -> Result<Self> {
+        let fallback = |texture: Option<Texture>, color: [u8; 4], srgb: bool, slot: &str| -> Result<Texture> {
+            match texture {
+                Some(texture) => Ok(texture),
+                None => Texture::from_color(device, queue, color, srgb, &format!("{name} {slot}"))
+            }
+        };
+
+        let base_color_texture = fallback(textures.base_color, [255, 255, 255, 255], true, "Base Color")?;
+        let metallic_roughness_texture = fallback(textures.metallic_roughness, [255, 255, 255, 255], false, "Metallic Roughness")?;
+        let normal_texture = fallback(textures.normal, [128, 128, 255, 255], false, "Normal")?;
+        let occlusion_texture = fallback(textures.occlusion, [255, 255, 255, 255], false, "Occlusion")?;
+        let emissive_texture = fallback(textures.emissive, [255, 255, 255, 255], true, "Emissive")?;
+
+        let uniform = MaterialUniform {
+            base_color: factors.base_color.into(),
+            emissive: factors.emissive.into(),
+            metallic: factors.metallic,
+            roughness: factors.roughness,
+            normal_scale: factors.normal_scale,
+            occlusion_strength: factors.occlusion_strength,
+            _padding: 0.0
+        };
+        let factor_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&format!("{name} Factors")),
+                contents: bytemuck::cast_slice(&[uniform]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+
+        let slots = [&base_color_texture, &metallic_roughness_texture, &normal_texture, &occlusion_texture, &emissive_texture];
+        let mut entries = slots.iter().enumerate().flat_map(|(i, texture)| [
+            BindGroupEntry {
+                binding: 2 * i as u32,
+                resource: BindingResource::TextureView(&texture.view)
+            },
+            BindGroupEntry {
+                binding: 2 * i as u32 + 1,
+                resource: BindingResource::Sampler(&texture.sampler)
+            }
+        ]).collect::<Vec<_>>();
+        entries.push(BindGroupEntry {
+            binding: 2 * Self::TEXTURE_SLOTS,
+            resource: factor_buffer.as_entire_binding()
+        });
+
+        let bind_group = device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(name),
+                layout,
+                entries: &entries
+            }
+        );
+
+        Ok(Self {
+            name: name.to_string(),
+            factors,
+            base_color_texture,
+            metallic_roughness_texture,
+            normal_texture,
+            occlusion_texture,
+            emissive_texture,
+            factor_buffer,
+            bind_group
+        })
+    }
+
+    // a texture and sampler pair per slot in the order of MaterialTextures, then the factors
+    pub fn bind_group_layout(device: &Device) -> BindGroupLayout {
+        let mut entries = (0..Self::TEXTURE_SLOTS).flat_map(|i| [
+            BindGroupLayoutEntry {
+                binding: 2 * i,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false
+                },
+                count: None
+            },
+            BindGroupLayoutEntry {
+                binding: 2 * i + 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None
+            }
+        ]).collect::<Vec<_>>();
+        entries.push(BindGroupLayoutEntry {
+            binding: 2 * Self::TEXTURE_SLOTS,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None
+            },
+            count: None
+        });
+
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some("Material Bind Group Layout"),
+                entries: &entries
+            }
+        )
+    }
+}
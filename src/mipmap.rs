@@ -0,0 +1,65 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use wgpu::*;
+
+use crate::postprocess::FullscreenPass;
+
+// a full chain down to 1x1
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    width.max(height).max(1).ilog2() + 1
+}
+
+// fills every mip after the first by blitting each level into the next, the texture needs RENDER_ATTACHMENT
+// and a renderable format. sRGB views filter in linear space, so color textures average correctly.
+// built once and shared by every texture load, a pass is made the first time a format shows up
+pub struct MipmapGenerator {
+    vertex_shader: ShaderModule,
+    shader: ShaderModule,
+    layout: BindGroupLayout,
+    sampler: Sampler,
+    passes: Mutex<HashMap<TextureFormat, FullscreenPass>>
+}
+
+
+impl MipmapGenerator {
+    pub fn new(device: &Device, vertex_shader: &ShaderModule) -> Self {
+        Self {
+            vertex_shader: vertex_shader.clone(),
+            shader: device.create_shader_module(include_wgsl!("mipmap.wgsl")),
+            layout: FullscreenPass::input_layout(device),
+            sampler: FullscreenPass::create_sampler(device),
+            passes: Mutex::new(HashMap::new())
+        }
+    }
+
+    pub fn generate(&self, device: &Device, queue: &Queue, texture: &wgpu::Texture) {
+        if texture.mip_level_count() < 2 {
+            return;
+        }
+
+        let format = texture.format();
+        let mut passes = self.passes.lock().unwrap();
+        let pass = passes.entry(format).or_insert_with(|| {
+            FullscreenPass::new(device, "Mipmap Pipeline", &self.vertex_shader, (&self.shader, "fs_main"), &[&self.layout], format, None)
+        });
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Mipmap Encoder")
+        });
+
+        let level_view = |mip: u32| texture.create_view(&TextureViewDescriptor {
+            label: Some("Mip Level"),
+            dimension: Some(TextureViewDimension::D2),
+            base_mip_level: mip,
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+
+        for mip in 1..texture.mip_level_count() {
+            let bind_group = FullscreenPass::input_bind_group(device, &self.layout, &level_view(mip - 1), &self.sampler);
+            pass.draw(&mut encoder, &level_view(mip), &[&bind_group], 0.0);
+        }
+
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
@@ -0,0 +1,18 @@
+// draws one mip from the level above it, the source view holds only that level
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+// a bilinear tap halfway between four texels is their average, odd sizes lose a sliver of the last row and column
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(source, source_sampler, in.uv, 0.0);
+}
@@ -0,0 +1,314 @@
+use std::{io::{BufReader, Cursor}, ops::Range, rc::Rc};
+
+use anyhow::{Context, Result};
+use nalgebra::{Matrix4, Point3, Vector3, Vector4};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, *};
+
+use crate::{material::{Material, MaterialFactors, MaterialTextures}, mipmap::MipmapGenerator, shader_structs::Vertex, texture::{Texture, TextureSettings}};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>
+}
+
+pub struct Mesh {
+    pub name: String,
+    pub bounds: Aabb,
+    pub vertex_buffer: Buffer,
+    pub index_buffer: Buffer,
+    pub num_elements: u32,
+    pub material: usize,
+    pub wireframe_buffer: Option<Buffer>,   // every triangle's corners unshared, made by build_wireframe
+    wireframe_source: Option<(Vec<[f32; 3]>, Vec<u32>)>     // positions and indices, only without native line drawing
+}
+
+pub struct Model {
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<Rc<Material>>
+}
+
+
+impl Aabb {
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a Point3<f32>>) -> Option<Self> {
+        points.into_iter().fold(None, |bounds: Option<Aabb>, p| {
+            Some(match bounds {
+                Some(b) => Aabb { min: b.min.inf(p), max: b.max.sup(p) },
+                None => Aabb { min: *p, max: *p }
+            })
+        })
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max)
+        }
+    }
+
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Point3::new(a.x, a.y, a.z), Point3::new(b.x, a.y, a.z), Point3::new(a.x, b.y, a.z), Point3::new(b.x, b.y, a.z),
+            Point3::new(a.x, a.y, b.z), Point3::new(b.x, a.y, b.z), Point3::new(a.x, b.y, b.z), Point3::new(b.x, b.y, b.z),
+        ]
+    }
+
+    // bounds of the transformed box, looser than the mesh but cheap
+    pub fn transformed(&self, m: &Matrix4<f32>) -> Aabb {
+        let corners = self.corners().map(|c| m.transform_point(&c));
+        Aabb::from_points(&corners).unwrap_or(*self)
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        nalgebra::center(&self.min, &self.max)
+    }
+
+    pub fn half_extents(&self) -> Vector3<f32> {
+        (self.max - self.min) / 2.0
+    }
+}
+
+
+impl Mesh {
+    pub fn new(device: &Device, name: &str, vertices: &[Vertex], indices: &[u32], material: usize) -> Self {
+        let vertex_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&format!("{name} Vertex Buffer")),
+                contents: bytemuck::cast_slice(vertices),
+                usage: BufferUsages::VERTEX
+            }
+        );
+
+        let index_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&format!("{name} Index Buffer")),
+                contents: bytemuck::cast_slice(indices),
+                usage: BufferUsages::INDEX
+            }
+        );
+
+        // kept for build_wireframe, most runs never select the wireframe view
+        let wireframe_source = (!device.features().contains(Features::POLYGON_MODE_LINE))
+            .then(|| (vertices.iter().map(|v| v.position).collect(), indices.to_vec()));
+
+        let positions = vertices.iter().map(|v| Point3::from(v.position)).collect::<Vec<_>>();
+        let bounds = Aabb::from_points(&positions).unwrap_or(Aabb { min: Point3::origin(), max: Point3::origin() });
+
+        Self {
+            name: name.to_string(),
+            bounds,
+            vertex_buffer,
+            index_buffer,
+            num_elements: indices.len() as u32,
+            material,
+            wireframe_buffer: None,
+            wireframe_source
+        }
+    }
+
+    // the barycentric wireframe tells the corners apart by vertex index, which shared vertices don't allow.
+    // does nothing once built or when native line drawing needs no buffer
+    pub fn build_wireframe(&mut self, device: &Device) {
+        if let Some((positions, indices)) = self.wireframe_source.take() {
+            let corners = indices.iter().map(|&i| positions[i as usize]).collect::<Vec<_>>();
+            self.wireframe_buffer = Some(device.create_buffer_init(
+                &BufferInitDescriptor {
+                    label: Some(&format!("{} Wireframe Buffer", self.name)),
+                    contents: bytemuck::cast_slice(&corners),
+                    usage: BufferUsages::VERTEX
+                }
+            ));
+        }
+    }
+}
+
+
+impl Model {
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.meshes.iter().map(|m| m.bounds).reduce(|a, b| a.union(&b))
+    }
+
+    // `load_file` resolves the files an OBJ refers to (mtllib, map_Kd, ...) by name, so the
+    // same code path works for files on disk and for assets baked in with include_bytes!
+    pub fn load_obj<F>(device: &Device, queue: &Queue, mipmaps: &MipmapGenerator, layout: &BindGroupLayout, texture_settings: &TextureSettings, obj_src: &str, load_file: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Result<Vec<u8>>
+    {
+        let (models, obj_materials) = tobj::load_obj_buf(
+            &mut BufReader::new(Cursor::new(obj_src)),
+            &tobj::LoadOptions {
+                triangulate: true,
+                single_index: true,
+                ..Default::default()
+            },
+            |p| {
+                let mtl_src = load_file(&p.to_string_lossy()).map_err(|e| {
+                    log::error!("Unable to load material library {}: {}", p.display(), e);
+                    tobj::LoadError::OpenFileFailed
+                })?;
+                tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mtl_src)))
+            }
+        )?;
+
+        let obj_materials = obj_materials.unwrap_or_else(|e| {
+            log::warn!("Unable to load materials, falling back to white: {}", e);
+            Vec::new()
+        });
+
+        let mut materials = Vec::with_capacity(obj_materials.len() + 1);
+        for m in obj_materials {
+            let load_texture = |file: &Option<String>, srgb: bool| -> Result<Option<Texture>> {
+                let Some(file) = file else { return Ok(None) };
+                let bytes = load_file(file).with_context(|| format!("loading texture {file}"))?;
+                let texture = Texture::from_encoded(device, queue, mipmaps, &bytes, srgb, texture_settings, Some(file)).with_context(|| format!("decoding texture {file}"))?;
+                Ok(Some(texture))
+            };
+
+            let textures = MaterialTextures {
+                base_color: load_texture(&m.diffuse_texture, true)?,
+                normal: load_texture(&m.normal_texture, false)?,
+                emissive: load_texture(&m.unknown_param.get("map_Ke").cloned(), true)?,
+                ..Default::default()
+            };
+            materials.push(Rc::new(Material::new(device, queue, layout, &m.name, textures, obj_factors(&m))?));
+        }
+
+        // meshes without a usemtl get a plain white material appended at the end
+        let default_material = materials.len();
+        if models.iter().any(|m| m.mesh.material_id.is_none()) {
+            materials.push(Rc::new(Material::new(device, queue, layout, "Default Material", MaterialTextures::default(), MaterialFactors::default())?));
+        }
+
+        let meshes = models.iter().map(|m| {
+            let material = m.mesh.material_id.unwrap_or(default_material);
+            let vertices = obj_vertices(&m.mesh);
+            Mesh::new(device, &m.name, &vertices, &m.mesh.indices, material)
+        }).collect();
+
+        Ok(Self { meshes, materials })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_obj_file(device: &Device, queue: &Queue, mipmaps: &MipmapGenerator, layout: &BindGroupLayout, texture_settings: &TextureSettings, path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let dir = path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+        let obj_src = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+
+        Self::load_obj(device, queue, mipmaps, layout, texture_settings, &obj_src, |name| {
+            let file = dir.join(name);
+            std::fs::read(&file).with_context(|| format!("reading {}", file.display()))
+        })
+    }
+}
+
+
+// plain MTL has no metalness, so the PBR extension's Pm/Pr/Ke are used when present and roughness
+// is otherwise derived from the Phong exponent
+fn obj_factors(m: &tobj::Material) -> MaterialFactors {
+    let param = |key: &str| m.unknown_param.get(key).map(|v| v.split_whitespace().filter_map(|x| x.parse::<f32>().ok()).collect::<Vec<_>>());
+    let [r, g, b] = m.diffuse.unwrap_or([1.0, 1.0, 1.0]);
+
+    MaterialFactors {
+        base_color: Vector4::new(r, g, b, m.dissolve.unwrap_or(1.0)),
+        metallic: param("Pm").and_then(|v| v.first().copied()).unwrap_or(0.0),
+        roughness: param("Pr").and_then(|v| v.first().copied())
+            .or(m.shininess.map(|ns| (2.0 / (ns.max(0.0) + 2.0)).sqrt()))
+            .unwrap_or(0.5),
+        emissive: param("Ke").filter(|v| v.len() >= 3).map_or(Vector3::zeros(), |v| Vector3::new(v[0], v[1], v[2])),
+        ..Default::default()
+    }
+}
+
+fn obj_vertices(mesh: &tobj::Mesh) -> Vec<Vertex> {
+    let num_vertices = mesh.positions.len() / 3;
+    let normals = if mesh.normals.len() == mesh.positions.len() {
+        mesh.normals.chunks_exact(3).map(|n| [n[0], n[1], n[2]]).collect()
+    } else {
+        generate_normals(&mesh.positions, &mesh.indices)
+    };
+
+    (0..num_vertices).map(|i| {
+        let color = if mesh.vertex_color.len() == mesh.positions.len() {
+            [mesh.vertex_color[3 * i], mesh.vertex_color[3 * i + 1], mesh.vertex_color[3 * i + 2]]
+        } else {
+            [1.0, 1.0, 1.0]
+        };
+
+        let tex_coords = if mesh.texcoords.len() >= 2 * num_vertices {
+            [mesh.texcoords[2 * i], mesh.texcoords[2 * i + 1]]
+        } else {
+            [0.0, 0.0]
+        };
+
+        Vertex {
+            position: [mesh.positions[3 * i], mesh.positions[3 * i + 1], mesh.positions[3 * i + 2]],
+            color,
+            tex_coords,
+            normal: normals[i]
+        }
+    }).collect()
+}
+
+// smooth normals, each vertex gets the area weighted sum of the faces touching it
+pub(crate) fn generate_normals(positions: &[f32], indices: &[u32]) -> Vec<[f32; 3]> {
+    let position = |i: u32| Vector3::new(positions[3 * i as usize], positions[3 * i as usize + 1], positions[3 * i as usize + 2]);
+    let mut normals = vec![Vector3::<f32>::zeros(); positions.len() / 3];
+
+    for tri in indices.chunks_exact(3) {
+        let (a, b, c) = (position(tri[0]), position(tri[1]), position(tri[2]));
+        let face_normal = (b - a).cross(&(c - a));
+        for &i in tri {
+            normals[i as usize] += face_normal;
+        }
+    }
+
+    normals.iter().map(|n| n.try_normalize(1e-12).unwrap_or(Vector3::y()).into()).collect()
+}
+
+
+
+pub trait DrawModel {
+    fn draw_mesh_instanced(&mut self, mesh: &Mesh, material: &Material, instances: Range<u32>);
+    fn draw_model_instanced(&mut self, model: &Model, instances: Range<u32>);
+
+    // geometry only, for passes like shadow maps that don't bind materials
+    fn draw_model_geometry_instanced(&mut self, model: &Model, instances: Range<u32>);
+
+    // the unshared corners in Mesh::wireframe_buffer, meshes without one (not built yet) are skipped
+    fn draw_model_wireframe_instanced(&mut self, model: &Model, instances: Range<u32>);
+}
+
+impl DrawModel for RenderPass<'_> {
+    fn draw_mesh_instanced(&mut self, mesh: &Mesh, material: &Material, instances: Range<u32>) {
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..), IndexFormat::Uint32);
+        self.set_bind_group(0, &material.bind_group, &[]);
+        self.draw_indexed(0..mesh.num_elements, 0, instances);
+    }
+
+    fn draw_model_instanced(&mut self, model: &Model, instances: Range<u32>) {
+        for mesh in &model.meshes {
+            let material = &model.materials[mesh.material];
+            self.draw_mesh_instanced(mesh, material, instances.clone());
+        }
+    }
+
+    fn draw_model_geometry_instanced(&mut self, model: &Model, instances: Range<u32>) {
+        for mesh in &model.meshes {
+            self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            self.set_index_buffer(mesh.index_buffer.slice(..), IndexFormat::Uint32);
+            self.draw_indexed(0..mesh.num_elements, 0, instances.clone());
+        }
+    }
+
+    fn draw_model_wireframe_instanced(&mut self, model: &Model, instances: Range<u32>) {
+        for mesh in &model.meshes {
+            if let Some(buffer) = &mesh.wireframe_buffer {
+                self.set_vertex_buffer(0, buffer.slice(..));
+                self.set_bind_group(0, &model.materials[mesh.material].bind_group, &[]);
+                self.draw(0..mesh.num_elements, instances.clone());
+            }
+        }
+    }
+}
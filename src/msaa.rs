@@ -0,0 +1,90 @@
+use wgpu::*;
+
+// the sample counts every target in `formats` can be rendered with. without adapter specific format features
+// only what webgpu guarantees is allowed, which for most formats is 1 and 4
+pub fn supported_sample_counts(adapter: &Adapter, device: &Device, formats: &[TextureFormat]) -> Vec<u32> {
+    let adapter_specific = device.features().contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+
+    [1, 2, 4, 8].into_iter().filter(|&count| {
+        formats.iter().all(|&format| {
+            let features = if adapter_specific {
+                adapter.get_texture_format_features(format)
+            } else {
+                format.guaranteed_format_features(device.features())
+            };
+            features.flags.sample_count_supported(count)
+        })
+    }).collect()
+}
+
+
+// multisampled stand-ins for the main pass color targets, resolved into the real ones when the pass ends.
+// at 1x there are none and the pass draws straight into the targets
+pub struct Msaa {
+    pub sample_count: u32,
+    supported: Vec<u32>,
+    formats: [TextureFormat; 3],
+    targets: Option<[TextureView; 3]>
+}
+
+
+impl Msaa {
+    pub fn new(device: &Device, width: u32, height: u32, formats: [TextureFormat; 3], sample_count: u32, supported: Vec<u32>) -> Self {
+        let mut msaa = Self {
+            sample_count: 1,
+            supported,
+            formats,
+            targets: None
+        };
+        msaa.set_sample_count(device, sample_count, width, height);
+        msaa
+    }
+
+    // falls back to the closest supported count below the one asked for
+    pub fn set_sample_count(&mut self, device: &Device, sample_count: u32, width: u32, height: u32) {
+        let supported = self.supported.iter().copied().filter(|&count| count <= sample_count).max().unwrap_or(1);
+        if supported != sample_count {
+            log::warn!("{}x MSAA isn't supported here, using {}x", sample_count, supported);
+        }
+
+        self.sample_count = supported;
+        self.resize(device, width, height);
+    }
+
+    // the next supported count, wrapping back around to 1x
+    pub fn next_sample_count(&self) -> u32 {
+        self.supported.iter().copied().find(|&count| count > self.sample_count).unwrap_or(1)
+    }
+
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        if self.sample_count == 1 {
+            self.targets = None;
+            return;
+        }
+
+        self.targets = Some(self.formats.map(|format| {
+            device.create_texture(&TextureDescriptor {
+                label: Some("Multisampled Target"),
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1
+                },
+                mip_level_count: 1,
+                sample_count: self.sample_count,
+                dimension: TextureDimension::D2,
+                format,
+                usage: TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[]
+            }).create_view(&TextureViewDescriptor::default())
+        }));
+    }
+
+    // what the pass renders into for color target `index`, and the view it resolves into if that isn't `view` itself
+    pub fn attachment<'a>(&'a self, index: usize, view: &'a TextureView) -> (&'a TextureView, Option<&'a TextureView>) {
+        match &self.targets {
+            Some(targets) => (&targets[index], Some(view)),
+            None => (view, None)
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{camera::Projection, debug_view::DebugView, postprocess::PostSettings, taa::TaaSettings, texture::{TextureFilter, TextureSettings}, tonemap::TonemapSettings};
+
+// command line options. the browser has no command line, on wasm only the scene and texture filter can be given as
+// ?scene=url&filter=nearest in the page address and everything else stays default
+//
+//     wgpu-tutorial [scene.obj|scene.gltf|scene.glb] [--env sky.hdr|sky.exr] [--skybox pano.hdr|px,nx,py,ny,pz,nz] [--clear-color r,g,b]
+//                   [--filter nearest|bilinear|trilinear] [--anisotropy 16] [--no-mipmaps] [--tonemap aces|reinhard|agx]
+//                   [--exposure 0.0] [--auto-exposure] [--post bloom,vignette,aberration,grading,fxaa,grain|none]
+//                   [--lut strip.png] [--msaa 1|2|4|8] [--taa] [--debug-view lit|albedo|normals|uvs|depth|overdraw|wireframe]
+//                   [--reverse-z] [--infinite-far]
+//                   [--headless out.png] [--size 800x800]
+#[derive(Clone)]
+pub struct Options {
+    pub scene: Option<String>,
+    pub environment: Option<String>,
+    pub skybox: Vec<String>,        // empty falls back to the --env map, then to the clear color
+    pub clear_color: [f64; 3],      // linear, only seen when there is no skybox
+    pub texture_settings: TextureSettings,
+    pub tonemap_settings: TonemapSettings,
+    pub post_settings: PostSettings,
+    pub lut: Option<String>,
+    pub sample_count: u32,          // lowered to what the adapter supports
+    pub taa_settings: TaaSettings,
+    pub debug_view: DebugView,
+    pub projection: Projection,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub headless: Option<String>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub size: (u32, u32)
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            scene: None,
+            environment: None,
+            skybox: Vec::new(),
+            clear_color: [0.0; 3],
+            texture_settings: TextureSettings::default(),
+            tonemap_settings: TonemapSettings::default(),
+            post_settings: PostSettings::default(),
+            lut: None,
+            sample_count: 4,
+            taa_settings: TaaSettings::default(),
+            debug_view: DebugView::Lit,
+            projection: Projection::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            headless: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            size: (800, 800)
+        }
+    }
+}
+
+
+impl Options {
+    pub fn from_args() -> anyhow::Result<Self> {
+        #[allow(unused_mut)]
+        let mut options = Self::default();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use crate::{postprocess::PostEffect, tonemap::Tonemapper};
+
+            let mut args = std::env::args().skip(1);
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--env" => options.environment = Some(args.next().ok_or_else(|| anyhow::anyhow!("--env needs an .hdr or .exr path"))?),
+                    "--skybox" => {
+                        let paths = args.next().ok_or_else(|| anyhow::anyhow!("--skybox needs a panorama or six comma separated faces"))?;
+                        options.skybox = paths.split(',').map(str::to_string).collect();
+                        if options.skybox.len() != 1 && options.skybox.len() != 6 {
+                            anyhow::bail!("--skybox expects one panorama or six faces, got {}", options.skybox.len());
+                        }
+                    },
+                    "--clear-color" => {
+                        let color = args.next().ok_or_else(|| anyhow::anyhow!("--clear-color needs r,g,b"))?;
+                        let channels = color.split(',').map(str::parse).collect::<Result<Vec<f64>, _>>()?;
+                        options.clear_color = channels.try_into().map_err(|_| anyhow::anyhow!("--clear-color expects r,g,b, got {color}"))?;
+                    },
+                    "--filter" => options.texture_settings.filter = parse_filter(&args.next().ok_or_else(|| anyhow::anyhow!("--filter needs nearest, bilinear or trilinear"))?)?,
+                    "--anisotropy" => options.texture_settings.anisotropy = args.next().ok_or_else(|| anyhow::anyhow!("--anisotropy needs a level from 1 to 16"))?.parse()?,
+                    "--no-mipmaps" => options.texture_settings.generate_mipmaps = false,
+                    "--tonemap" => {
+                        let operator = args.next().ok_or_else(|| anyhow::anyhow!("--tonemap needs aces, reinhard or agx"))?;
+                        options.tonemap_settings.operator = match operator.as_str() {
+                            "aces" => Tonemapper::Aces,
+                            "reinhard" => Tonemapper::Reinhard,
+                            "agx" => Tonemapper::AgX,
+                            _ => anyhow::bail!("unknown tonemapper {operator}, expected aces, reinhard or agx")
+                        };
+                    },
+                    "--exposure" => options.tonemap_settings.exposure = args.next().ok_or_else(|| anyhow::anyhow!("--exposure needs a value in stops"))?.parse()?,
+                    "--auto-exposure" => options.tonemap_settings.auto_exposure = true,
+                    "--post" => {
+                        let list = args.next().ok_or_else(|| anyhow::anyhow!("--post needs a comma separated list of effects or none"))?;
+                        let effects = list.split(',').filter(|name| *name != "none").map(|name| match name {
+                            "bloom" => Ok(PostEffect::Bloom),
+                            "vignette" => Ok(PostEffect::Vignette),
+                            "aberration" => Ok(PostEffect::ChromaticAberration),
+                            "grading" => Ok(PostEffect::ColorGrading),
+                            "fxaa" => Ok(PostEffect::Fxaa),
+                            "grain" => Ok(PostEffect::FilmGrain),
+                            _ => anyhow::bail!("unknown effect {name}, expected bloom, vignette, aberration, grading, fxaa or grain")
+                        }).collect::<anyhow::Result<Vec<_>>>()?;
+                        options.post_settings.enable_only(&effects);
+                    },
+                    "--lut" => {
+                        options.lut = Some(args.next().ok_or_else(|| anyhow::anyhow!("--lut needs a png strip"))?);
+                        options.post_settings.set_enabled(PostEffect::ColorGrading, true);
+                    },
+                    "--msaa" => {
+                        options.sample_count = args.next().ok_or_else(|| anyhow::anyhow!("--msaa needs a sample count of 1, 2, 4 or 8"))?.parse()?;
+                        if ![1, 2, 4, 8].contains(&options.sample_count) {
+                            anyhow::bail!("--msaa expects 1, 2, 4 or 8, got {}", options.sample_count);
+                        }
+                    },
+                    "--taa" => options.taa_settings.enabled = true,
+                    "--debug-view" => {
+                        let view = args.next().ok_or_else(|| anyhow::anyhow!("--debug-view needs lit, albedo, normals, uvs, depth, overdraw or wireframe"))?;
+                        options.debug_view = match view.as_str() {
+                            "lit" => DebugView::Lit,
+                            "albedo" => DebugView::Albedo,
+                            "normals" => DebugView::Normals,
+                            "uvs" => DebugView::Uvs,
+                            "depth" => DebugView::Depth,
+                            "overdraw" => DebugView::Overdraw,
+                            "wireframe" => DebugView::Wireframe,
+                            _ => anyhow::bail!("unknown debug view {view}, expected lit, albedo, normals, uvs, depth, overdraw or wireframe")
+                        };
+                    },
+                    "--reverse-z" => options.projection.reverse_z = true,
+                    "--infinite-far" => options.projection.zfar = None,
+                    "--headless" => options.headless = Some(args.next().ok_or_else(|| anyhow::anyhow!("--headless needs an output path"))?),
+                    "--size" => {
+                        let size = args.next().ok_or_else(|| anyhow::anyhow!("--size needs WIDTHxHEIGHT"))?;
+                        let (w, h) = size.split_once('x').ok_or_else(|| anyhow::anyhow!("--size expects WIDTHxHEIGHT, got {size}"))?;
+                        options.size = (w.parse()?, h.parse()?);
+                        if options.size.0 == 0 || options.size.1 == 0 {
+                            anyhow::bail!("--size needs a width and height of at least 1, got {size}");
+                        }
+                    },
+                    flag if flag.starts_with("--") => anyhow::bail!("unknown option {flag}"),
+                    _ => options.scene = Some(arg)
+                }
+            }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            let search = web_sys::window().and_then(|w| w.location().search().ok()).unwrap_or_default();
+            let params = web_sys::UrlSearchParams::new_with_str(&search).ok();
+            options.scene = params.as_ref().and_then(|params| params.get("scene"));
+            if let Some(filter) = params.as_ref().and_then(|params| params.get("filter")) {
+                options.texture_settings.filter = parse_filter(&filter)?;
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_filter(filter: &str) -> anyhow::Result<TextureFilter> {
+    match filter {
+        "nearest" => Ok(TextureFilter::Nearest),
+        "bilinear" => Ok(TextureFilter::Bilinear),
+        "trilinear" => Ok(TextureFilter::Trilinear),
+        _ => anyhow::bail!("unknown filter {filter}, expected nearest, bilinear or trilinear")
+    }
+}
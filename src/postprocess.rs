@@ -0,0 +1,582 @@
+use bytemuck::Zeroable;
+use wgpu::{util::{BufferInitDescriptor, DeviceExt, TextureDataOrder}, *};
+
+use crate::{bloom::{Bloom, BloomSettings}, texture::Texture, tonemap::Tonemap};
+
+// bloom works on the hdr image so it always runs before tonemapping, the rest run in list order on the tonemapped image.
+// fxaa comes before film grain so the grain isn't taken for edges
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostEffect {
+    Bloom,
+    Vignette,
+    ChromaticAberration,
+    ColorGrading,
+    Fxaa,
+    FilmGrain
+}
+
+impl PostEffect {
+    pub const ALL: [PostEffect; 6] = [Self::Bloom, Self::Vignette, Self::ChromaticAberration, Self::ColorGrading, Self::Fxaa, Self::FilmGrain];
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct VignetteSettings {
+    pub intensity: f32,     // how dark the corners get, 0 to 1
+    pub radius: f32,        // distance from the center where darkening starts, 1 is a corner
+    pub smoothness: f32     // width of the falloff past the radius
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ChromaticAberrationSettings {
+    pub strength: f32       // red and blue offset at the corners, as a fraction of the screen
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ColorGradingSettings {
+    pub contribution: f32   // 0 leaves the image as it was, 1 is the full lut
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FxaaSettings {
+    pub subpixel: f32,              // how much single pixel detail gets smoothed, 0 to 1
+    pub edge_threshold: f32,        // local contrast needed to count as an edge, relative to the brightest neighbor
+    pub edge_threshold_min: f32     // and absolute, so noise in the dark isn't treated as edges
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FilmGrainSettings {
+    pub intensity: f32,
+    pub size: f32           // in pixels
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PostSettings {
+    pub effects: [(PostEffect, bool); 6],   // every effect once, in the order they run
+    pub bloom: BloomSettings,
+    pub vignette: VignetteSettings,
+    pub chromatic_aberration: ChromaticAberrationSettings,
+    pub color_grading: ColorGradingSettings,
+    pub fxaa: FxaaSettings,
+    pub film_grain: FilmGrainSettings
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        Self {
+            effects: PostEffect::ALL.map(|effect| (effect, matches!(effect, PostEffect::Bloom | PostEffect::Vignette))),
+            bloom: BloomSettings::default(),
+            vignette: VignetteSettings {
+                intensity: 0.35,
+                radius: 0.5,
+                smoothness: 0.6
+            },
+            chromatic_aberration: ChromaticAberrationSettings {
+                strength: 0.004
+            },
+            color_grading: ColorGradingSettings {
+                contribution: 1.0
+            },
+            fxaa: FxaaSettings {
+                subpixel: 0.75,
+                edge_threshold: 0.166,
+                edge_threshold_min: 0.0833
+            },
+            film_grain: FilmGrainSettings {
+                intensity: 0.06,
+                size: 1.5
+            }
+        }
+    }
+}
+
+impl PostSettings {
+    pub fn is_enabled(&self, effect: PostEffect) -> bool {
+        self.effects.iter().any(|&(e, enabled)| e == effect && enabled)
+    }
+
+    // the given effects are turned on in this order, everything else moves behind them turned off
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn enable_only(&mut self, effects: &[PostEffect]) {
+        let rest = PostEffect::ALL.into_iter().filter(|effect| !effects.contains(effect));
+        let order = effects.iter().map(|&effect| (effect, true)).chain(rest.map(|effect| (effect, false)));
+        for (slot, entry) in self.effects.iter_mut().zip(order) {
+            *slot = entry;
+        }
+    }
+
+    pub fn set_enabled(&mut self, effect: PostEffect, enabled: bool) {
+        for entry in self.effects.iter_mut().filter(|(e, _)| *e == effect) {
+            entry.1 = enabled;
+        }
+    }
+
+    pub fn toggle(&mut self, effect: PostEffect) {
+        self.set_enabled(effect, !self.is_enabled(effect));
+    }
+}
+
+
+// one fragment shader over a fullscreen triangle. post effects bind the image they read as group 0 and their own
+// parameters as group 1
+pub struct FullscreenPass {
+    pipeline: RenderPipeline,
+    blends: bool    // blending passes draw over what the target holds instead of clearing it
+}
+
+
+impl FullscreenPass {
+    // fullscreen.wgsl, compiled once and handed to every pass
+    pub fn vertex_shader(device: &Device) -> ShaderModule {
+        device.create_shader_module(include_wgsl!("fullscreen.wgsl"))
+    }
+
+    pub fn new(device: &Device, label: &str, vertex_shader: &ShaderModule, (shader, entry_point): (&ShaderModule, &str), bind_group_layouts: &[&BindGroupLayout], format: TextureFormat, blend: Option<BlendState>) -> Self {
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts,
+            push_constant_ranges: &[]
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: vertex_shader,
+                entry_point: Some("vs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[]
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: Some(entry_point),
+                compilation_options: PipelineCompilationOptions::default(),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend,
+                    write_mask: ColorWrites::ALL
+                })]
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None
+        });
+
+        Self {
+            pipeline,
+            blends: blend.is_some()
+        }
+    }
+
+    // a filterable texture and the sampler to read it with
+    pub fn input_layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Fullscreen Input Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false
+                    },
+                    count: None
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None
+                }
+            ]
+        })
+    }
+
+    pub fn input_bind_group(device: &Device, layout: &BindGroupLayout, view: &TextureView, sampler: &Sampler) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Fullscreen Input Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(view)
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(sampler)
+                }
+            ]
+        })
+    }
+
+    // bilinear and clamped, effects sampling off screen get the edge
+    pub fn create_sampler(device: &Device) -> Sampler {
+        device.create_sampler(&SamplerDescriptor {
+            label: Some("Fullscreen Sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Nearest,
+            ..Default::default()
+        })
+    }
+
+    // `blend_constant` feeds BlendFactor::Constant for passes that mix into their target
+    pub fn draw(&self, encoder: &mut CommandEncoder, output: &TextureView, bind_groups: &[&BindGroup], blend_constant: f64) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Fullscreen Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: Operations {
+                    load: if self.blends { LoadOp::Load } else { LoadOp::Clear(Color::BLACK) },
+                    store: StoreOp::Store
+                },
+                depth_slice: None
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            render_pass.set_bind_group(index as u32, *bind_group, &[]);
+        }
+        render_pass.set_blend_constant(Color { r: blend_constant, g: blend_constant, b: blend_constant, a: blend_constant });
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct VignetteUniform {
+    intensity: f32,
+    radius: f32,
+    smoothness: f32,
+    aspect_ratio: f32
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ChromaticAberrationUniform {
+    strength: f32,
+    _padding: [f32; 3]
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ColorGradingUniform {
+    contribution: f32,
+    lut_size: f32,
+    _padding: [f32; 2]
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct FxaaUniform {
+    subpixel: f32,
+    edge_threshold: f32,
+    edge_threshold_min: f32,
+    _padding: f32
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct FilmGrainUniform {
+    intensity: f32,
+    size: f32,
+    time: f32,
+    _padding: f32
+}
+
+
+// a display space effect, a pass plus the uniform and bind group holding its parameters
+struct Effect {
+    pass: FullscreenPass,
+    uniform_buffer: Buffer,
+    params: BindGroup
+}
+
+
+impl Effect {
+    fn new<T: bytemuck::Pod>(device: &Device, label: &str, (vertex_shader, shader): (&ShaderModule, ShaderModuleDescriptor), input_layout: &BindGroupLayout, format: TextureFormat, uniform: T, extra: &[(BindGroupLayoutEntry, BindingResource)]) -> Self {
+        let shader = device.create_shader_module(shader);
+
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+        });
+
+        // binding 0 is always the uniform, anything else the effect samples follows it
+        let uniform_entry = BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None
+            },
+            count: None
+        };
+        let layout_entries = std::iter::once(uniform_entry).chain(extra.iter().map(|(entry, _)| *entry)).collect::<Vec<_>>();
+        let params_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &layout_entries
+        });
+
+        let entries = std::iter::once(BindGroupEntry { binding: 0, resource: uniform_buffer.as_entire_binding() })
+            .chain(extra.iter().map(|(entry, resource)| BindGroupEntry { binding: entry.binding, resource: resource.clone() }))
+            .collect::<Vec<_>>();
+        let params = device.create_bind_group(&BindGroupDescriptor {
+            label: Some(label),
+            layout: &params_layout,
+            entries: &entries
+        });
+
+        Self {
+            pass: FullscreenPass::new(device, label, vertex_shader, (&shader, "fs_main"), &[input_layout, &params_layout], format, None),
+            uniform_buffer,
+            params
+        }
+    }
+
+    fn write<T: bytemuck::Pod>(&self, queue: &Queue, uniform: T) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+}
+
+
+// everything between the scene pass and the screen. display space effects ping-pong between two textures in the
+// output format, the last enabled one writes straight into the output
+pub struct PostProcess {
+    pub settings: PostSettings,
+    bloom: Bloom,
+    vignette: Effect,
+    chromatic_aberration: Effect,
+    color_grading: Effect,
+    fxaa: Effect,
+    film_grain: Effect,
+    lut_size: u32,
+
+    format: TextureFormat,
+    input_layout: BindGroupLayout,
+    sampler: Sampler,
+    ping_pong: [Texture; 2],
+    ping_pong_bind_groups: [BindGroup; 2],
+    aspect_ratio: f32
+}
+
+
+impl PostProcess {
+    pub const DEFAULT_LUT_SIZE: u32 = 32;
+
+    // `lut` is a 3d color grading table, a gentle built in look is used without one
+    pub fn new(device: &Device, queue: &Queue, vertex_shader: &ShaderModule, hdr: &Texture, format: TextureFormat, lut: Option<Texture>, settings: PostSettings) -> Self {
+        let input_layout = FullscreenPass::input_layout(device);
+        let sampler = FullscreenPass::create_sampler(device);
+        let size = hdr.texture.size();
+
+        let lut = lut.unwrap_or_else(|| create_lut(device, queue, Self::DEFAULT_LUT_SIZE, &default_look_lut(Self::DEFAULT_LUT_SIZE), "Default Color Grading LUT"));
+        let lut_size = lut.texture.size().width;
+
+        let vignette = Effect::new(device, "Vignette", (vertex_shader, include_wgsl!("vignette.wgsl")), &input_layout, format, VignetteUniform::zeroed(), &[]);
+        let chromatic_aberration = Effect::new(device, "Chromatic Aberration", (vertex_shader, include_wgsl!("chromatic_aberration.wgsl")), &input_layout, format, ChromaticAberrationUniform::zeroed(), &[]);
+        let fxaa = Effect::new(device, "FXAA", (vertex_shader, include_wgsl!("fxaa.wgsl")), &input_layout, format, FxaaUniform::zeroed(), &[]);
+        let film_grain = Effect::new(device, "Film Grain", (vertex_shader, include_wgsl!("film_grain.wgsl")), &input_layout, format, FilmGrainUniform::zeroed(), &[]);
+        let color_grading = Effect::new(device, "Color Grading", (vertex_shader, include_wgsl!("color_grading.wgsl")), &input_layout, format, ColorGradingUniform::zeroed(), &[
+            (BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D3,
+                    multisampled: false
+                },
+                count: None
+            }, BindingResource::TextureView(&lut.view)),
+            (BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None
+            }, BindingResource::Sampler(&lut.sampler))
+        ]);
+
+        let ping_pong = Self::create_ping_pong(device, size.width, size.height, format);
+        let ping_pong_bind_groups = ping_pong.each_ref().map(|texture| FullscreenPass::input_bind_group(device, &input_layout, &texture.view, &sampler));
+
+        Self {
+            settings,
+            bloom: Bloom::new(device, vertex_shader, &input_layout, &sampler, hdr, settings.bloom),
+            vignette,
+            chromatic_aberration,
+            color_grading,
+            fxaa,
+            film_grain,
+            lut_size,
+            format,
+            input_layout,
+            sampler,
+            ping_pong,
+            ping_pong_bind_groups,
+            aspect_ratio: size.width as f32 / size.height as f32
+        }
+    }
+
+    fn create_ping_pong(device: &Device, width: u32, height: u32, format: TextureFormat) -> [Texture; 2] {
+        [0, 1].map(|_| Texture::create_render_target(device, width, height, format, "Post Ping-Pong"))
+    }
+
+    // call after the hdr target was recreated
+    pub fn resize(&mut self, device: &Device, hdr: &Texture) {
+        let size = hdr.texture.size();
+        self.ping_pong = Self::create_ping_pong(device, size.width, size.height, self.format);
+        self.ping_pong_bind_groups = self.ping_pong.each_ref().map(|texture| FullscreenPass::input_bind_group(device, &self.input_layout, &texture.view, &self.sampler));
+        self.bloom.resize(device, &self.input_layout, &self.sampler, hdr);
+        self.aspect_ratio = size.width as f32 / size.height as f32;
+    }
+
+    pub fn update(&self, queue: &Queue, time: f32) {
+        let settings = &self.settings;
+
+        self.bloom.update(queue, &settings.bloom);
+        self.vignette.write(queue, VignetteUniform {
+            intensity: settings.vignette.intensity,
+            radius: settings.vignette.radius,
+            smoothness: settings.vignette.smoothness.max(1e-3),
+            aspect_ratio: self.aspect_ratio
+        });
+        self.chromatic_aberration.write(queue, ChromaticAberrationUniform {
+            strength: settings.chromatic_aberration.strength,
+            _padding: [0.0; 3]
+        });
+        self.color_grading.write(queue, ColorGradingUniform {
+            contribution: settings.color_grading.contribution,
+            lut_size: self.lut_size as f32,
+            _padding: [0.0; 2]
+        });
+        self.fxaa.write(queue, FxaaUniform {
+            subpixel: settings.fxaa.subpixel.clamp(0.0, 1.0),
+            edge_threshold: settings.fxaa.edge_threshold,
+            edge_threshold_min: settings.fxaa.edge_threshold_min,
+            _padding: 0.0
+        });
+        self.film_grain.write(queue, FilmGrainUniform {
+            intensity: settings.film_grain.intensity,
+            size: settings.film_grain.size.max(1.0),
+            time,
+            _padding: 0.0
+        });
+    }
+
+    // the effects that change the hdr target in place, run these before metering or tonemapping reads it
+    pub fn apply_hdr(&self, encoder: &mut CommandEncoder, hdr: &Texture) {
+        if self.settings.is_enabled(PostEffect::Bloom) {
+            self.bloom.draw(encoder, &hdr.view, self.settings.bloom.intensity, self.settings.bloom.scatter);
+        }
+    }
+
+    // tonemaps into the first ping-pong texture and runs the display space effects from there into `output`.
+    // doesn't touch the hdr target, so this can run again for another output
+    pub fn resolve(&self, encoder: &mut CommandEncoder, tonemap: &Tonemap, output: &TextureView) {
+        let effects = self.settings.effects.iter()
+            .filter(|&&(_, enabled)| enabled)
+            .filter_map(|&(effect, _)| match effect {
+                PostEffect::Bloom => None,
+                PostEffect::Vignette => Some(&self.vignette),
+                PostEffect::ChromaticAberration => Some(&self.chromatic_aberration),
+                PostEffect::ColorGrading => Some(&self.color_grading),
+                PostEffect::Fxaa => Some(&self.fxaa),
+                PostEffect::FilmGrain => Some(&self.film_grain)
+            })
+            .collect::<Vec<_>>();
+
+        if effects.is_empty() {
+            tonemap.draw(encoder, output);
+            return;
+        }
+
+        tonemap.draw(encoder, &self.ping_pong[0].view);
+        for (i, effect) in effects.iter().enumerate() {
+            let target = if i + 1 == effects.len() { output } else { &self.ping_pong[(i + 1) % 2].view };
+            effect.pass.draw(encoder, target, &[&self.ping_pong_bind_groups[i % 2], &effect.params], 0.0);
+        }
+    }
+}
+
+
+// a size^3 table, red along x, green along y and blue along z, holding srgb encoded colors
+pub fn create_lut(device: &Device, queue: &Queue, size: u32, texels: &[[u8; 4]], label: &str) -> Texture {
+    let texture = device.create_texture_with_data(queue, &TextureDescriptor {
+        label: Some(label),
+        size: Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: size
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D3,
+        format: TextureFormat::Rgba8Unorm,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[]
+    }, TextureDataOrder::LayerMajor, bytemuck::cast_slice(texels));
+
+    let view = texture.create_view(&TextureViewDescriptor::default());
+    let sampler = device.create_sampler(&SamplerDescriptor {
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        address_mode_w: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        mipmap_filter: FilterMode::Nearest,
+        ..Default::default()
+    });
+
+    Texture { texture, view, sampler }
+}
+
+// the usual unwrapped strip, `size` slices of size x size side by side with blue stepping from slice to slice
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_lut(device: &Device, queue: &Queue, path: &str) -> anyhow::Result<Texture> {
+    use anyhow::Context;
+
+    let image = image::open(path).with_context(|| format!("loading color grading lut {path}"))?.to_rgba8();
+    let size = image.height();
+    if image.width() != size * size {
+        anyhow::bail!("{path} is {}x{}, a lut strip is size*size wide and size tall", image.width(), image.height());
+    }
+
+    let texels = (0..size).flat_map(|b| (0..size).flat_map(move |g| (0..size).map(move |r| (r, g, b))))
+        .map(|(r, g, b)| image.get_pixel(b * size + r, g).0)
+        .collect::<Vec<_>>();
+
+    Ok(create_lut(device, queue, size, &texels, "Color Grading LUT"))
+}
+
+// a little contrast, cool shadows and warm highlights
+fn default_look_lut(size: u32) -> Vec<[u8; 4]> {
+    let scale = 1.0 / (size - 1) as f32;
+
+    (0..size).flat_map(|b| (0..size).flat_map(move |g| (0..size).map(move |r| [r, g, b])))
+        .map(|texel| {
+            let color = texel.map(|c| c as f32 * scale);
+            let luma = 0.2126 * color[0] + 0.7152 * color[1] + 0.0722 * color[2];
+            let tint = [0.05, 0.01, -0.05];
+
+            let graded = [0, 1, 2].map(|i| {
+                let c = color[i];
+                let contrast = c + 0.3 * (c * c * (3.0 - 2.0 * c) - c);
+                ((contrast + (luma - 0.5) * tint[i]).clamp(0.0, 1.0) * 255.0).round() as u8
+            });
+            [graded[0], graded[1], graded[2], 255]
+        })
+        .collect()
+}
@@ -0,0 +1,175 @@
+use nalgebra::*;
+use wgpu::*;
+
+use crate::{camera::CameraUniform, scene::Scene, texture::Texture};
+
+// runtime knobs for the stochastic probe shadows in shader.wgsl
+#[derive(Clone, Copy, Debug)]
+pub struct ProbeSettings {
+    pub num_samples: u32,
+    pub probe_density: f32,     // spacing of the probe grid shadow rays are snapped to, in world units
+    pub sample_radius: f32,     // random jitter before snapping
+    pub ray_offset: f32,        // shadow rays start this far along the normal and ignore hits closer than it
+    pub temporal_accumulation: bool,
+    pub history_weight: f32     // share of the previous frame kept each frame
+}
+
+impl Default for ProbeSettings {
+    fn default() -> Self {
+        Self {
+            num_samples: 100,
+            probe_density: 0.25,
+            sample_radius: 0.0,
+            ray_offset: 0.001,
+            temporal_accumulation: true,
+            history_weight: 0.75
+        }
+    }
+}
+
+
+// an oriented box, axes are unit length with the half extent along each in w
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct OccluderRaw {
+    center: [f32; 4],
+    axes: [[f32; 4]; 3]
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ProbeUniform {
+    previous_camera: CameraUniform,
+    num_samples: u32,
+    probe_density: f32,
+    sample_radius: f32,
+    ray_offset: f32,
+    history_weight: f32,    // 0 whenever the history can't be trusted
+    occluder_count: u32,
+    frame: u32,
+    _padding: u32
+}
+
+
+pub struct ProbeShadows {
+    pub settings: ProbeSettings,
+    pub uniform_buffer: Buffer,
+    pub occluder_buffer: Buffer,
+    occluder_capacity: usize,
+
+    // ping-pong pair, each frame reads one and renders into the other
+    pub history: [Texture; 2],
+    frame: u32,
+    previous_camera: Option<CameraUniform>
+}
+
+
+impl ProbeShadows {
+    // the probe lighting is accumulated in float, the alpha channel holds the view distance it was shaded at
+    pub const HISTORY_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+    pub fn new(device: &Device, scene: &Scene, width: u32, height: u32, settings: ProbeSettings) -> Self {
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Probe Uniform Buffer"),
+            size: std::mem::size_of::<ProbeUniform>() as BufferAddress,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        // storage bindings can't be empty
+        let occluder_capacity = scene_occluders(scene).len().max(1);
+        let occluder_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Occluder Buffer"),
+            size: (occluder_capacity * std::mem::size_of::<OccluderRaw>()) as BufferAddress,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        Self {
+            settings,
+            uniform_buffer,
+            occluder_buffer,
+            occluder_capacity,
+            history: Self::create_history(device, width, height),
+            frame: 0,
+            previous_camera: None
+        }
+    }
+
+    fn create_history(device: &Device, width: u32, height: u32) -> [Texture; 2] {
+        [0, 1].map(|_| Texture::create_render_target(device, width, height, Self::HISTORY_FORMAT, "Probe History"))
+    }
+
+    // the old history no longer lines up with the screen
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        self.history = Self::create_history(device, width, height);
+        self.previous_camera = None;
+    }
+
+    pub fn reset_history(&mut self) {
+        self.previous_camera = None;
+    }
+
+    // frame n renders into history[n % 2] and reads the other one
+    pub fn read_index(&self) -> usize {
+        (self.frame as usize + 1) % 2
+    }
+
+    pub fn write_view(&self) -> &TextureView {
+        &self.history[self.frame as usize % 2].view
+    }
+
+    // call once per frame with the camera the frame is drawn with
+    pub fn update(&mut self, queue: &Queue, scene: &Scene, camera: &CameraUniform) {
+        self.frame = self.frame.wrapping_add(1);
+
+        let mut occluders = scene_occluders(scene);
+        occluders.truncate(self.occluder_capacity);
+        queue.write_buffer(&self.occluder_buffer, 0, bytemuck::cast_slice(&occluders));
+
+        let history_weight = match self.previous_camera {
+            Some(_) if self.settings.temporal_accumulation => self.settings.history_weight.clamp(0.0, 0.98),
+            _ => 0.0
+        };
+
+        let uniform = ProbeUniform {
+            previous_camera: self.previous_camera.unwrap_or(*camera),
+            num_samples: self.settings.num_samples,
+            probe_density: self.settings.probe_density.max(1e-3),
+            sample_radius: self.settings.sample_radius,
+            ray_offset: self.settings.ray_offset,
+            history_weight,
+            occluder_count: occluders.len() as u32,
+            frame: self.frame,
+            _padding: 0
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        self.previous_camera = Some(*camera);
+    }
+}
+
+
+// one box per mesh per instance, the mesh bounds carried through the instance transform
+fn scene_occluders(scene: &Scene) -> Vec<OccluderRaw> {
+    scene.objects.iter().flat_map(|object| {
+        object.instances.iter().flat_map(move |instance| {
+            let matrix = instance.to_matrix();
+            object.model.meshes.iter().map(move |mesh| {
+                let center = matrix.transform_point(&mesh.bounds.center());
+                let half_extents = mesh.bounds.half_extents();
+
+                let axes = [0, 1, 2].map(|i| {
+                    let column = matrix.fixed_view::<3, 1>(0, i).into_owned();
+                    let unit = column.try_normalize(1e-6).unwrap_or_else(|| Vector3::ith(i, 1.0));
+                    unit.push(column.norm() * half_extents[i]).into()
+                });
+
+                OccluderRaw {
+                    center: center.to_homogeneous().into(),
+                    axes
+                }
+            })
+        })
+    }).collect()
+}
@@ -1,68 +1,115 @@
 use std::sync::Arc;
 
-#[cfg(target_arch="wasm32")]
-use web_time::Instant;
-
-#[cfg(not(target_arch="wasm32"))]
-use std::time::Instant;
-
-use nalgebra::{Quaternion, UnitQuaternion, Vector3};
-use winit::{dpi::PhysicalPosition, event_loop::ActiveEventLoop, keyboard::KeyCode, window::Window};
-#[cfg(target_arch = "wasm32")]
-use winit::event_loop::{self};
+use winit::{dpi::PhysicalPosition, event::{MouseButton, MouseScrollDelta}, event_loop::ActiveEventLoop, keyboard::KeyCode, window::{CursorGrabMode, Window}};
 
 use wgpu::{util::{BufferInitDescriptor, DeviceExt}, wgt::TextureViewDescriptor, *};
 
-use crate::{camera::*, instance::InstanceRaw, render, texture};
-use crate::texture::Texture;
-use crate::shader_structs::*;
+use nalgebra::{Point3, Vector2, Vector3};
+
+use crate::{camera::*, capture::{self, PendingCapture}, clock::Clock, color, debug_view::{DebugView, DebugViews}, ibl::{Environment, EnvironmentSettings}, light::{Light, LightKind, LightsUniform}, msaa::{self, Msaa}, options::Options, postprocess::{FullscreenPass, PostEffect, PostProcess}, probes::{ProbeSettings, ProbeShadows}, shadow::{ShadowMaps, ShadowSettings}, skybox::Skybox, taa::Taa, texture, tonemap::Tonemap};
+use crate::texture::{Texture, TextureSettings};
+use crate::material::Material;
+use crate::mipmap::MipmapGenerator;
+use crate::model::Model;
+use crate::scene::{DrawScene, Scene};
 use crate::helper::*;
 use crate::instance::Instance;
 
+// every color target of the main pass in location order, the lit color, the probe lighting and the motion vectors
+pub const MAIN_PASS_FORMATS: [TextureFormat; 3] = [Tonemap::HDR_FORMAT, ProbeShadows::HISTORY_FORMAT, Taa::MOTION_FORMAT];
+
+#[derive(Default)]
+struct MouseButtons {
+    left: bool,
+    middle: bool
+}
+
 pub struct State {
-    surface: Surface<'static>,          // the render target essentially
+    surface: Option<Surface<'static>>,  // the render target essentially, None when rendering headless
     device: Device,                     // the GPU
     queue: Queue,                       // the work queue for submitting commands to the GPU
     config: SurfaceConfiguration,       // the surface settings
     brown_render_pipeline: RenderPipeline,    // render pipeline handle
-    barycentric_render_pipeline: RenderPipeline,    // render pipeline handle
-    vertex_buffer: Buffer,
-    index_buffer: Buffer,
-
-    #[allow(unused)]
-    albedo_texture: Texture,
-    albedo_texture_bind_group: BindGroup,
+    render_pipeline_layout: PipelineLayout,
+    brown_triangle_shader: ShaderModule,        // kept to rebuild the pipeline when the sample count changes
+    scene: Scene,
 
     camera: Camera,
     camera_buffer: Buffer,
     camera_bind_group: BindGroup,
 
     time_buffer: Buffer,
-    time_bind_group: BindGroup,
+    lights: Vec<Light>,
+    light_buffer: Buffer,
+    shadow_maps: ShadowMaps,
+    probes: ProbeShadows,
+    frame_bind_group_layout: BindGroupLayout,
+    frame_bind_groups: [BindGroup; 2],  // one per probe history texture
 
-    instances: Vec<Instance>,
-    instance_buffer: Buffer,
+    #[allow(unused)]
+    environment: Environment,
+    environment_bind_group: BindGroup,
+    skybox: Option<Skybox>,
+    clear_color: Color,
+    tonemap: Tonemap,
+    post: PostProcess,
+    taa: Taa,
+    debug: DebugViews,
 
     depth_texture: Texture,
+    msaa: Msaa,
 
     is_surface_configured: bool,
-    triangle_toggle: bool,
-    num_indices: u32,
 
-    pub window: Arc<Window>,
+    pub window: Option<Arc<Window>>,
     mouse_pos: (f64, f64),
-    start_time: Instant
+    mouse_buttons: MouseButtons,
+    clock: Clock,
+    previous_camera_pose: CameraPose,
+
+    screenshot_requested: bool,
+    pending_captures: Vec<PendingCapture>
 }
 
 impl State {
-    pub async fn new(window: Arc<Window>) -> anyhow::Result<Self> {
-        let (surface, config, device, queue) = configure_surface(window.clone()).await?;
+    pub async fn new(window: Arc<Window>, options: &Options) -> anyhow::Result<Self> {
+        let (surface, config, adapter, device, queue) = configure_surface(window.clone()).await?;
+        Self::from_device(&adapter, device, queue, config, Some(surface), Some(window), options).await
+    }
+
+    // renders into an offscreen texture instead of a window, read frames back with render_to_image
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn new_headless(width: u32, height: u32, options: &Options) -> anyhow::Result<Self> {
+        let (adapter, device, queue) = create_headless_device().await?;
+
+        let config = SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            format: color::texture_format(true),
+            width,
+            height,
+            present_mode: PresentMode::Fifo,
+            alpha_mode: CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+
+        let mut state = Self::from_device(&adapter, device, queue, config, None, None, options).await?;
+        state.is_surface_configured = true;
+        Ok(state)
+    }
 
-        let diffuse_bytes = include_bytes!("../happy-tree.png");
-        let texture = Texture::from_bytes(&device, &queue, diffuse_bytes, "Happy Tree Texture").unwrap();
-        let (texture_bind_group_layout, texture_bind_group) = Texture::bind_default_texture(&device, &texture);
+    async fn from_device(adapter: &Adapter, device: Device, queue: Queue, config: SurfaceConfiguration, surface: Option<Surface<'static>>, window: Option<Arc<Window>>, options: &Options) -> anyhow::Result<Self> {
+        // everything the main pass draws into has to support the sample count
+        let supported_sample_counts = msaa::supported_sample_counts(adapter, &device, &[&MAIN_PASS_FORMATS[..], &[Texture::DEPTH_FORMAT]].concat());
+        let msaa = Msaa::new(&device, config.width, config.height, MAIN_PASS_FORMATS, options.sample_count, supported_sample_counts);
 
-        let camera = Camera::from_dimensions(config.width, config.height);
+        let material_bind_group_layout = Material::bind_group_layout(&device);
+        let fullscreen_shader = FullscreenPass::vertex_shader(&device);
+        let mipmaps = MipmapGenerator::new(&device, &fullscreen_shader);
+        let mut scene = load_startup_scene(&device, &queue, &mipmaps, &material_bind_group_layout, &options.texture_settings, options.scene.as_deref()).await?;
+
+        let mut camera = Camera::from_dimensions(config.width, config.height);
+        camera.projection = options.projection;
         let camera_uniform = camera.get_uniform();
         let (camera_buffer, camera_bind_group_layout, camera_bind_group) = CameraUniform::bind_camera(&camera_uniform, &device);
 
@@ -74,9 +121,14 @@ impl State {
             }
         );
 
-        let time_bind_group_layout = device.create_bind_group_layout(
+        let lights = orbiting_lights(0.0);
+        let mut shadow_maps = ShadowMaps::new(&device, ShadowSettings::default());
+        let shadow_indices = shadow_maps.update(&queue, &lights, &camera, scene.bounds());
+        let light_buffer = LightsUniform::create_buffer(&lights, &shadow_indices, &device);
+
+        let frame_bind_group_layout = device.create_bind_group_layout(
             &BindGroupLayoutDescriptor { 
-                label: Some("Time Bind Group Layout"), 
+                label: Some("Frame Bind Group Layout"), 
                 entries: &[
                     BindGroupLayoutEntry {
                         binding: 0,
@@ -87,72 +139,120 @@ impl State {
                             min_binding_size: None 
                         },
                         visibility: ShaderStages::FRAGMENT
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        count: None,
+                        ty: BindingType::Buffer { 
+                            ty: BufferBindingType::Uniform, 
+                            has_dynamic_offset: false, 
+                            min_binding_size: None 
+                        },
+                        visibility: ShaderStages::FRAGMENT
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        count: None,
+                        ty: BindingType::Texture { 
+                            sample_type: TextureSampleType::Depth, 
+                            view_dimension: TextureViewDimension::D2Array, 
+                            multisampled: false 
+                        },
+                        visibility: ShaderStages::FRAGMENT
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 3,
+                        count: None,
+                        ty: BindingType::Sampler(SamplerBindingType::Comparison),
+                        visibility: ShaderStages::FRAGMENT
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 4,
+                        count: None,
+                        ty: BindingType::Buffer { 
+                            ty: BufferBindingType::Uniform, 
+                            has_dynamic_offset: false, 
+                            min_binding_size: None 
+                        },
+                        visibility: ShaderStages::FRAGMENT
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 5,
+                        count: None,
+                        ty: BindingType::Buffer { 
+                            ty: BufferBindingType::Uniform, 
+                            has_dynamic_offset: false, 
+                            min_binding_size: None 
+                        },
+                        visibility: ShaderStages::FRAGMENT
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 6,
+                        count: None,
+                        ty: BindingType::Buffer { 
+                            ty: BufferBindingType::Storage { read_only: true }, 
+                            has_dynamic_offset: false, 
+                            min_binding_size: None 
+                        },
+                        visibility: ShaderStages::FRAGMENT
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 7,
+                        count: None,
+                        ty: BindingType::Texture { 
+                            sample_type: TextureSampleType::Float { filterable: false }, 
+                            view_dimension: TextureViewDimension::D2, 
+                            multisampled: false 
+                        },
+                        visibility: ShaderStages::FRAGMENT
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 8,
+                        count: None,
+                        ty: BindingType::Buffer { 
+                            ty: BufferBindingType::Uniform, 
+                            has_dynamic_offset: false, 
+                            min_binding_size: None 
+                        },
+                        visibility: ShaderStages::FRAGMENT
                     }
                 ] 
             }
         );
 
-        let time_bind_group = device.create_bind_group(
-            &BindGroupDescriptor { 
-                label: Some("Time Bind Group"), 
-                layout: &time_bind_group_layout, 
-                entries: &[
-                    BindGroupEntry {
-                        binding: 0,
-                        resource: time_buffer.as_entire_binding()
-                    }
-                ] 
-            }
-        );
+        let probes = ProbeShadows::new(&device, &scene, config.width, config.height, ProbeSettings::default());
 
-        let vertex_buffer = device.create_buffer_init(
-            &BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(VERTICES),
-                usage: BufferUsages::VERTEX
-            }
-        );
+        let environment = load_environment(&device, &queue, options.environment.as_deref())?;
+        let environment_bind_group_layout = Environment::bind_group_layout(&device);
+        let environment_bind_group = environment.bind_group(&device, &environment_bind_group_layout);
+
+        // --skybox images first, then the --env map itself, the clear color shows without either
+        let skybox_cubemap = load_skybox_cubemap(&device, &queue, &options.skybox)?;
+        let skybox = skybox_cubemap.as_ref()
+            .or(options.environment.is_some().then_some(&environment.cubemap))
+            .map(|cubemap| Skybox::new(&device, cubemap, MAIN_PASS_FORMATS, &camera, msaa.sample_count));
+        let [r, g, b] = options.clear_color;
 
-        let index_buffer = device.create_buffer_init(
-            &BufferInitDescriptor { 
-                label: Some("Index Buffer"), 
-                contents: bytemuck::cast_slice(INDICES), 
-                usage: BufferUsages::INDEX
-            }
-        );
-        
         let render_pipeline_layout  = device.create_pipeline_layout(
             &PipelineLayoutDescriptor { 
                 label: Some("Render Pipeline Layout"), 
-                bind_group_layouts: &[&texture_bind_group_layout, &camera_bind_group_layout, &time_bind_group_layout], 
+                bind_group_layouts: &[&material_bind_group_layout, &camera_bind_group_layout, &frame_bind_group_layout, &environment_bind_group_layout], 
                 push_constant_ranges: &[] 
             }
         );
 
         let brown_triangle_shader = device.create_shader_module(include_wgsl!("shader.wgsl"));
-        let barycentric_triangle_shader = device.create_shader_module(include_wgsl!("barycentric.wgsl"));
+        let brown_render_pipeline = make_pipeline_desc_from_shader(&device, &render_pipeline_layout, &brown_triangle_shader, &MAIN_PASS_FORMATS, camera.projection.depth_compare(), msaa.sample_count);
 
-        let brown_render_pipeline = make_pipeline_desc_from_shader(&device, &render_pipeline_layout, &brown_triangle_shader, config.format);
-        let barycentric_render_pipeline = make_pipeline_desc_from_shader(&device, &render_pipeline_layout, &barycentric_triangle_shader, config.format);
-
-        let instances = (0..1).flat_map(|x| {
-            (0..1).map(move |z| {
-                Instance {
-                    position: Vector3::new(2.0 * (x - 0) as f32, 0.0, 2.0 * (z - 0) as f32),
-                    rotation: Quaternion::identity()
-                }
-            })
-        }).collect::<Vec<_>>();
-        let instance_data = instances.iter().map(|x| x.to_raw()).collect::<Vec<_>>();
-        let instance_buffer = device.create_buffer_init(
-            &BufferInitDescriptor { 
-                label: Some("Instance Buffer"), 
-                contents: bytemuck::cast_slice(&instance_data),
-                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST
-            }
-        );
+        let depth_texture = Texture::create_depth_texture(&device, &config, camera.projection.depth_sample_compare(), msaa.sample_count, "Depth Texture");
+        let tonemap = Tonemap::new(&device, &fullscreen_shader, config.width, config.height, color::render_format(&config), options.tonemap_settings);
+        let lut = load_lut(&device, &queue, options.lut.as_deref())?;
+        let post = PostProcess::new(&device, &queue, &fullscreen_shader, &tonemap.hdr, color::render_format(&config), lut, options.post_settings);
+        let taa = Taa::new(&device, &fullscreen_shader, &tonemap.hdr, options.taa_settings);
 
-        let depth_texture = Texture::create_depth_texture(&device, &config, "Depth Texture");
+        let mut debug = DebugViews::new(&device, (&render_pipeline_layout, &brown_triangle_shader), &fullscreen_shader, camera.projection.depth_compare(), msaa.sample_count, &tonemap.hdr, color::render_format(&config));
+        debug.select(&device, &mut scene, options.debug_view);
+        let frame_bind_groups = create_frame_bind_groups(&device, &frame_bind_group_layout, &time_buffer, &light_buffer, &shadow_maps, &probes, &debug.uniform_buffer);
 
         Ok(Self {
             surface,
@@ -162,23 +262,35 @@ impl State {
             config,
             is_surface_configured: false,
             brown_render_pipeline,
-            barycentric_render_pipeline,
-            vertex_buffer,
+            render_pipeline_layout,
+            brown_triangle_shader,
+            scene,
             mouse_pos: (0.0, 0.0),
-            triangle_toggle: true,
-            num_indices: INDICES.len() as u32,
-            index_buffer,
-            albedo_texture_bind_group: texture_bind_group,
-            albedo_texture: texture,
+            mouse_buttons: MouseButtons::default(),
+            previous_camera_pose: camera.pose(),
             camera,
             camera_bind_group,
             camera_buffer,
-            instances,
-            instance_buffer,
             depth_texture,
-            start_time: Instant::now(),
+            msaa,
+            clock: Clock::new(),
+            screenshot_requested: false,
+            pending_captures: Vec::new(),
             time_buffer,
-            time_bind_group
+            lights,
+            light_buffer,
+            shadow_maps,
+            probes,
+            frame_bind_group_layout,
+            frame_bind_groups,
+            environment,
+            environment_bind_group,
+            skybox,
+            clear_color: Color { r, g, b, a: 1.0 },
+            tonemap,
+            post,
+            taa,
+            debug
         })
     }
 
@@ -199,10 +311,20 @@ impl State {
                 self.config.width = width;
                 self.config.height = height;
             }
-            self.surface.configure(&self.device, &self.config);
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
             self.is_surface_configured = true;
             self.camera.aspect_ratio = self.config.width as f32 / self.config.height as f32;
-            self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, "Depth Texture");
+            self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, self.camera.projection.depth_sample_compare(), self.msaa.sample_count, "Depth Texture");
+            self.msaa.resize(&self.device, self.config.width, self.config.height);
+            self.probes.resize(&self.device, self.config.width, self.config.height);
+            self.tonemap.resize(&self.device, self.config.width, self.config.height);
+            self.post.resize(&self.device, &self.tonemap.hdr);
+            self.taa.resize(&self.device, &self.tonemap.hdr);
+            self.debug.resize(&self.device, &self.tonemap.hdr);
+            self.camera.reset_previous_view_proj();
+            self.frame_bind_groups = create_frame_bind_groups(&self.device, &self.frame_bind_group_layout, &self.time_buffer, &self.light_buffer, &self.shadow_maps, &self.probes, &self.debug.uniform_buffer);
         }
     }
 
@@ -214,7 +336,46 @@ impl State {
     pub fn handle_key(&mut self, event_loop: &ActiveEventLoop, code: KeyCode, is_pressed: bool) {
         match (code, is_pressed) {
             (KeyCode::Escape, true) => event_loop.exit(),
-            (KeyCode::Space, true) => self.triangle_toggle = !self.triangle_toggle,
+            (KeyCode::F12, true) => self.screenshot_requested = true,
+            (KeyCode::KeyC, true) => self.toggle_camera_mode(),
+            (KeyCode::KeyP, true) => self.clock.toggle_pause(),
+            (KeyCode::BracketLeft, true) => self.clock.time_scale = (self.clock.time_scale * 0.5).max(1.0 / 16.0),
+            (KeyCode::BracketRight, true) => self.clock.time_scale = (self.clock.time_scale * 2.0).min(16.0),
+            (KeyCode::KeyO | KeyCode::Numpad5, true) => self.camera.toggle_projection(),
+            (KeyCode::Minus, true) => self.probes.settings.num_samples = (self.probes.settings.num_samples / 2).max(1),
+            (KeyCode::Equal, true) => self.probes.settings.num_samples = (self.probes.settings.num_samples * 2).min(1024),
+            (KeyCode::KeyH, true) => {
+                self.probes.settings.temporal_accumulation = !self.probes.settings.temporal_accumulation;
+                self.probes.reset_history();
+            },
+            (KeyCode::KeyV, true) => self.shadow_maps.settings.debug_cascades = !self.shadow_maps.settings.debug_cascades,
+            (KeyCode::KeyM, true) => self.tonemap.settings.operator = self.tonemap.settings.operator.next(),
+            (KeyCode::KeyX, true) => self.tonemap.settings.auto_exposure = !self.tonemap.settings.auto_exposure,
+            (KeyCode::Comma, true) => self.tonemap.settings.exposure -= 0.5,
+            (KeyCode::Period, true) => self.tonemap.settings.exposure += 0.5,
+            (KeyCode::F1, true) => self.post.settings.toggle(PostEffect::Bloom),
+            (KeyCode::F2, true) => self.post.settings.toggle(PostEffect::Vignette),
+            (KeyCode::F3, true) => self.post.settings.toggle(PostEffect::ChromaticAberration),
+            (KeyCode::F4, true) => self.post.settings.toggle(PostEffect::ColorGrading),
+            (KeyCode::F5, true) => self.post.settings.toggle(PostEffect::Fxaa),
+            (KeyCode::F6, true) => self.post.settings.toggle(PostEffect::FilmGrain),
+            (KeyCode::KeyN, true) => self.set_sample_count(self.msaa.next_sample_count()),
+            (KeyCode::KeyJ, true) => {
+                self.taa.settings.enabled = !self.taa.settings.enabled;
+                log::info!("TAA {}", if self.taa.settings.enabled { "on" } else { "off" });
+            },
+            (KeyCode::Digit1 | KeyCode::Numpad1, true) => self.set_preset_view(PresetView::Front, PresetView::Back),
+            (KeyCode::Digit3 | KeyCode::Numpad3, true) => self.set_preset_view(PresetView::Right, PresetView::Left),
+            (KeyCode::Digit7 | KeyCode::Numpad7, true) => self.set_preset_view(PresetView::Top, PresetView::Bottom),
+            (KeyCode::KeyT, true) => {
+                let step = if self.clock.fixed_timestep.is_some() { None } else { Some(1.0 / 60.0) };
+                self.clock.set_fixed_timestep(step);
+                self.previous_camera_pose = self.camera.pose();
+            },
+            (KeyCode::Space, true) if self.camera.mode == CameraMode::Orbit => {
+                self.debug.select(&self.device, &mut self.scene, self.debug.view().next());
+                log::info!("Debug view {:?}", self.debug.view());
+            },
 
             (KeyCode::KeyQ, x) => self.camera.cam_controller.q = x,
             (KeyCode::KeyE, x) => self.camera.cam_controller.e = x,
@@ -222,6 +383,9 @@ impl State {
             (KeyCode::KeyD, x) => self.camera.cam_controller.d = x,
             (KeyCode::KeyW, x) => self.camera.cam_controller.w = x,
             (KeyCode::KeyS, x) => self.camera.cam_controller.s = x,
+            (KeyCode::Space, x) => self.camera.cam_controller.space = x,
+            (KeyCode::ShiftLeft | KeyCode::ShiftRight, x) => self.camera.cam_controller.shift = x,
+            (KeyCode::ControlLeft | KeyCode::ControlRight, x) => self.camera.cam_controller.ctrl = x,
 
             _ => ()
         }
@@ -230,7 +394,97 @@ impl State {
 
      
     pub fn handle_mouse_moved(&mut self, _event_loop: &ActiveEventLoop, pos: PhysicalPosition<f64>) {
+        let dx = (pos.x - self.mouse_pos.0) as f32;
+        let dy = (pos.y - self.mouse_pos.1) as f32;
         self.mouse_pos = (pos.x, pos.y);
+
+        if self.camera.mode != CameraMode::Orbit {
+            return;
+        }
+
+        if self.mouse_buttons.left {
+            self.camera.orbit(dx, dy);
+        } else if self.mouse_buttons.middle {
+            self.camera.pan(dx, dy);
+        }
+    }
+
+    pub fn handle_mouse_input(&mut self, button: MouseButton, is_pressed: bool) {
+        match button {
+            MouseButton::Left => self.mouse_buttons.left = is_pressed,
+            MouseButton::Middle => self.mouse_buttons.middle = is_pressed,
+            _ => ()
+        }
+    }
+
+    pub fn handle_mouse_wheel(&mut self, delta: MouseScrollDelta) {
+        // trackpads report pixels, treat roughly one text line worth as a wheel notch
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 20.0
+        };
+
+        if self.camera.mode == CameraMode::Orbit {
+            self.camera.zoom(lines);
+        }
+    }
+
+    // raw device deltas, these keep coming while the cursor is grabbed
+    pub fn handle_mouse_motion(&mut self, delta: (f64, f64)) {
+        if self.camera.mode == CameraMode::Fly {
+            let (dx, dy) = &mut self.camera.cam_controller.mouse_delta;
+            *dx += delta.0 as f32;
+            *dy += delta.1 as f32;
+        }
+    }
+
+    // the pipelines, depth texture and multisampled targets all have to agree on it
+    fn set_sample_count(&mut self, sample_count: u32) {
+        self.msaa.set_sample_count(&self.device, sample_count, self.config.width, self.config.height);
+        let sample_count = self.msaa.sample_count;
+        log::info!("MSAA {}x", sample_count);
+
+        let depth_compare = self.camera.projection.depth_compare();
+        self.brown_render_pipeline = make_pipeline_desc_from_shader(&self.device, &self.render_pipeline_layout, &self.brown_triangle_shader, &MAIN_PASS_FORMATS, depth_compare, sample_count);
+        self.debug.set_sample_count(&self.device, sample_count);
+        if let Some(skybox) = &mut self.skybox {
+            skybox.set_sample_count(&self.device, sample_count);
+        }
+        self.depth_texture = Texture::create_depth_texture(&self.device, &self.config, self.camera.projection.depth_sample_compare(), sample_count, "Depth Texture");
+    }
+
+    // ctrl picks the opposite side, same as the blender numpad
+    fn set_preset_view(&mut self, view: PresetView, opposite: PresetView) {
+        if self.camera.mode == CameraMode::Fly {
+            self.toggle_camera_mode();
+        }
+        self.camera.set_preset_view(if self.camera.cam_controller.ctrl { opposite } else { view });
+        self.previous_camera_pose = self.camera.pose();
+        self.camera.reset_previous_view_proj();
+    }
+
+    fn toggle_camera_mode(&mut self) {
+        let mode = match self.camera.mode {
+            CameraMode::Orbit => CameraMode::Fly,
+            CameraMode::Fly => CameraMode::Orbit
+        };
+        self.camera.set_mode(mode);
+
+        let Some(window) = &self.window else {
+            return;
+        };
+
+        // mouse look needs the cursor held in place, Locked isn't available everywhere so fall back to Confined
+        if mode == CameraMode::Fly {
+            if let Err(e) = window.set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined)) {
+                log::warn!("Unable to grab cursor: {}", e);
+            }
+            window.set_cursor_visible(false);
+        } else {
+            let _ = window.set_cursor_grab(CursorGrabMode::None);
+            window.set_cursor_visible(true);
+        }
     }
 
 
@@ -238,15 +492,47 @@ impl State {
 
 
     pub fn update(&mut self) {
-        self.camera.update();
-        self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera.get_uniform()]));
+        let steps = self.clock.tick();
+
+        // the debug views skip the taa resolve, so they'd only shake
+        self.taa.update(&self.queue);
+        self.camera.jitter = match self.debug.view() {
+            DebugView::Lit => self.taa.jitter(self.config.width, self.config.height),
+            _ => Vector2::zeros()
+        };
+
+        // the camera runs on real time so it can still be flown around while paused or in slow motion
+        let camera_uniform = match self.clock.fixed_timestep {
+            Some(step) => {
+                for _ in 0..steps {
+                    self.previous_camera_pose = self.camera.pose();
+                    self.camera.update(step);
+                }
+                self.camera.get_interpolated_uniform(&self.previous_camera_pose, self.clock.alpha())
+            },
+            None => {
+                self.camera.update(self.clock.unscaled_delta);
+                self.camera.get_uniform()
+            }
+        };
+        self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[camera_uniform]));
+        self.camera.store_previous_view_proj(&camera_uniform);
+
+        self.queue.write_buffer(&self.time_buffer, 0, bytemuck::cast_slice(&[self.clock.total]));
 
-        // let _ = self.instances.iter_mut().for_each(|x: &mut Instance| x.rotation *= UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.1_f32.to_radians()).quaternion());
-        // let raw_instances = self.instances.iter().map(|x| x.to_raw()).collect::<Vec<InstanceRaw>>();
-        // self.queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&raw_instances));
+        self.lights = orbiting_lights(self.clock.total);
+        let shadow_indices = self.shadow_maps.update(&self.queue, &self.lights, &self.camera, self.scene.bounds());
+        LightsUniform::write_buffer(&self.lights, &shadow_indices, &self.queue, &self.light_buffer);
+
+        self.probes.update(&self.queue, &self.scene, &camera_uniform);
+
+        if let Some(skybox) = &mut self.skybox {
+            skybox.update(&self.queue, &self.camera);
+        }
 
-        let elapsed = self.start_time.elapsed().as_secs_f32();
-        self.queue.write_buffer(&self.time_buffer, 0, bytemuck::cast_slice(&[elapsed]));
+        self.tonemap.update(&self.queue, self.clock.unscaled_delta);
+        self.post.update(&self.queue, self.clock.total);
+        self.debug.update(&self.queue, &self.camera, self.scene.bounds());
     }
 
 
@@ -254,35 +540,295 @@ impl State {
     
 
     pub fn render(&mut self) -> Result<(), SurfaceError>{
-        self.window.request_redraw();
+        let (Some(window), Some(surface)) = (&self.window, &self.surface) else {
+            return Ok(());
+        };
+
+        window.request_redraw();
 
         if !self.is_surface_configured {
             return Ok(());
         }
 
-        let output = self.surface.get_current_texture()?;
+        let output = surface.get_current_texture()?;
 
-        let view = output.texture.create_view(&TextureViewDescriptor::default());
+        let view = color::surface_view(&output.texture, &self.config);
 
         let mut encoder = self.device.create_command_encoder( &CommandEncoderDescriptor {
             label: Some("Render Encoder")
         });
 
-        with_default_render_pass(&mut encoder, &view, Some(&self.depth_texture), |render_pass| {
-            render_pass.set_pipeline(if self.triangle_toggle { &self.brown_render_pipeline } else { &self.barycentric_render_pipeline });
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
-            render_pass.set_bind_group(0, &self.albedo_texture_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-            render_pass.set_bind_group(2, &self.time_bind_group, &[]);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..self.instances.len() as _);
+        self.draw(&mut encoder, &view);
+
+        // the swapchain texture can't be copied from everywhere, so screenshots resolve the frame a second time
+        let capture_buffer = std::mem::take(&mut self.screenshot_requested).then(|| {
+            let target = self.create_capture_target();
+            self.resolve(&mut encoder, &target.create_view(&TextureViewDescriptor::default()));
+            capture::copy_texture_to_buffer(&self.device, &mut encoder, &target)
         });
 
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
 
+        if let Some(buffer) = capture_buffer {
+            self.pending_captures.push(PendingCapture::new(buffer, self.config.width, self.config.height, color::render_format(&self.config)));
+        }
+        self.poll_captures();
+
         Ok(())
     }
+
+    fn poll_captures(&mut self) {
+        if self.pending_captures.is_empty() {
+            return;
+        }
+
+        let _ = self.device.poll(PollType::Poll);
+        self.pending_captures.retain(|capture| {
+            match capture.try_take_image() {
+                Some(Ok(image)) => capture::save_screenshot(image),
+                Some(Err(e)) => log::error!("Unable to read back screenshot: {}", e),
+                None => return true
+            }
+            false
+        });
+    }
+
+    fn create_capture_target(&self) -> wgpu::Texture {
+        self.device.create_texture(
+            &TextureDescriptor {
+                label: Some("Capture Target"),
+                size: Extent3d {
+                    width: self.config.width,
+                    height: self.config.height,
+                    depth_or_array_layers: 1
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: color::render_format(&self.config),
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+                view_formats: &[]
+            }
+        )
+    }
+
+    // draws the scene into a fresh texture and copies it back to the cpu
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn render_to_image(&mut self) -> anyhow::Result<image::RgbaImage> {
+        let target = self.create_capture_target();
+        let view = target.create_view(&TextureViewDescriptor::default());
+
+        let mut encoder = self.device.create_command_encoder( &CommandEncoderDescriptor {
+            label: Some("Offscreen Encoder")
+        });
+
+        self.draw(&mut encoder, &view);
+        let buffer = capture::copy_texture_to_buffer(&self.device, &mut encoder, &target);
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        capture::read_buffer_blocking(&self.device, &buffer, self.config.width, self.config.height, color::render_format(&self.config))
+    }
+
+    fn draw(&self, encoder: &mut CommandEncoder, view: &TextureView) {
+        self.shadow_maps.render(encoder, &self.scene);
+
+        let color = self.msaa.attachment(0, &self.tonemap.hdr.view);
+        let probe_history = self.msaa.attachment(1, self.probes.write_view());
+        let motion = self.msaa.attachment(2, &self.taa.motion.view);
+
+        // overdraw counts up from zero, and the other debug views read better without the sky behind them
+        let lit = self.debug.view() == DebugView::Lit;
+        let clear_color = if lit { self.clear_color } else { Color::BLACK };
+
+        with_default_render_pass(encoder, color, clear_color, &[probe_history, motion], Some(&self.depth_texture), self.camera.projection.depth_clear_value(), |render_pass| {
+            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.frame_bind_groups[self.probes.read_index()], &[]);
+            render_pass.set_bind_group(3, &self.environment_bind_group, &[]);
+
+            if !lit {
+                self.debug.draw(render_pass, &self.scene);
+                return;
+            }
+
+            render_pass.set_pipeline(&self.brown_render_pipeline);
+            render_pass.draw_scene(&self.scene);
+
+            if let Some(skybox) = &self.skybox {
+                skybox.draw(render_pass);
+            }
+        });
+
+        if lit {
+            self.taa.resolve(encoder, &self.tonemap.hdr);
+            self.tonemap.measure_exposure(encoder);
+            self.post.apply_hdr(encoder, &self.tonemap.hdr);
+        }
+        self.resolve(encoder, view);
+    }
+
+    // the finished hdr target onto `view`, through the tonemap and post processing or as a debug view
+    fn resolve(&self, encoder: &mut CommandEncoder, view: &TextureView) {
+        match self.debug.view() {
+            DebugView::Lit => self.post.resolve(encoder, &self.tonemap, view),
+            _ => self.debug.resolve(encoder, view)
+        }
+    }
+}
+
+
+
+// everything in group 2, once for each probe history texture it can read from
+fn create_frame_bind_groups(device: &Device, layout: &BindGroupLayout, time_buffer: &Buffer, light_buffer: &Buffer, shadow_maps: &ShadowMaps, probes: &ProbeShadows, debug_buffer: &Buffer) -> [BindGroup; 2] {
+    probes.history.each_ref().map(|history| {
+        device.create_bind_group(
+            &BindGroupDescriptor { 
+                label: Some("Frame Bind Group"), 
+                layout, 
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: time_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: light_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::TextureView(&shadow_maps.texture.view)
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: BindingResource::Sampler(&shadow_maps.texture.sampler)
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: shadow_maps.uniform_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 5,
+                        resource: probes.uniform_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 6,
+                        resource: probes.occluder_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 7,
+                        resource: BindingResource::TextureView(&history.view)
+                    },
+                    BindGroupEntry {
+                        binding: 8,
+                        resource: debug_buffer.as_entire_binding()
+                    }
+                ] 
+            }
+        )
+    })
+}
+
+// the five lights that used to be hardcoded in shader.wgsl, circling high above the origin, plus a dim shadowed sun
+fn orbiting_lights(time: f32) -> Vec<Light> {
+    const NUM_LIGHTS: usize = 5;
+    let interval = 2.0 * std::f32::consts::PI / NUM_LIGHTS as f32;
+
+    let sun = Light {
+        kind: LightKind::Directional {
+            direction: Vector3::new(-0.4, -1.0, -0.3)
+        },
+        color: Vector3::new(1.0, 0.95, 0.85),
+        intensity: 2.0,
+        cast_shadows: true
+    };
+
+    std::iter::once(sun).chain((0..NUM_LIGHTS).map(|l| {
+        let angle = time + l as f32 * interval;
+        Light {
+            kind: LightKind::Point {
+                position: Point3::new(2.0 * angle.cos(), 3.0, 2.0 * angle.sin()) * 4.0,
+                range: 40.0
+            },
+            color: Vector3::new(1.0, 1.0, 1.0),
+            intensity: 150.0,
+            cast_shadows: false
+        }
+    })).collect()
+}
+
+// an .hdr or .exr given with --env, a procedural sky otherwise
+fn load_environment(device: &Device, queue: &Queue, #[allow(unused)] path: Option<&str>) -> anyhow::Result<Environment> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = path {
+        return Environment::from_file(device, queue, path, EnvironmentSettings::default());
+    }
+
+    Environment::procedural_sky(device, queue, EnvironmentSettings::default())
+}
+
+fn load_skybox_cubemap(#[allow(unused)] device: &Device, #[allow(unused)] queue: &Queue, #[allow(unused)] paths: &[String]) -> anyhow::Result<Option<Texture>> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if !paths.is_empty() {
+        return Skybox::load_cubemap(device, queue, paths).map(Some);
+    }
+
+    Ok(None)
+}
+
+// a --lut strip, PostProcess falls back to its built in look without one
+fn load_lut(#[allow(unused)] device: &Device, #[allow(unused)] queue: &Queue, #[allow(unused)] path: Option<&str>) -> anyhow::Result<Option<Texture>> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = path {
+        return crate::postprocess::load_lut(device, queue, path).map(Some);
+    }
+
+    Ok(None)
+}
+
+// a path given on the command line (.obj, .gltf or .glb) replaces the embedded cube scene, on wasm a .gltf or .glb
+// url from the page address does
+async fn load_startup_scene(device: &Device, queue: &Queue, mipmaps: &MipmapGenerator, layout: &BindGroupLayout, texture_settings: &TextureSettings, #[allow(unused)] path: Option<&str>) -> anyhow::Result<Scene> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = path {
+        let extension = std::path::Path::new(&path).extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+        return match extension.as_deref() {
+            Some("gltf" | "glb") => Scene::load_gltf_file(device, queue, mipmaps, layout, texture_settings, path),
+            Some("obj") => Ok(Scene::from_model(device, Model::load_obj_file(device, queue, mipmaps, layout, texture_settings, path)?, vec![Instance::default()])),
+            _ => anyhow::bail!("unsupported scene file {path}, expected .obj, .gltf or .glb")
+        };
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    if let Some(url) = path {
+        return Scene::fetch_gltf(device, queue, mipmaps, layout, texture_settings, url).await;
+    }
+
+    let model = Model::load_obj(device, queue, mipmaps, layout, texture_settings, include_str!("../res/cube.obj"), |name| {
+        match name {
+            "cube.mtl" => Ok(include_bytes!("../res/cube.mtl").to_vec()),
+            "happy-tree.png" => Ok(include_bytes!("../res/happy-tree.png").to_vec()),
+            _ => anyhow::bail!("no embedded resource named {name}")
+        }
+    })?;
+
+    Ok(Scene::from_model(device, model, vec![Instance::default()]))
+}
+
+
+
+// renders a single frame without opening a window and saves it as a png
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_headless(output: &str, options: &Options) -> anyhow::Result<()> {
+    env_logger::init();
+
+    let (width, height) = options.size;
+    let mut state = pollster::block_on(State::new_headless(width, height, options))?;
+    state.resize(width, height);
+    state.update();
+    state.render_to_image()?.save(output)?;
+
+    log::info!("Saved {}", output);
+    Ok(())
 }
 
@@ -0,0 +1,363 @@
+use std::{collections::HashMap, rc::Rc};
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use nalgebra::Matrix4;
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, *};
+
+use crate::{instance::Instance, material::{Material, MaterialFactors, MaterialTextures}, mipmap::MipmapGenerator, model::{self, Aabb, DrawModel, Mesh, Model}, shader_structs::Vertex, texture::{Texture, TextureSettings}};
+
+// a model together with every place it is drawn
+pub struct SceneObject {
+    pub model: Model,
+    pub instances: Vec<Instance>,
+    pub instance_buffer: Buffer
+}
+
+pub struct Scene {
+    pub objects: Vec<SceneObject>
+}
+
+
+impl SceneObject {
+    pub fn new(device: &Device, model: Model, instances: Vec<Instance>) -> Self {
+        let instance_data = instances.iter().map(|x| x.to_raw()).collect::<Vec<_>>();
+        let instance_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some("Instance Buffer"),
+                contents: bytemuck::cast_slice(&instance_data),
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST
+            }
+        );
+
+        Self { model, instances, instance_buffer }
+    }
+}
+
+
+impl Scene {
+    // world space bounds over every instance, None for an empty scene
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.objects.iter()
+            .filter_map(|o| o.model.bounds().map(|b| (o, b)))
+            .flat_map(|(o, b)| o.instances.iter().map(move |i| b.transformed(&i.to_matrix())))
+            .reduce(|a, b| a.union(&b))
+    }
+
+    // the wireframe debug view's buffers, on its first selection
+    pub fn build_wireframes(&mut self, device: &Device) {
+        for mesh in self.objects.iter_mut().flat_map(|o| &mut o.model.meshes) {
+            mesh.build_wireframe(device);
+        }
+    }
+
+    pub fn from_model(device: &Device, model: Model, instances: Vec<Instance>) -> Self {
+        Self {
+            objects: vec![SceneObject::new(device, model, instances)]
+        }
+    }
+
+    // accepts both .gltf (json) and .glb, `load_file` resolves external buffer and image uris
+    pub fn load_gltf<F>(device: &Device, queue: &Queue, mipmaps: &MipmapGenerator, layout: &BindGroupLayout, texture_settings: &TextureSettings, bytes: &[u8], load_file: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Result<Vec<u8>>
+    {
+        let gltf = gltf::Gltf::from_slice(bytes)?;
+
+        let buffers = gltf.buffers().map(|buffer| {
+            match buffer.source() {
+                gltf::buffer::Source::Bin => gltf.blob.clone().context("glTF references a missing GLB binary chunk"),
+                gltf::buffer::Source::Uri(uri) => load_uri(uri, &load_file)
+            }
+        }).collect::<Result<Vec<_>>>()?;
+
+        // kept encoded, ktx2 and dds images are uploaded without ever going through rgba8
+        let images = gltf.images().map(|image| {
+            let bytes = match image.source() {
+                gltf::image::Source::View { view, .. } => {
+                    let start = view.offset();
+                    buffers.get(view.buffer().index())
+                        .and_then(|buffer| buffer.get(start..start + view.length()))
+                        .with_context(|| format!("glTF image {} lies outside its buffer", image.index()))?
+                        .to_vec()
+                },
+                gltf::image::Source::Uri { uri, .. } => load_uri(uri, &load_file)?
+            };
+            Ok(bytes)
+        }).collect::<Result<Vec<_>>>()?;
+
+        let mut materials = gltf.materials().map(|material| {
+            let pbr = material.pbr_metallic_roughness();
+            let name = material.name().unwrap_or("glTF Material");
+
+            let load_texture = |texture: Option<gltf::Texture>, srgb: bool| -> Result<Option<Texture>> {
+                let Some(texture) = texture else { return Ok(None) };
+                let image = texture_image(&texture).context("glTF texture has no image source")?;
+                let bytes = images.get(image).with_context(|| format!("glTF texture references missing image {image}"))?;
+                let sampler = texture.sampler();
+                let settings = TextureSettings {
+                    address_modes: [address_mode(sampler.wrap_s()), address_mode(sampler.wrap_t())],
+                    ..*texture_settings
+                };
+                let texture = Texture::from_encoded(device, queue, mipmaps, bytes, srgb, &settings, Some(name))
+                    .with_context(|| format!("decoding glTF image {image}"))?;
+                Ok(Some(texture))
+            };
+
+            let normal = material.normal_texture();
+            let occlusion = material.occlusion_texture();
+
+            let textures = MaterialTextures {
+                base_color: load_texture(pbr.base_color_texture().map(|t| t.texture()), true)?,
+                metallic_roughness: load_texture(pbr.metallic_roughness_texture().map(|t| t.texture()), false)?,
+                normal: load_texture(normal.as_ref().map(|t| t.texture()), false)?,
+                occlusion: load_texture(occlusion.as_ref().map(|t| t.texture()), false)?,
+                emissive: load_texture(material.emissive_texture().map(|t| t.texture()), true)?
+            };
+
+            let factors = MaterialFactors {
+                base_color: pbr.base_color_factor().into(),
+                metallic: pbr.metallic_factor(),
+                roughness: pbr.roughness_factor(),
+                normal_scale: normal.as_ref().map_or(1.0, |t| t.scale()),
+                occlusion_strength: occlusion.as_ref().map_or(1.0, |t| t.strength()),
+                emissive: material.emissive_factor().into()
+            };
+
+            Ok(Rc::new(Material::new(device, queue, layout, name, textures, factors)?))
+        }).collect::<Result<Vec<_>>>()?;
+
+        // primitives without a material use the glTF default material, which is plain white and fully metallic
+        let default_material = materials.len();
+        let default_factors = MaterialFactors { metallic: 1.0, roughness: 1.0, ..Default::default() };
+        materials.push(Rc::new(Material::new(device, queue, layout, "Default Material", MaterialTextures::default(), default_factors)?));
+
+        let mut models = gltf.meshes().map(|mesh| {
+            let name = mesh.name().unwrap_or("glTF Mesh");
+            let mut meshes = Vec::new();
+            let mut model_materials = Vec::new();
+            let mut material_slots = HashMap::new();
+
+            for primitive in mesh.primitives() {
+                if primitive.mode() != gltf::mesh::Mode::Triangles {
+                    log::warn!("Skipping non triangle primitive in mesh {}", name);
+                    continue;
+                }
+
+                let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|b| b.as_slice()));
+
+                let positions = reader.read_positions().context("glTF primitive has no positions")?.collect::<Vec<_>>();
+                let indices = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+                    None => (0..positions.len() as u32).collect()
+                };
+                let normals = reader.read_normals().map(|n| n.collect::<Vec<_>>());
+                let tex_coords = reader.read_tex_coords(0).map(|t| t.into_f32().collect::<Vec<_>>());
+                let colors = reader.read_colors(0).map(|c| c.into_rgb_f32().collect::<Vec<_>>());
+
+                // every attribute has to line up with the positions and every index has to name one of them
+                let attributes = [("normals", normals.as_ref().map(Vec::len)), ("uvs", tex_coords.as_ref().map(Vec::len)), ("colors", colors.as_ref().map(Vec::len))];
+                for (attribute, count) in attributes {
+                    if let Some(count) = count && count != positions.len() {
+                        anyhow::bail!("glTF mesh {name} has {count} {attribute} for {} positions", positions.len());
+                    }
+                }
+                if let Some(index) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+                    anyhow::bail!("glTF mesh {name} indexes vertex {index} but has only {} positions", positions.len());
+                }
+
+                let normals = normals.unwrap_or_else(|| model::generate_normals(positions.as_flattened(), &indices));
+
+                let vertices = positions.iter().enumerate().map(|(i, &position)| {
+                    // glTF puts the uv origin at the top left, the shaders expect it at the bottom left like OBJ
+                    let [u, v] = tex_coords.as_ref().map_or([0.0, 0.0], |t| t[i]);
+                    Vertex {
+                        position,
+                        color: colors.as_ref().map_or([1.0, 1.0, 1.0], |c| c[i]),
+                        tex_coords: [u, 1.0 - v],
+                        normal: normals[i]
+                    }
+                }).collect::<Vec<_>>();
+
+                let material_index = primitive.material().index().unwrap_or(default_material);
+                let material = *material_slots.entry(material_index).or_insert_with(|| {
+                    model_materials.push(materials[material_index].clone());
+                    model_materials.len() - 1
+                });
+
+                meshes.push(Mesh::new(device, name, &vertices, &indices, material));
+            }
+
+            Ok((Model { meshes, materials: model_materials }, Vec::new()))
+        }).collect::<Result<Vec<_>>>()?;
+
+        let scene = gltf.default_scene().or_else(|| gltf.scenes().next()).context("glTF file contains no scenes")?;
+        for node in scene.nodes() {
+            collect_instances(&node, &Matrix4::identity(), &mut models);
+        }
+
+        let objects = models.into_iter()
+            .filter(|(_, instances)| !instances.is_empty())
+            .map(|(model, instances)| SceneObject::new(device, model, instances))
+            .collect();
+
+        Ok(Self { objects })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_gltf_file(device: &Device, queue: &Queue, mipmaps: &MipmapGenerator, layout: &BindGroupLayout, texture_settings: &TextureSettings, path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let dir = path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+        let bytes = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+
+        Self::load_gltf(device, queue, mipmaps, layout, texture_settings, &bytes, |name| {
+            let file = dir.join(name);
+            std::fs::read(&file).with_context(|| format!("reading {}", file.display()))
+        })
+    }
+
+    // the browser has no file system, so every file the scene refers to is fetched relative to `url` up front
+    #[cfg(target_arch = "wasm32")]
+    pub async fn fetch_gltf(device: &Device, queue: &Queue, mipmaps: &MipmapGenerator, layout: &BindGroupLayout, texture_settings: &TextureSettings, url: &str) -> Result<Self> {
+        let bytes = fetch(url).await?;
+        let dir = url.rsplit_once('/').map_or("", |(dir, _)| dir);
+
+        let gltf = gltf::Gltf::from_slice(&bytes)?;
+        let uris = gltf.buffers()
+            .filter_map(|buffer| match buffer.source() {
+                gltf::buffer::Source::Uri(uri) => Some(uri.to_string()),
+                gltf::buffer::Source::Bin => None
+            })
+            .chain(gltf.images().filter_map(|image| match image.source() {
+                gltf::image::Source::Uri { uri, .. } => Some(uri.to_string()),
+                gltf::image::Source::View { .. } => None
+            }))
+            .filter(|uri| !uri.starts_with("data:"))
+            .collect::<Vec<_>>();
+
+        let mut files = HashMap::new();
+        for uri in uris {
+            let name = percent_decode(&uri);
+            let file_url = if dir.is_empty() { uri } else { format!("{dir}/{uri}") };
+            files.insert(name, fetch(&file_url).await?);
+        }
+
+        Self::load_gltf(device, queue, mipmaps, layout, texture_settings, &bytes, |name| {
+            files.get(name).cloned().with_context(|| format!("{name} was not fetched with the scene"))
+        })
+    }
+}
+
+
+#[cfg(target_arch = "wasm32")]
+async fn fetch(url: &str) -> Result<Vec<u8>> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    let error = |e: wasm_bindgen::JsValue| anyhow::anyhow!("fetching {url}: {e:?}");
+    let window = web_sys::window().context("no browser window to fetch with")?;
+    let response = JsFuture::from(window.fetch_with_str(url)).await.map_err(error)?
+        .dyn_into::<web_sys::Response>().map_err(error)?;
+    if !response.ok() {
+        anyhow::bail!("fetching {url}: http {}", response.status());
+    }
+
+    let buffer = JsFuture::from(response.array_buffer().map_err(error)?).await.map_err(error)?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+
+fn collect_instances(node: &gltf::Node, parent: &Matrix4<f32>, models: &mut [(Model, Vec<Instance>)]) {
+    let transform = parent * Matrix4::from(node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        models[mesh.index()].1.push(Instance::from_matrix(&transform));
+    }
+
+    for child in node.children() {
+        collect_instances(&child, &transform, models);
+    }
+}
+
+// the container extensions name their image in the extension and may leave the core source as a png fallback
+fn texture_image(texture: &gltf::Texture) -> Option<usize> {
+    ["KHR_texture_basisu", "MSFT_texture_dds"].iter()
+        .find_map(|extension| texture.extension_value(extension)?.get("source")?.as_u64())
+        .map(|index| index as usize)
+        .or_else(|| texture.source().map(|image| image.index()))
+}
+
+fn address_mode(mode: gltf::texture::WrappingMode) -> AddressMode {
+    match mode {
+        gltf::texture::WrappingMode::Repeat => AddressMode::Repeat,
+        gltf::texture::WrappingMode::MirroredRepeat => AddressMode::MirrorRepeat,
+        gltf::texture::WrappingMode::ClampToEdge => AddressMode::ClampToEdge
+    }
+}
+
+// handles base64 data uris inline so embedded assets need no file access, which wasm doesn't have
+fn load_uri<F>(uri: &str, load_file: &F) -> Result<Vec<u8>>
+where
+    F: Fn(&str) -> Result<Vec<u8>>
+{
+    match uri.strip_prefix("data:") {
+        Some(data) => {
+            let (_, payload) = data.split_once(";base64,").context("only base64 data uris are supported")?;
+            Ok(base64::engine::general_purpose::STANDARD.decode(payload)?)
+        },
+        None => load_file(&percent_decode(uri))
+    }
+}
+
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            },
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+
+
+pub trait DrawScene {
+    fn draw_scene(&mut self, scene: &Scene);
+    fn draw_scene_geometry(&mut self, scene: &Scene);
+    fn draw_scene_wireframe(&mut self, scene: &Scene);
+}
+
+impl DrawScene for RenderPass<'_> {
+    fn draw_scene(&mut self, scene: &Scene) {
+        for object in &scene.objects {
+            self.set_vertex_buffer(1, object.instance_buffer.slice(..));
+            self.draw_model_instanced(&object.model, 0..object.instances.len() as u32);
+        }
+    }
+
+    fn draw_scene_geometry(&mut self, scene: &Scene) {
+        for object in &scene.objects {
+            self.set_vertex_buffer(1, object.instance_buffer.slice(..));
+            self.draw_model_geometry_instanced(&object.model, 0..object.instances.len() as u32);
+        }
+    }
+
+    fn draw_scene_wireframe(&mut self, scene: &Scene) {
+        for object in &scene.objects {
+            self.set_vertex_buffer(1, object.instance_buffer.slice(..));
+            self.draw_model_wireframe_instanced(&object.model, 0..object.instances.len() as u32);
+        }
+    }
+}
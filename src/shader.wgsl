@@ -12,8 +12,8 @@ struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
-    @location(2) normal: vec3<f32>,
-    @location(3) pos: vec3<f32>
+    @location(2) normal: vec3<f32>,       // world space
+    @location(3) pos: vec3<f32>           // world space
 };
 
 struct InstanceInput {
@@ -21,10 +21,17 @@ struct InstanceInput {
     @location(6) model_matrix_1: vec4<f32>,
     @location(7) model_matrix_2: vec4<f32>,
     @location(8) model_matrix_3: vec4<f32>,
+    @location(9) normal_matrix_0: vec3<f32>,
+    @location(10) normal_matrix_1: vec3<f32>,
+    @location(11) normal_matrix_2: vec3<f32>,
 }
 
+// matches camera::CameraUniform
 struct CameraUniform {
-    view_proj: mat4x4<f32>
+    view_proj: mat4x4<f32>,             // jittered
+    view_pos: vec4<f32>,
+    unjittered_view_proj: mat4x4<f32>,
+    previous_view_proj: mat4x4<f32>,    // last frame's, unjittered
 }
 
 @group(1) @binding(0)
@@ -38,14 +45,20 @@ fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
         instance.model_matrix_2,
         instance.model_matrix_3,
     );
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
 
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
     out.color = model.color;
-    out.normal = model.normal;
-    out.pos = model.position;
+    out.normal = normal_matrix * model.normal;
 
-    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    let world_pos = model_matrix * vec4<f32>(model.position, 1.0);
+    out.pos = world_pos.xyz;
+    out.clip_position = camera.view_proj * world_pos;
 
     return out;
 }
@@ -56,100 +69,513 @@ fn rng(seed: f32) -> f32 {
 }
 
 
-@group(0) @binding(0)
-var diff_tex: texture_2d<f32>;
+// matches material::MaterialUniform
+struct Material {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+}
 
+@group(0) @binding(0)
+var base_color_tex: texture_2d<f32>;
 @group(0) @binding(1)
-var diff_sampler: sampler;
+var base_color_sampler: sampler;
+
+// metallic in blue, roughness in green
+@group(0) @binding(2)
+var metallic_roughness_tex: texture_2d<f32>;
+@group(0) @binding(3)
+var metallic_roughness_sampler: sampler;
+
+@group(0) @binding(4)
+var normal_tex: texture_2d<f32>;
+@group(0) @binding(5)
+var normal_sampler: sampler;
+
+@group(0) @binding(6)
+var occlusion_tex: texture_2d<f32>;
+@group(0) @binding(7)
+var occlusion_sampler: sampler;
+
+@group(0) @binding(8)
+var emissive_tex: texture_2d<f32>;
+@group(0) @binding(9)
+var emissive_sampler: sampler;
+
+@group(0) @binding(10)
+var<uniform> material: Material;
 
 @group(2) @binding(0)
 var<uniform> time: f32;
 
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    var tex_coords = in.tex_coords;
-    tex_coords.y = 1.0 - tex_coords.y;
+const MAX_LIGHTS = 16;
+const LIGHT_DIRECTIONAL = 0u;
+const LIGHT_POINT = 1u;
+const LIGHT_SPOT = 2u;
+
+// matches light::LightRaw, 64 bytes so the array stride is valid for a uniform
+struct Light {
+    position: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    range: f32,
+    color: vec3<f32>,
+    intensity: f32,
+    inner_cos: f32,
+    outer_cos: f32,
+    shadow_index: i32,      // layer in shadow_maps, -1 when the light has no shadow map
+    _padding: f32,
+}
 
-    let tex_color : vec4<f32> = textureSample(diff_tex, diff_sampler, tex_coords);
+struct Lights {
+    lights: array<Light, MAX_LIGHTS>,
+    count: u32,
+}
 
-    let N = normalize(in.normal);
-    // let light_dir = normalize(light_pos - in.pos);
+@group(2) @binding(1)
+var<uniform> lights: Lights;
+
+const MAX_SHADOW_MAPS = 8;
+
+// matches shadow::ShadowUniform, directional lights use cascade_count layers starting at their shadow_index
+struct Shadows {
+    view_proj: array<mat4x4<f32>, MAX_SHADOW_MAPS>,
+    cascade_splits: vec4<f32>,      // far view distance of each cascade
+    view_forward: vec4<f32>,
+    depth_bias: f32,
+    normal_bias: f32,
+    pcf_radius: i32,
+    texel_size: f32,
+    cascade_count: u32,
+    debug_cascades: u32,
+    _padding: vec2<u32>,
+}
 
-    // var diff = max(dot(N, light_dir), 0.0);
-    var diff = 0.0;
+@group(2) @binding(2)
+var shadow_maps: texture_depth_2d_array;
+
+@group(2) @binding(3)
+var shadow_sampler: sampler_comparison;
+
+@group(2) @binding(4)
+var<uniform> shadows: Shadows;
+
+// matches probes::ProbeUniform
+struct Probes {
+    previous_camera: CameraUniform,
+    num_samples: u32,
+    probe_density: f32,
+    sample_radius: f32,
+    ray_offset: f32,
+    history_weight: f32,
+    occluder_count: u32,
+    frame: u32,
+    _padding: u32,
+}
 
-    const PROBE_DENSITY = 0.25;
-    const STOCHASTIC_SAMPLE_RADIUS = 0.0;
-    // const STOCHASTIC_SAMPLE_RADIUS = 0.0;
-    const NUM_LIGHTS = 5;
-    const NUM_SAMPLES = 100;
-    const PI = 3.14159;
+// matches probes::OccluderRaw, an oriented box with its half extent along each axis in w
+struct Occluder {
+    center: vec4<f32>,
+    axes: array<vec4<f32>, 3>,
+}
+
+@group(2) @binding(5)
+var<uniform> probes: Probes;
 
-    let interval = 2 * PI / NUM_LIGHTS;
+@group(2) @binding(6)
+var<storage, read> occluders: array<Occluder>;
 
+// last frame's probe lighting, rgb lighting and the view distance it was shaded at in alpha
+@group(2) @binding(7)
+var probe_history: texture_2d<f32>;
 
-    // for(var l = 0; l < NUM_LIGHTS; l++) {
-        // let i = 1;
-    for(var i = 0; i < NUM_SAMPLES; i++) {
-        var offset = STOCHASTIC_SAMPLE_RADIUS * vec3<f32>(rng(time * in.pos.x), rng(time * in.pos.y), rng(time * in.pos.z)) - (STOCHASTIC_SAMPLE_RADIUS / 2.0);
+// matches debug_view::DebugUniform, only read by fs_debug
+struct DebugView {
+    view: u32,
+    depth_near: f32,
+    depth_far: f32,
+    _padding: u32,
+}
 
-        var shadowray_pos = in.pos + N * 0.001 + offset;
-        shadowray_pos /= PROBE_DENSITY;
+@group(2) @binding(8)
+var<uniform> debug_view: DebugView;
 
-        // X
-        var fract = fract(shadowray_pos.x);
-        var rng = rng(time * in.pos.x);
-        if rng > fract {
-            shadowray_pos.x = floor(shadowray_pos.x);
-        } else {
-            shadowray_pos.x = ceil(shadowray_pos.x);
+// image based lighting baked by ibl.rs
+struct Environment {
+    intensity: f32,
+    max_lod: f32,
+}
+
+@group(3) @binding(0)
+var irradiance_map: texture_cube<f32>;
+
+@group(3) @binding(1)
+var prefiltered_map: texture_cube<f32>;
+
+// split sum scale and bias, indexed by n_dot_v and roughness
+@group(3) @binding(2)
+var brdf_lut: texture_2d<f32>;
+
+@group(3) @binding(3)
+var environment_sampler: sampler;
+
+@group(3) @binding(4)
+var<uniform> environment: Environment;
+
+const PI = 3.14159265359;
+const HALF_MAX = 65504.0;
+
+struct Surface {
+    albedo: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+}
+
+// unit vector from pos towards the light
+fn light_dir(light: Light, pos: vec3<f32>) -> vec3<f32> {
+    if light.kind == LIGHT_DIRECTIONAL {
+        return -light.direction;
+    }
+    return normalize(light.position - pos);
+}
+
+// inverse square with a smooth window so the light reaches exactly zero at its range
+fn attenuation(light: Light, pos: vec3<f32>) -> f32 {
+    if light.kind == LIGHT_DIRECTIONAL {
+        return 1.0;
+    }
+
+    let d = distance(light.position, pos);
+    let window = saturate(1.0 - pow(d / light.range, 4.0));
+    var falloff = window * window / max(d * d, 0.0001);
+
+    if light.kind == LIGHT_SPOT {
+        let cos_angle = dot(normalize(pos - light.position), light.direction);
+        falloff *= smoothstep(light.outer_cos, light.inner_cos, cos_angle);
+    }
+
+    return falloff;
+}
+
+// the first cascade reaching past pos along the view direction, -1 beyond the last one
+fn cascade_index(pos: vec3<f32>) -> i32 {
+    let depth = dot(pos - camera.view_pos.xyz, shadows.view_forward.xyz);
+    for (var i = 0; i < i32(min(shadows.cascade_count, 4u)); i++) {
+        if depth < shadows.cascade_splits[i] {
+            return i;
         }
+    }
+    return -1;
+}
 
-        // Y
-        fract = fract(shadowray_pos.y);
-        rng = rng(time * in.pos.y);
-        if rng > fract {
-            shadowray_pos.y = floor(shadowray_pos.y);
-        } else {
-            shadowray_pos.y = ceil(shadowray_pos.y);
+// fraction of the light reaching pos, averaged over a (2r + 1)^2 pcf kernel
+fn shadow_visibility(index: i32, pos: vec3<f32>, N: vec3<f32>) -> f32 {
+    let light_clip = shadows.view_proj[index] * vec4<f32>(pos + N * shadows.normal_bias, 1.0);
+    let ndc = light_clip.xyz / light_clip.w;
+    let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+
+    // outside the light's frustum counts as lit
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    let depth = ndc.z - shadows.depth_bias;
+    let radius = clamp(shadows.pcf_radius, 0, 4);
+
+    var visibility = 0.0;
+    for (var x = -radius; x <= radius; x++) {
+        for (var y = -radius; y <= radius; y++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * shadows.texel_size;
+            visibility += textureSampleCompareLevel(shadow_maps, shadow_sampler, uv + offset, index, depth);
         }
+    }
 
-        // Z
-        fract = fract(shadowray_pos.z);
-        rng = rng(time * in.pos.z);
-        if rng > fract {
-            shadowray_pos.z = floor(shadowray_pos.z);
-        } else {
-            shadowray_pos.z = ceil(shadowray_pos.z);
+    let taps = f32((2 * radius + 1) * (2 * radius + 1));
+    return visibility / taps;
+}
+
+// trowbridge-reitz normal distribution, alpha is roughness squared
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// smith masking-shadowing with the schlick-ggx approximation for direct light
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l;
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// rough surfaces get less of a grazing angle boost from the environment
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    return f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// diffuse from the irradiance map plus the split sum specular from the prefiltered map and brdf lut
+fn ambient_ibl(N: vec3<f32>, V: vec3<f32>, surface: Surface) -> vec3<f32> {
+    let n_dot_v = max(dot(N, V), 1e-4);
+    let f0 = mix(vec3<f32>(0.04), surface.albedo, surface.metallic);
+    let F = fresnel_schlick_roughness(n_dot_v, f0, surface.roughness);
+
+    let irradiance = textureSampleLevel(irradiance_map, environment_sampler, N, 0.0).rgb;
+    let diffuse = (1.0 - F) * (1.0 - surface.metallic) * irradiance * surface.albedo;
+
+    let R = reflect(-V, N);
+    let prefiltered = textureSampleLevel(prefiltered_map, environment_sampler, R, surface.roughness * environment.max_lod).rgb;
+    let brdf = textureSampleLevel(brdf_lut, environment_sampler, vec2<f32>(n_dot_v, surface.roughness), 0.0).rg;
+    let specular = prefiltered * (F * brdf.x + brdf.y);
+
+    return (diffuse + specular) * environment.intensity;
+}
+
+// radiance reflected towards V from one light, lambert diffuse plus a cook-torrance specular lobe
+fn cook_torrance(light: Light, pos: vec3<f32>, N: vec3<f32>, V: vec3<f32>, surface: Surface) -> vec3<f32> {
+    let L = light_dir(light, pos);
+    let H = normalize(L + V);
+
+    let n_dot_l = max(dot(N, L), 0.0);
+    if n_dot_l <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+    let n_dot_v = max(dot(N, V), 1e-4);
+    let n_dot_h = max(dot(N, H), 0.0);
+
+    // dielectrics reflect about 4% head on, metals tint the reflection with their albedo
+    let f0 = mix(vec3<f32>(0.04), surface.albedo, surface.metallic);
+    let F = fresnel_schlick(max(dot(H, V), 0.0), f0);
+    let D = distribution_ggx(n_dot_h, surface.roughness);
+    let G = geometry_smith(n_dot_v, n_dot_l, surface.roughness);
+
+    let specular = D * G * F / (4.0 * n_dot_v * n_dot_l + 1e-4);
+    let diffuse = (1.0 - F) * (1.0 - surface.metallic) * surface.albedo / PI;
+
+    let radiance = light.color * light.intensity * attenuation(light, pos);
+    return (diffuse + specular) * radiance * n_dot_l;
+}
+
+// tangent frame from screen space derivatives, so meshes don't need tangents for normal mapping
+// the geometric normal bent by the material's normal map
+fn shading_normal(geometric_normal: vec3<f32>, pos: vec3<f32>, tex_coords: vec2<f32>) -> vec3<f32> {
+    // z is rebuilt from xy so two channel normal maps (bc5, eac rg11) shade the same as rgb ones
+    let normal_xy = textureSample(normal_tex, normal_sampler, tex_coords).xy * 2.0 - 1.0;
+    let normal_z = sqrt(max(1.0 - dot(normal_xy, normal_xy), 0.0));
+    let tangent_normal = vec3<f32>(normal_xy * material.normal_scale, normal_z);
+    return perturb_normal(geometric_normal, pos, tex_coords, normalize(tangent_normal));
+}
+
+fn perturb_normal(N: vec3<f32>, pos: vec3<f32>, uv: vec2<f32>, tangent_normal: vec3<f32>) -> vec3<f32> {
+    let dp1 = dpdx(pos);
+    let dp2 = dpdy(pos);
+    let duv1 = dpdx(uv);
+    let duv2 = dpdy(uv);
+
+    let dp2perp = cross(dp2, N);
+    let dp1perp = cross(N, dp1);
+    let T = dp2perp * duv1.x + dp1perp * duv2.x;
+    let B = dp2perp * duv1.y + dp1perp * duv2.y;
+
+    let inv_max = inverseSqrt(max(max(dot(T, T), dot(B, B)), 1e-12));
+    return normalize(mat3x3<f32>(T * inv_max, B * inv_max, N) * tangent_normal);
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) probe_history: vec4<f32>,
+    @location(2) motion: vec2<f32>,
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    var tex_coords = in.tex_coords;
+    tex_coords.y = 1.0 - tex_coords.y;
+
+    let base_color = textureSample(base_color_tex, base_color_sampler, tex_coords) * material.base_color * vec4<f32>(in.color, 1.0);
+    let metallic_roughness = textureSample(metallic_roughness_tex, metallic_roughness_sampler, tex_coords);
+    let occlusion_sample = textureSample(occlusion_tex, occlusion_sampler, tex_coords).r;
+    let emissive = textureSample(emissive_tex, emissive_sampler, tex_coords).rgb * material.emissive;
+
+    let geometric_normal = normalize(in.normal);
+    let N = shading_normal(geometric_normal, in.pos, tex_coords);
+    let V = normalize(camera.view_pos.xyz - in.pos);
+    let albedo = base_color.rgb;
+    let occlusion = mix(1.0, occlusion_sample, material.occlusion_strength);
+
+    // roughness is clamped so the ggx lobe of a perfect mirror doesn't collapse to nothing under point lights
+    let surface = Surface(albedo, saturate(material.metallic * metallic_roughness.b), clamp(material.roughness * metallic_roughness.g, 0.045, 1.0));
+
+    let num_lights = i32(min(lights.count, u32(MAX_LIGHTS)));
+
+    // lights with a shadow map are shaded exactly, the probes below only handle the rest
+    let cascade = cascade_index(in.pos);
+    var shadowed = vec3<f32>(0.0);
+    for (var l = 0; l < num_lights; l++) {
+        let light = lights.lights[l];
+        if light.shadow_index < 0 {
+            continue;
         }
 
-        shadowray_pos *= PROBE_DENSITY;
+        var visibility = 1.0;
+        if light.kind != LIGHT_DIRECTIONAL {
+            visibility = shadow_visibility(light.shadow_index, in.pos, geometric_normal);
+        } else if cascade >= 0 {
+            visibility = shadow_visibility(light.shadow_index + cascade, in.pos, geometric_normal);
+        }
+        shadowed += cook_torrance(light, in.pos, N, V, surface) * visibility;
+    }
 
-        let l = i32(rng(time + (rng(shadowray_pos.x) + rng(shadowray_pos.y) * rng(shadowray_pos.z)) * f32(i + 4)) * NUM_LIGHTS);
+    var lighting = vec3<f32>(0.0);
+    let num_samples = i32(max(probes.num_samples, 1u));
+    let seed_time = time + f32(probes.frame % 64u) * 0.618034;
+
+    // each sample shades with one randomly picked light, and only counts if the probe can see it
+    for(var i = 0; i < num_samples && num_lights > 0; i++) {
+        let radius = probes.sample_radius;
+        var offset = radius * vec3<f32>(rng(seed_time * in.pos.x), rng(seed_time * in.pos.y), rng(seed_time * in.pos.z)) - (radius / 2.0);
+
+        var shadowray_pos = in.pos + geometric_normal * probes.ray_offset + offset;
+        shadowray_pos /= probes.probe_density;
+
+        // round each axis to a neighbouring grid point, with odds given by how close it is
+        for (var axis = 0; axis < 3; axis++) {
+            let fract = fract(shadowray_pos[axis]);
+            let rng = rng(seed_time * in.pos[axis] + f32(i));
+            if rng > fract {
+                shadowray_pos[axis] = floor(shadowray_pos[axis]);
+            } else {
+                shadowray_pos[axis] = ceil(shadowray_pos[axis]);
+            }
+        }
 
-        var light_pos = vec3<f32>(2 * cos(time + f32(l) * interval), 3.0, 2 * sin(time + f32(l) * interval)) * 4.0;
+        shadowray_pos *= probes.probe_density;
 
-        let shadowray_dir = normalize(light_pos - shadowray_pos);
+        let l = min(i32(rng(seed_time + (rng(shadowray_pos.x) + rng(shadowray_pos.y) * rng(shadowray_pos.z)) * f32(i + 4)) * f32(num_lights)), num_lights - 1);
+        let light = lights.lights[l];
+        if light.shadow_index >= 0 {
+            continue;
+        }
 
-        let ray = Ray(shadowray_pos, shadowray_dir);
-        let hitinfo = intersect_unit_cube(ray);
-        
-        if !(hitinfo.hit && hitinfo.t_near > 0.001) {
-            diff += 0.2;
+        // occluders past a point or spot light don't block it
+        var max_t = 1e10;
+        if light.kind != LIGHT_DIRECTIONAL {
+            max_t = distance(light.position, shadowray_pos);
+        }
+
+        let ray = Ray(shadowray_pos, light_dir(light, shadowray_pos));
+        if !occluded(ray, max_t) {
+            lighting += cook_torrance(light, in.pos, N, V, surface);
         }
     }
 
-    diff *= f32(NUM_LIGHTS) / f32(NUM_SAMPLES);
+    lighting *= f32(num_lights) / f32(num_samples);
+
+    // blend with last frame where this surface was on screen at about the same distance, anything else is a disocclusion
+    let view_distance = distance(camera.view_pos.xyz, in.pos);
+    if probes.history_weight > 0.0 {
+        let previous_clip = probes.previous_camera.view_proj * vec4<f32>(in.pos, 1.0);
+        let previous_ndc = previous_clip.xy / previous_clip.w;
+        let previous_uv = vec2<f32>(previous_ndc.x * 0.5 + 0.5, 0.5 - previous_ndc.y * 0.5);
+
+        if previous_clip.w > 0.0 && all(previous_uv >= vec2<f32>(0.0)) && all(previous_uv < vec2<f32>(1.0)) {
+            let size = vec2<f32>(textureDimensions(probe_history));
+            let history = textureLoad(probe_history, vec2<i32>(previous_uv * size), 0);
+            let previous_distance = distance(probes.previous_camera.view_pos.xyz, in.pos);
 
-    return vec4<f32>(tex_color.xyz * (0.1 + diff), 1.0);
+            if history.a > 0.0 && abs(history.a - previous_distance) < 0.02 * previous_distance + 0.01 {
+                lighting = mix(lighting, history.rgb, probes.history_weight);
+            }
+        }
+    }
+
+    var color = ambient_ibl(N, V, surface) * occlusion + shadowed + lighting + emissive;
+
+    // red, green, blue, yellow from the nearest cascade out
+    if shadows.debug_cascades != 0u && cascade >= 0 {
+        var cascade_colors = array<vec3<f32>, 4>(
+            vec3<f32>(1.0, 0.2, 0.2),
+            vec3<f32>(0.2, 1.0, 0.2),
+            vec3<f32>(0.2, 0.2, 1.0),
+            vec3<f32>(1.0, 1.0, 0.2),
+        );
+        color = mix(color, cascade_colors[cascade], 0.5);
+    }
+
+    // both targets are half float, a tight highlight past its largest value would be stored as inf and turn into
+    // nan in whatever filters it next, taa and the probe history would then keep it around for good
+    var out: FragmentOutput;
+    out.color = vec4<f32>(min(color, vec3<f32>(HALF_MAX)), 1.0);
+    out.probe_history = vec4<f32>(min(lighting, vec3<f32>(HALF_MAX)), view_distance);
+    out.motion = motion_vector(in.pos);
+    return out;
+}
+
+// how far this point moved on screen since last frame, in uv. only the camera moves, instances keep no previous transform
+fn motion_vector(pos: vec3<f32>) -> vec2<f32> {
+    let current = camera.unjittered_view_proj * vec4<f32>(pos, 1.0);
+    let previous = camera.previous_view_proj * vec4<f32>(pos, 1.0);
+    return (current.xy / current.w - previous.xy / previous.w) * vec2<f32>(0.5, -0.5);
+}
+
+// matches debug_view::DebugView::index
+const DEBUG_ALBEDO = 1u;
+const DEBUG_NORMALS = 2u;
+const DEBUG_UVS = 3u;
+const DEBUG_DEPTH = 4u;
+const DEBUG_OVERDRAW = 5u;
+
+// the debug views in place of fs_main, unlit and drawn without taa or post processing. everything but albedo is
+// meant to be seen as is, so it's written linear for the srgb output to encode back
+@fragment
+fn fs_debug(in: VertexOutput) -> FragmentOutput {
+    var tex_coords = in.tex_coords;
+    tex_coords.y = 1.0 - tex_coords.y;
+
+    var color = vec3<f32>(0.0);
+    switch debug_view.view {
+        case DEBUG_ALBEDO: {
+            color = (textureSample(base_color_tex, base_color_sampler, tex_coords) * material.base_color).rgb * in.color;
+        }
+        case DEBUG_NORMALS: {
+            color = srgb_to_linear(shading_normal(normalize(in.normal), in.pos, tex_coords) * 0.5 + 0.5);
+        }
+        case DEBUG_UVS: {
+            color = srgb_to_linear(vec3<f32>(fract(in.tex_coords), 0.0));
+        }
+        case DEBUG_DEPTH: {
+            let depth = dot(in.pos - camera.view_pos.xyz, shadows.view_forward.xyz);
+            color = srgb_to_linear(vec3<f32>(saturate((depth - debug_view.depth_near) / (debug_view.depth_far - debug_view.depth_near))));
+        }
+        // one per fragment, blended additively and turned into a heatmap by debug_view.wgsl
+        case DEBUG_OVERDRAW: {
+            color = vec3<f32>(1.0, 0.0, 0.0);
+        }
+        // the wireframe with native lines
+        default: {
+            color = vec3<f32>(0.9, 0.9, 0.9);
+        }
+    }
+
+    var out: FragmentOutput;
+    out.color = vec4<f32>(color, 1.0);
+    out.probe_history = vec4<f32>(0.0);
+    out.motion = vec2<f32>(0.0);
+    return out;
 }
 
-fn convert_color(srgb_color: vec4<f32>) -> vec4<f32> {
-    var rgb_color: vec4<f32> = ((srgb_color + 0.055) / 1.055);
-    rgb_color.r = pow(rgb_color.r, 2.4);
-    rgb_color.g = pow(rgb_color.g, 2.4);
-    rgb_color.b = pow(rgb_color.b, 2.4);
-    return rgb_color;
+fn srgb_to_linear(srgb_color: vec3<f32>) -> vec3<f32> {
+    let low = srgb_color / 12.92;
+    let high = pow((srgb_color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, srgb_color <= vec3<f32>(0.04045));
 }
 
 struct Ray {
@@ -163,22 +589,27 @@ struct HitInfo {
     t_far: f32,
 };
 
-fn intersect_unit_cube(ray: Ray) -> HitInfo {
+fn intersect_occluder(ray: Ray, occluder: Occluder) -> HitInfo {
     var tmin = -1e10;
     var tmax =  1e10;
 
-    let bounds_min = vec3<f32>(-0.5, -0.5, -0.5);
-    let bounds_max = vec3<f32>( 0.5,  0.5,  0.5);
+    // slab test in the box's own frame
+    let origin = ray.origin - occluder.center.xyz;
 
     for (var i = 0; i < 3; i = i + 1) {
-        if (abs(ray.dir[i]) < 1e-6) {
-            if (ray.origin[i] < bounds_min[i] || ray.origin[i] > bounds_max[i]) {
+        let axis = occluder.axes[i];
+        let o = dot(origin, axis.xyz);
+        let d = dot(ray.dir, axis.xyz);
+        let half_extent = axis.w;
+
+        if (abs(d) < 1e-6) {
+            if (o < -half_extent || o > half_extent) {
                 return HitInfo(false, 0.0, 0.0);
             }
         } else {
-            let invD = 1.0 / ray.dir[i];
-            var t0 = (bounds_min[i] - ray.origin[i]) * invD;
-            var t1 = (bounds_max[i] - ray.origin[i]) * invD;
+            let invD = 1.0 / d;
+            var t0 = (-half_extent - o) * invD;
+            var t1 = (half_extent - o) * invD;
 
             if (t0 > t1) {
                 let tmp = t0;
@@ -197,3 +628,15 @@ fn intersect_unit_cube(ray: Ray) -> HitInfo {
 
     return HitInfo(true, tmin, tmax);
 }
+
+// rays starting inside a box pass through it, like the unit cube test this replaced
+fn occluded(ray: Ray, max_t: f32) -> bool {
+    let count = min(probes.occluder_count, arrayLength(&occluders));
+    for (var i = 0u; i < count; i++) {
+        let hit = intersect_occluder(ray, occluders[i]);
+        if hit.hit && hit.t_near > probes.ray_offset && hit.t_near < max_t {
+            return true;
+        }
+    }
+    return false;
+}
@@ -0,0 +1,335 @@
+use nalgebra::*;
+use wgpu::*;
+
+use crate::{camera::{Camera, Projection}, instance::InstanceRaw, light::{Light, LightKind}, model::Aabb, scene::{DrawScene, Scene}, shader_structs::Vertex, texture::Texture};
+
+// layers in the shadow map array, must match the array size in shader.wgsl
+pub const MAX_SHADOW_MAPS: usize = 8;
+// must match the size of cascade_splits in shader.wgsl
+pub const MAX_CASCADES: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowSettings {
+    pub resolution: u32,
+    pub depth_bias: f32,    // subtracted from the receiver's depth before comparing
+    pub normal_bias: f32,   // receiver pushed out along its normal, in world units
+    pub slope_bias: f32,    // rasterizer slope scaled bias in the shadow pass, fixed once the pipeline exists
+    pub pcf_radius: i32,    // (2r + 1)^2 comparison taps
+
+    // directional lights only, each cascade takes a layer of the shadow map array
+    pub cascades: u32,
+    pub cascade_lambda: f32,    // splits the depth range evenly at 0, logarithmically at 1, blends in between
+    pub max_distance: f32,  // cascades stop here even when the camera's far plane is further or infinite
+    pub debug_cascades: bool
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            depth_bias: 0.0005,
+            normal_bias: 0.02,
+            slope_bias: 2.0,
+            pcf_radius: 1,
+            cascades: 4,
+            cascade_lambda: 0.75,
+            max_distance: 60.0,
+            debug_cascades: false
+        }
+    }
+}
+
+// far distance of each cascade, the last one is always `far`
+fn cascade_splits(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (1..=count).map(|i| {
+        let t = i as f32 / count as f32;
+        let uniform = near + (far - near) * t;
+        let logarithmic = near * (far / near).powf(t);
+        uniform + (logarithmic - uniform) * lambda
+    }).collect()
+}
+
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowUniform {
+    view_proj: [[[f32; 4]; 4]; MAX_SHADOW_MAPS],
+    cascade_splits: [f32; MAX_CASCADES],
+    view_forward: [f32; 4],
+    depth_bias: f32,
+    normal_bias: f32,
+    pcf_radius: i32,
+    texel_size: f32,
+    cascade_count: u32,
+    debug_cascades: u32,
+    _padding: [u32; 2]
+}
+
+
+pub struct ShadowMaps {
+    pub settings: ShadowSettings,
+    pub texture: Texture,
+    pub uniform_buffer: Buffer,
+    layer_views: Vec<TextureView>,
+    pipeline: RenderPipeline,
+
+    // one light matrix per layer, each at its own dynamic offset
+    pass_buffer: Buffer,
+    pass_bind_group: BindGroup,
+    pass_stride: u32,
+
+    view_projs: Vec<Matrix4<f32>>
+}
+
+
+impl ShadowMaps {
+    pub fn new(device: &Device, settings: ShadowSettings) -> Self {
+        let texture = Texture::create_depth_texture_array(device, settings.resolution, MAX_SHADOW_MAPS as u32, CompareFunction::LessEqual, "Shadow Maps");
+        let layer_views = (0..MAX_SHADOW_MAPS as u32).map(|layer| {
+            texture.texture.create_view(&TextureViewDescriptor {
+                label: Some("Shadow Map Layer"),
+                dimension: Some(TextureViewDimension::D2),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            })
+        }).collect();
+
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Shadow Uniform Buffer"),
+            size: std::mem::size_of::<ShadowUniform>() as BufferAddress,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let pass_stride = device.limits().min_uniform_buffer_offset_alignment.max(std::mem::size_of::<[[f32; 4]; 4]>() as u32);
+        let pass_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Shadow Pass Buffer"),
+            size: (pass_stride as usize * MAX_SHADOW_MAPS) as BufferAddress,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let pass_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Shadow Pass Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    count: None,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: BufferSize::new(std::mem::size_of::<[[f32; 4]; 4]>() as u64)
+                    }
+                }
+            ]
+        });
+
+        let pass_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Shadow Pass Bind Group"),
+            layout: &pass_bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: &pass_buffer,
+                        offset: 0,
+                        size: BufferSize::new(std::mem::size_of::<[[f32; 4]; 4]>() as u64)
+                    })
+                }
+            ]
+        });
+
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&pass_bind_group_layout],
+            push_constant_ranges: &[]
+        });
+
+        let shader = device.create_shader_module(include_wgsl!("shadow.wgsl"));
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Shadow Pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[Vertex::desc(), InstanceRaw::desc()]
+            },
+            fragment: None,
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                front_face: FrontFace::Ccw,
+                cull_mode: Some(Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::Less,
+                stencil: StencilState::default(),
+                bias: DepthBiasState {
+                    constant: 2,
+                    slope_scale: settings.slope_bias,
+                    clamp: 0.0
+                }
+            }),
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None
+        });
+
+        Self {
+            settings,
+            texture,
+            uniform_buffer,
+            layer_views,
+            pipeline,
+            pass_buffer,
+            pass_bind_group,
+            pass_stride,
+            view_projs: Vec::new()
+        }
+    }
+
+    // hands out layers to shadow casting lights and uploads their matrices, returns each light's first layer or -1,
+    // a directional light owns `cascades` consecutive layers
+    pub fn update(&mut self, queue: &Queue, lights: &[Light], camera: &Camera, bounds: Option<Aabb>) -> Vec<i32> {
+        self.view_projs.clear();
+
+        let cascades = (self.settings.cascades as usize).clamp(1, MAX_CASCADES);
+        let near = camera.projection.znear;
+        let far = camera.projection.zfar.unwrap_or(f32::INFINITY).min(self.settings.max_distance).max(near * 2.0);
+        let splits = cascade_splits(near, far, cascades, self.settings.cascade_lambda);
+
+        let shadow_indices = lights.iter().map(|light| {
+            if !light.cast_shadows {
+                return -1;
+            }
+
+            let view_projs = match light.kind {
+                LightKind::Directional { direction } => {
+                    let Some(direction) = direction.try_normalize(1e-6) else { return -1 };
+                    let mut cascade_near = near;
+                    splits.iter().map(|&split| {
+                        let corners = camera.frustum_corners(cascade_near, split);
+                        cascade_near = split;
+                        cascade_view_proj(&direction, &corners, bounds.as_ref(), self.settings.resolution)
+                    }).collect()
+                },
+                _ => match spot_view_proj(light) {
+                    Some(view_proj) => vec![view_proj],
+                    None => return -1
+                }
+            };
+
+            if self.view_projs.len() + view_projs.len() > MAX_SHADOW_MAPS {
+                log::warn!("out of shadow map layers, a light is left unshadowed");
+                return -1;
+            }
+
+            let first = self.view_projs.len() as i32;
+            self.view_projs.extend(view_projs);
+            first
+        }).collect();
+
+        let mut cascade_splits = [f32::MAX; MAX_CASCADES];
+        cascade_splits[..cascades].copy_from_slice(&splits);
+
+        let mut uniform = ShadowUniform {
+            view_proj: [Matrix4::identity().into(); MAX_SHADOW_MAPS],
+            cascade_splits,
+            view_forward: camera.forward().push(0.0).into(),
+            depth_bias: self.settings.depth_bias,
+            normal_bias: self.settings.normal_bias,
+            pcf_radius: self.settings.pcf_radius,
+            texel_size: 1.0 / self.settings.resolution as f32,
+            cascade_count: cascades as u32,
+            debug_cascades: self.settings.debug_cascades as u32,
+            _padding: [0; 2]
+        };
+
+        for (i, view_proj) in self.view_projs.iter().enumerate() {
+            uniform.view_proj[i] = (*view_proj).into();
+            let matrix: [[f32; 4]; 4] = (*view_proj).into();
+            queue.write_buffer(&self.pass_buffer, (i as u32 * self.pass_stride) as BufferAddress, bytemuck::cast_slice(&[matrix]));
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        shadow_indices
+    }
+
+    pub fn render(&self, encoder: &mut CommandEncoder, scene: &Scene) {
+        for (i, view) in self.layer_views.iter().enumerate().take(self.view_projs.len()) {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(Operations {
+                        load: LoadOp::Clear(1.0),
+                        store: StoreOp::Store
+                    }),
+                    stencil_ops: None
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None
+            });
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &self.pass_bind_group, &[i as u32 * self.pass_stride]);
+            render_pass.draw_scene_geometry(scene);
+        }
+    }
+}
+
+
+// an ortho box around the bounding sphere of one cascade's slice of the camera frustum. the sphere's size doesn't
+// change as the camera turns, and snapping its center to whole texels keeps edges from shimmering as it moves
+fn cascade_view_proj(direction: &Vector3<f32>, corners: &[Point3<f32>; 8], bounds: Option<&Aabb>, resolution: u32) -> Matrix4<f32> {
+    let center = Point3::from(corners.iter().map(|c| c.coords).sum::<Vector3<f32>>() / 8.0);
+    let radius = corners.iter().map(|c| (c - center).norm()).fold(0.0, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    // rotation only light space, the translation is added back after snapping
+    let up = stable_up(direction);
+    let light_view = Matrix4::look_at_rh(&Point3::origin(), &Point3::from(*direction), &up);
+    let mut light_center = light_view.transform_point(&center);
+
+    let texel = 2.0 * radius / resolution as f32;
+    light_center.x = (light_center.x / texel).floor() * texel;
+    light_center.y = (light_center.y / texel).floor() * texel;
+
+    // casters between the light and the cascade still need to land in the map, pull the near plane back to the scene
+    let top = bounds
+        .map(|b| b.corners().iter().map(|c| light_view.transform_point(c).z).fold(f32::MIN, f32::max))
+        .unwrap_or(f32::MIN)
+        .max(light_center.z + radius) + 1.0;
+
+    let eye = light_view.try_inverse().unwrap_or_else(Matrix4::identity).transform_point(&Point3::new(light_center.x, light_center.y, top));
+    let view = Matrix4::look_at_rh(&eye, &(eye + direction), &up);
+    let projection = Projection { fovy_degrees: 0.0, znear: 0.0, zfar: Some(top - (light_center.z - radius)), reverse_z: false };
+
+    projection.orthographic_matrix(radius, radius) * view
+}
+
+// a perspective frustum over the spot's cone
+fn spot_view_proj(light: &Light) -> Option<Matrix4<f32>> {
+    let LightKind::Spot { position, direction, range, outer_angle, .. } = light.kind else { return None };
+
+    let direction = direction.try_normalize(1e-6)?;
+    let view = Matrix4::look_at_rh(&position, &(position + direction), &stable_up(&direction));
+    let projection = Projection {
+        fovy_degrees: (2.0 * outer_angle).to_degrees().clamp(1.0, 170.0),
+        znear: 0.05,
+        zfar: Some(range),
+        reverse_z: false
+    };
+
+    Some(projection.perspective_matrix(1.0) * view)
+}
+
+fn stable_up(direction: &Vector3<f32>) -> Vector3<f32> {
+    if direction.y.abs() > 0.99 { Vector3::z() } else { Vector3::y() }
+}
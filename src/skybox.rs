@@ -0,0 +1,240 @@
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, *};
+
+use crate::{camera::Camera, texture::Texture};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyboxUniform {
+    forward: [f32; 4],
+    right: [f32; 4],
+    up: [f32; 4],
+    depth: f32,
+    _padding: [f32; 3],
+    previous_forward: [f32; 4],
+    previous_right: [f32; 4],
+    previous_up: [f32; 4]
+}
+
+// a cubemap drawn behind everything, it always uses a perspective ray even when the camera is orthographic
+pub struct Skybox {
+    pipeline: RenderPipeline,
+    uniform_buffer: Buffer,
+    bind_group: BindGroup,
+    previous: Option<SkyboxUniform>,    // last frame's view, the motion vectors point back to it
+
+    // kept to rebuild the pipeline when the main pass changes sample count
+    shader: ShaderModule,
+    layout: PipelineLayout,
+    formats: [TextureFormat; 3],
+    depth_compare: CompareFunction
+}
+
+
+impl Skybox {
+    // the main pass targets in location order, color, probe history and motion vectors. the probe history is left alone
+    pub fn new(device: &Device, cubemap: &Texture, formats: [TextureFormat; 3], camera: &Camera, sample_count: u32) -> Self {
+        let shader = device.create_shader_module(include_wgsl!("skybox.wgsl"));
+
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Skybox Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[skybox_uniform(camera, None)]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Skybox Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::Cube,
+                        multisampled: false
+                    },
+                    count: None
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None
+                    },
+                    count: None
+                }
+            ]
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Skybox Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&cubemap.view)
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&cubemap.sampler)
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding()
+                }
+            ]
+        });
+
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Skybox Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[]
+        });
+
+        let depth_compare = camera.projection.depth_sample_compare();
+
+        Self {
+            pipeline: create_pipeline(device, &layout, &shader, formats, depth_compare, sample_count),
+            uniform_buffer,
+            bind_group,
+            previous: None,
+            shader,
+            layout,
+            formats,
+            depth_compare
+        }
+    }
+
+    pub fn set_sample_count(&mut self, device: &Device, sample_count: u32) {
+        self.pipeline = create_pipeline(device, &self.layout, &self.shader, self.formats, self.depth_compare, sample_count);
+    }
+
+    // a single equirectangular panorama, or six faces in +x -x +y -y +z -z order
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_cubemap(device: &Device, queue: &Queue, paths: &[String]) -> anyhow::Result<Texture> {
+        use anyhow::Context;
+
+        let open = |path: &String| image::open(path).with_context(|| format!("loading skybox image {path}"));
+
+        match paths {
+            [panorama] => {
+                // baked on the gpu like the environment, a quarter of the panorama's width per face
+                let panorama = open(panorama)?;
+                let hdr = matches!(panorama.color(), image::ColorType::Rgb32F | image::ColorType::Rgba32F);
+                let mut image = panorama.to_rgba32f();
+                if !hdr {
+                    for pixel in image.pixels_mut() {
+                        for c in &mut pixel.0[..3] {
+                            *c = srgb_to_linear(*c);
+                        }
+                    }
+                }
+                let size = (image.width() / 4).clamp(1, device.limits().max_texture_dimension_2d);
+                Ok(crate::ibl::cubemap_from_equirect(device, queue, &image, size, "Skybox Cubemap"))
+            },
+            [px, nx, py, ny, pz, nz] => {
+                let faces = [open(px)?, open(nx)?, open(py)?, open(ny)?, open(pz)?, open(nz)?];
+                Texture::cubemap_from_images(device, queue, &faces, "Skybox Cubemap")
+            },
+            _ => anyhow::bail!("a skybox is one equirectangular panorama or six cube faces, got {} images", paths.len())
+        }
+    }
+
+    pub fn update(&mut self, queue: &Queue, camera: &Camera) {
+        let uniform = skybox_uniform(camera, self.previous.as_ref());
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        self.previous = Some(uniform);
+    }
+
+    pub fn draw(&self, render_pass: &mut RenderPass<'_>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+
+fn create_pipeline(device: &Device, layout: &PipelineLayout, shader: &ShaderModule, [color_format, history_format, motion_format]: [TextureFormat; 3], depth_compare: CompareFunction, sample_count: u32) -> RenderPipeline {
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("Skybox Pipeline"),
+        layout: Some(layout),
+        vertex: VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            compilation_options: PipelineCompilationOptions::default(),
+            buffers: &[]
+        },
+        fragment: Some(FragmentState {
+            module: shader,
+            entry_point: Some("fs_main"),
+            compilation_options: PipelineCompilationOptions::default(),
+            targets: &[Some(ColorTargetState {
+                format: color_format,
+                blend: None,
+                write_mask: ColorWrites::ALL
+            }), Some(ColorTargetState {
+                format: history_format,
+                blend: None,
+                write_mask: ColorWrites::empty()
+            }), Some(ColorTargetState {
+                format: motion_format,
+                blend: None,
+                write_mask: ColorWrites::ALL
+            })]
+        }),
+        primitive: PrimitiveState::default(),
+        // the triangle sits exactly on the cleared depth, so equal has to pass
+        depth_stencil: Some(DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare,
+            stencil: StencilState::default(),
+            bias: DepthBiasState::default()
+        }),
+        multisample: MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+        cache: None
+    })
+}
+
+// without a previous frame the view is its own previous one and the sky doesn't move
+fn skybox_uniform(camera: &Camera, previous: Option<&SkyboxUniform>) -> SkyboxUniform {
+    let forward = camera.forward();
+    let right = forward.cross(&camera.view_up()).normalize();
+    let up = right.cross(&forward);
+
+    let half_height = (camera.projection.fovy_radians() / 2.0).tan();
+    let half_width = half_height * camera.aspect_ratio;
+
+    let forward = forward.push(0.0).into();
+    let right = (right * half_width).push(0.0).into();
+    let up = (up * half_height).push(0.0).into();
+
+    SkyboxUniform {
+        forward,
+        right,
+        up,
+        depth: camera.projection.depth_clear_value(),
+        _padding: [0.0; 3],
+        previous_forward: previous.map_or(forward, |p| p.forward),
+        previous_right: previous.map_or(right, |p| p.right),
+        previous_up: previous.map_or(up, |p| p.up)
+    }
+}
+
+
+// 8 bit panoramas are stored in srgb, the bake works in linear
+#[cfg(not(target_arch = "wasm32"))]
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
@@ -0,0 +1,59 @@
+// drawn after the scene as a fullscreen triangle at the far plane, so only pixels the scene left empty pass the depth test
+
+struct SkyboxUniform {
+    forward: vec4<f32>,
+    right: vec4<f32>,   // scaled by the half width of the view at distance 1
+    up: vec4<f32>,      // scaled by the half height
+    depth: f32,         // far plane depth, 1 or 0 with reverse z
+    previous_forward: vec4<f32>,    // last frame's view, for motion vectors
+    previous_right: vec4<f32>,
+    previous_up: vec4<f32>,
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @location(2) motion: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var sky: texture_cube<f32>;
+
+@group(0) @binding(1)
+var sky_sampler: sampler;
+
+@group(0) @binding(2)
+var<uniform> skybox: SkyboxUniform;
+
+// fullscreen.wgsl's triangle moved to the far plane, which the shared shader can't know
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, skybox.depth, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    let dir = skybox.forward.xyz + in.ndc.x * skybox.right.xyz + in.ndc.y * skybox.up.xyz;
+
+    // where the same direction was on screen last frame, the basis vectors are orthogonal so this undoes the sum above
+    let previous = skybox.previous_forward.xyz;
+    let right = skybox.previous_right.xyz;
+    let up = skybox.previous_up.xyz;
+    let depth = dot(dir, previous);
+    let previous_ndc = vec2<f32>(dot(dir, right) / dot(right, right), dot(dir, up) / dot(up, up)) / depth;
+
+    var out: FragmentOutput;
+    out.color = vec4<f32>(textureSample(sky, sky_sampler, normalize(dir)).rgb, 1.0);
+    out.motion = select(vec2<f32>(0.0), (in.ndc - previous_ndc) * vec2<f32>(0.5, -0.5), depth > 0.0);
+    return out;
+}
@@ -0,0 +1,206 @@
+use nalgebra::Vector2;
+use wgpu::*;
+
+use crate::{postprocess::FullscreenPass, texture::Texture, tonemap::Tonemap};
+
+#[derive(Clone, Copy, Debug)]
+pub struct TaaSettings {
+    pub enabled: bool,
+    pub feedback: f32,          // share of the history kept each frame, higher is smoother and slower to follow changes
+    pub jitter_samples: u32     // points of the halton sequence the projection cycles through
+}
+
+impl Default for TaaSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            feedback: 0.9,
+            jitter_samples: 8
+        }
+    }
+}
+
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TaaUniform {
+    feedback: f32,      // 0 whenever the history can't be trusted
+    _padding: [f32; 3]
+}
+
+
+// temporal antialiasing. the projection is jittered by a different sub-pixel offset every frame and each frame is
+// blended into a history reprojected with the motion vectors the main pass writes, which also averages out the
+// stochastic probe shadows. the history is clamped to the colors around each pixel so disocclusions don't ghost
+pub struct Taa {
+    pub settings: TaaSettings,
+    pub motion: Texture,        // uv offsets from where each pixel was last frame, the main pass's third target
+    resolve: FullscreenPass,
+    uniform_buffer: Buffer,
+
+    input_layout: BindGroupLayout,
+    params_layout: BindGroupLayout,
+    sampler: Sampler,
+    input_bind_group: BindGroup,        // the hdr target
+
+    // ping-pong pair like the probe history, frame n resolves into history[n % 2] and reads the other one
+    history: [Texture; 2],
+    params_bind_groups: [BindGroup; 2],
+    frame: u32,
+    history_valid: bool
+}
+
+
+impl Taa {
+    pub const MOTION_FORMAT: TextureFormat = TextureFormat::Rg16Float;
+
+    pub fn new(device: &Device, vertex_shader: &ShaderModule, hdr: &Texture, settings: TaaSettings) -> Self {
+        let shader = device.create_shader_module(include_wgsl!("taa.wgsl"));
+        let input_layout = FullscreenPass::input_layout(device);
+        let sampler = FullscreenPass::create_sampler(device);
+
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("TAA Uniform Buffer"),
+            size: std::mem::size_of::<TaaUniform>() as BufferAddress,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let texture_entry = |binding, filterable| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false
+            },
+            count: None
+        };
+
+        let params_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("TAA Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None
+                    },
+                    count: None
+                },
+                texture_entry(1, true),
+                texture_entry(2, false)
+            ]
+        });
+
+        let resolve = FullscreenPass::new(device, "TAA Resolve Pipeline", vertex_shader, (&shader, "fs_main"), &[&input_layout, &params_layout], Tonemap::HDR_FORMAT, None);
+
+        let size = hdr.texture.size();
+        let motion = Texture::create_render_target(device, size.width, size.height, Self::MOTION_FORMAT, "Motion Vectors");
+        let history = Self::create_history(device, size.width, size.height);
+        let params_bind_groups = Self::create_params_bind_groups(device, &params_layout, &uniform_buffer, &history, &motion);
+
+        Self {
+            settings,
+            motion,
+            resolve,
+            uniform_buffer,
+            input_bind_group: FullscreenPass::input_bind_group(device, &input_layout, &hdr.view, &sampler),
+            input_layout,
+            params_layout,
+            sampler,
+            history,
+            params_bind_groups,
+            frame: 0,
+            history_valid: false
+        }
+    }
+
+    fn create_history(device: &Device, width: u32, height: u32) -> [Texture; 2] {
+        [0, 1].map(|_| Texture::create_render_target(device, width, height, Tonemap::HDR_FORMAT, "TAA History"))
+    }
+
+    // one per history texture the resolve can read from
+    fn create_params_bind_groups(device: &Device, layout: &BindGroupLayout, uniform_buffer: &Buffer, history: &[Texture; 2], motion: &Texture) -> [BindGroup; 2] {
+        history.each_ref().map(|history| device.create_bind_group(&BindGroupDescriptor {
+            label: Some("TAA Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding()
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&history.view)
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&motion.view)
+                }
+            ]
+        }))
+    }
+
+    // call after the hdr target was recreated, the old history no longer lines up with the screen
+    pub fn resize(&mut self, device: &Device, hdr: &Texture) {
+        let size = hdr.texture.size();
+        self.motion = Texture::create_render_target(device, size.width, size.height, Self::MOTION_FORMAT, "Motion Vectors");
+        self.history = Self::create_history(device, size.width, size.height);
+        self.params_bind_groups = Self::create_params_bind_groups(device, &self.params_layout, &self.uniform_buffer, &self.history, &self.motion);
+        self.input_bind_group = FullscreenPass::input_bind_group(device, &self.input_layout, &hdr.view, &self.sampler);
+        self.history_valid = false;
+    }
+
+    // call once per frame before the camera uniform is built, it picks the jitter the frame is drawn with
+    pub fn update(&mut self, queue: &Queue) {
+        self.frame = self.frame.wrapping_add(1);
+
+        let uniform = TaaUniform {
+            feedback: if self.history_valid { self.settings.feedback.clamp(0.0, 0.98) } else { 0.0 },
+            _padding: [0.0; 3]
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        // stays false while taa is off, so turning it back on doesn't blend in a stale history
+        self.history_valid = self.settings.enabled;
+    }
+
+    // the offset to draw this frame with in ndc, within half a pixel of the center
+    pub fn jitter(&self, width: u32, height: u32) -> Vector2<f32> {
+        if !self.settings.enabled {
+            return Vector2::zeros();
+        }
+
+        let index = self.frame % self.settings.jitter_samples.max(1) + 1;
+        let offset = Vector2::new(halton(index, 2), halton(index, 3)) - Vector2::repeat(0.5);
+        offset.component_mul(&Vector2::new(2.0 / width as f32, 2.0 / height as f32))
+    }
+
+    // blends the hdr target with the history and writes the result back into it, run before anything reads the frame
+    pub fn resolve(&self, encoder: &mut CommandEncoder, hdr: &Texture) {
+        if !self.settings.enabled {
+            return;
+        }
+
+        let write = &self.history[self.frame as usize % 2];
+        let read = (self.frame as usize + 1) % 2;
+        self.resolve.draw(encoder, &write.view, &[&self.input_bind_group, &self.params_bind_groups[read]], 0.0);
+        encoder.copy_texture_to_texture(write.texture.as_image_copy(), hdr.texture.as_image_copy(), hdr.texture.size());
+    }
+}
+
+
+// the radical inverse of `index` in `base`, low discrepancy points in 0..1
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
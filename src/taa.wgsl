@@ -0,0 +1,113 @@
+// the taa resolve, this frame blended over the reprojected history. colors are compressed by their brightness
+// before clamping and blending so single bright samples can't flicker, then expanded again
+
+struct TaaUniform {
+    feedback: f32,  // share of the history kept, 0 without one
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var current: texture_2d<f32>;
+
+@group(0) @binding(1)
+var linear_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> taa: TaaUniform;
+
+@group(1) @binding(1)
+var history: texture_2d<f32>;
+
+@group(1) @binding(2)
+var motion: texture_2d<f32>;
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn compress(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + luminance(color));
+}
+
+fn expand(color: vec3<f32>) -> vec3<f32> {
+    return color / max(1.0 - luminance(color), 1e-4);
+}
+
+fn rgb_to_ycocg(color: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(
+        0.25 * color.r + 0.5 * color.g + 0.25 * color.b,
+        0.5 * color.r - 0.5 * color.b,
+        -0.25 * color.r + 0.5 * color.g - 0.25 * color.b,
+    );
+}
+
+fn ycocg_to_rgb(color: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(color.x + color.y - color.z, color.x + color.z, color.x - color.y - color.z);
+}
+
+// catmull-rom in five bilinear taps, keeps the history from blurring a little more every frame
+fn sample_history(uv: vec2<f32>) -> vec3<f32> {
+    let size = vec2<f32>(textureDimensions(history));
+    let position = uv * size;
+    let center = floor(position - 0.5) + 0.5;
+    let f = position - center;
+
+    let w0 = f * (-0.5 + f * (1.0 - 0.5 * f));
+    let w1 = 1.0 + f * f * (-2.5 + 1.5 * f);
+    let w2 = f * (0.5 + f * (2.0 - 1.5 * f));
+    let w3 = f * f * (-0.5 + 0.5 * f);
+    let w12 = w1 + w2;
+
+    let uv0 = (center - 1.0) / size;
+    let uv3 = (center + 2.0) / size;
+    let uv12 = (center + w2 / w12) / size;
+
+    let result = textureSampleLevel(history, linear_sampler, vec2<f32>(uv12.x, uv0.y), 0.0).rgb * w12.x * w0.y
+        + textureSampleLevel(history, linear_sampler, vec2<f32>(uv0.x, uv12.y), 0.0).rgb * w0.x * w12.y
+        + textureSampleLevel(history, linear_sampler, uv12, 0.0).rgb * w12.x * w12.y
+        + textureSampleLevel(history, linear_sampler, vec2<f32>(uv3.x, uv12.y), 0.0).rgb * w3.x * w12.y
+        + textureSampleLevel(history, linear_sampler, vec2<f32>(uv12.x, uv3.y), 0.0).rgb * w12.x * w3.y;
+    let weight = w12.x * w0.y + w0.x * w12.y + w12.x * w12.y + w3.x * w12.y + w12.x * w3.y;
+
+    return max(result / weight, vec3<f32>(0.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(current));
+    let pixel = vec2<i32>(in.clip_position.xy);
+
+    // the color box of the 3x3 neighborhood, and the longest motion in it so edges reproject with what's in front
+    var box_min = vec3<f32>(1e10);
+    var box_max = vec3<f32>(-1e10);
+    var center = vec3<f32>(0.0);
+    var velocity = vec2<f32>(0.0);
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let texel = clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), size - 1);
+            let color = rgb_to_ycocg(compress(textureLoad(current, texel, 0).rgb));
+            box_min = min(box_min, color);
+            box_max = max(box_max, color);
+            if x == 0 && y == 0 {
+                center = color;
+            }
+
+            let texel_motion = textureLoad(motion, texel, 0).xy;
+            if dot(texel_motion, texel_motion) > dot(velocity, velocity) {
+                velocity = texel_motion;
+            }
+        }
+    }
+
+    let previous_uv = in.uv - velocity;
+    if taa.feedback <= 0.0 || any(previous_uv < vec2<f32>(0.0)) || any(previous_uv > vec2<f32>(1.0)) {
+        return vec4<f32>(expand(ycocg_to_rgb(center)), 1.0);
+    }
+
+    let previous = clamp(rgb_to_ycocg(compress(sample_history(previous_uv))), box_min, box_max);
+    return vec4<f32>(expand(ycocg_to_rgb(mix(center, previous, taa.feedback))), 1.0);
+}
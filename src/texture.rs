@@ -1,6 +1,7 @@
 use wgpu::{wgt::SamplerDescriptor, *};
 use anyhow::Result;
-use image::GenericImageView;
+
+use crate::{block_decode, color, container, mipmap::{self, MipmapGenerator}};
 
 pub struct Texture {
     #[allow(unused)]
@@ -9,11 +10,88 @@ pub struct Texture {
     pub sampler: Sampler
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFilter {
+    Nearest,
+    Bilinear,   // linear within a mip, nearest between them
+    Trilinear
+}
+
+// how loaded images are sampled and whether they get a mip chain
+#[derive(Clone, Copy, Debug)]
+pub struct TextureSettings {
+    pub filter: TextureFilter,
+    pub anisotropy: u16,            // 1 turns it off, only used with trilinear filtering and wgpu drops it when the adapter can't
+    pub generate_mipmaps: bool,
+    pub use_precomputed_mips: bool, // take mips a container already has instead of generating them
+    pub address_modes: [AddressMode; 2]     // u then v, glTF samplers bring their own
+}
+
+impl Default for TextureSettings {
+    fn default() -> Self {
+        Self {
+            filter: TextureFilter::Trilinear,
+            anisotropy: 16,
+            generate_mipmaps: true,
+            use_precomputed_mips: true,
+            address_modes: [AddressMode::ClampToEdge; 2]
+        }
+    }
+}
+
+impl TextureSettings {
+    pub fn create_sampler(&self, device: &Device) -> Sampler {
+        let (filter, mipmap_filter) = match self.filter {
+            TextureFilter::Nearest => (FilterMode::Nearest, FilterMode::Nearest),
+            TextureFilter::Bilinear => (FilterMode::Linear, FilterMode::Nearest),
+            TextureFilter::Trilinear => (FilterMode::Linear, FilterMode::Linear)
+        };
+
+        device.create_sampler(&SamplerDescriptor {
+            address_mode_u: self.address_modes[0],
+            address_mode_v: self.address_modes[1],
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter,
+            // wgpu rejects anisotropy unless every filter is linear
+            anisotropy_clamp: if self.filter == TextureFilter::Trilinear { self.anisotropy.clamp(1, 16) } else { 1 },
+            ..Default::default()
+        })
+    }
+}
+
+
 impl Texture {
-    pub fn from_image(device: &Device, queue: &Queue, img: &image::DynamicImage, label: Option<&str>) -> Result<Self> {
-        let rgba = img.to_rgba8();
+    // `srgb` off for textures holding data rather than color (normals, metallic-roughness, occlusion)
+    fn from_image_with_srgb(device: &Device, queue: &Queue, mipmaps: &MipmapGenerator, img: &image::DynamicImage, srgb: bool, settings: &TextureSettings, label: Option<&str>) -> Result<Self> {
+        let mip_level_count = if settings.generate_mipmaps { mipmap::mip_level_count(img.width(), img.height()) } else { 1 };
+        let texture = Self::from_mip_levels(device, queue, &[img.to_rgba8()], mip_level_count, srgb, settings, label)?;
+        mipmaps.generate(device, queue, &texture.texture);
+        Ok(texture)
+    }
+
+    // mips decoded from a container, level i is the base size halved i times. without use_precomputed_mips
+    // only the base level is kept and the rest is generated like any other image
+    pub fn from_mip_chain(device: &Device, queue: &Queue, mipmaps: &MipmapGenerator, levels: &[image::RgbaImage], srgb: bool, settings: &TextureSettings, label: Option<&str>) -> Result<Self> {
+        let base = levels.first().ok_or_else(|| anyhow::anyhow!("a mip chain needs at least one level"))?;
+        if !settings.use_precomputed_mips || levels.len() == 1 {
+            return Self::from_image_with_srgb(device, queue, mipmaps, &image::DynamicImage::ImageRgba8(base.clone()), srgb, settings, label);
+        }
+
+        for (mip, level) in levels.iter().enumerate() {
+            let expected = ((base.width() >> mip).max(1), (base.height() >> mip).max(1));
+            if level.dimensions() != expected {
+                anyhow::bail!("mip {mip} is {}x{}, expected {}x{}", level.width(), level.height(), expected.0, expected.1);
+            }
+        }
+
+        Self::from_mip_levels(device, queue, levels, levels.len() as u32, srgb, settings, label)
+    }
 
-        let dimensions = img.dimensions();
+    // uploads the given levels into a texture with room for `mip_level_count`, anything past them is left for MipmapGenerator
+    fn from_mip_levels(device: &Device, queue: &Queue, levels: &[image::RgbaImage], mip_level_count: u32, srgb: bool, settings: &TextureSettings, label: Option<&str>) -> Result<Self> {
+        let dimensions = levels[0].dimensions();
 
         let texture_size = Extent3d {
             width: dimensions.0, 
@@ -24,47 +102,36 @@ impl Texture {
         let texture = device.create_texture(
             &TextureDescriptor {
                 size: texture_size,
-                mip_level_count: 1,
+                mip_level_count,
                 sample_count: 1,
                 dimension: TextureDimension::D2,
-                #[cfg(target_arch="wasm32")]
-                format: TextureFormat::Rgba8Unorm,
-                #[cfg(not(target_arch="wasm32"))]
-                format: TextureFormat::Rgba8UnormSrgb,
-                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                format: color::texture_format(srgb),
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::RENDER_ATTACHMENT,
                 label,
                 view_formats: &[]
             }
         );
 
-        queue.write_texture(
-           TexelCopyTextureInfo { 
-                texture: &texture, 
-                mip_level: 0, 
-                origin: Origin3d::ZERO, 
-                aspect: TextureAspect::All 
-            }, 
-            &rgba,
-            TexelCopyBufferLayout { 
-                offset: 0, 
-                bytes_per_row: Some(4 * dimensions.0), 
-                rows_per_image: Some(dimensions.1)
-            }, 
-            texture_size
-        );
+        for (mip, level) in levels.iter().enumerate() {
+            queue.write_texture(
+               TexelCopyTextureInfo { 
+                    texture: &texture, 
+                    mip_level: mip as u32, 
+                    origin: Origin3d::ZERO, 
+                    aspect: TextureAspect::All 
+                }, 
+                level,
+                TexelCopyBufferLayout { 
+                    offset: 0, 
+                    bytes_per_row: Some(4 * level.width()), 
+                    rows_per_image: Some(level.height())
+                }, 
+                texture.size().mip_level_size(mip as u32, TextureDimension::D2)
+            );
+        }
 
         let texture_view = texture.create_view(&TextureViewDescriptor::default());
-        let sampler = device.create_sampler(
-            &SamplerDescriptor { 
-                address_mode_u: AddressMode::ClampToEdge,
-                address_mode_v: AddressMode::ClampToEdge,
-                address_mode_w: AddressMode::ClampToEdge,
-                mag_filter: FilterMode::Linear,
-                min_filter: FilterMode::Nearest,
-                mipmap_filter: FilterMode::Nearest,
-                ..Default::default()
-            }
-        );
+        let sampler = settings.create_sampler(device);
 
         Ok(Self {
             sampler,
@@ -73,100 +140,267 @@ impl Texture {
         })
     }
 
-    pub fn from_bytes(
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        bytes: &[u8], 
-        label: &str
-    ) -> Result<Self> {
+    // ktx2 and dds files go through from_container, anything else is decoded by the image crate
+    pub fn from_encoded(device: &Device, queue: &Queue, mipmaps: &MipmapGenerator, bytes: &[u8], srgb: bool, settings: &TextureSettings, label: Option<&str>) -> Result<Self> {
+        if container::is_container(bytes) {
+            return Self::from_container(device, queue, mipmaps, bytes, srgb, settings, label);
+        }
         let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+        Self::from_image_with_srgb(device, queue, mipmaps, &img, srgb, settings, label)
     }
 
+    // block compressed data is uploaded as is when the device can sample the format, otherwise every level
+    // is decoded to rgba8 on the cpu. compressed textures can't be rendered to, so they keep the container's mips
+    pub fn from_container(device: &Device, queue: &Queue, mipmaps: &MipmapGenerator, bytes: &[u8], srgb: bool, settings: &TextureSettings, label: Option<&str>) -> Result<Self> {
+        let image = container::parse(bytes)?;
+        let format = if srgb { image.format.add_srgb_suffix() } else { image.format.remove_srgb_suffix() };
 
+        let (block_width, block_height) = format.block_dimensions();
+        let supported = format.is_compressed()
+            && device.features().contains(format.required_features())
+            && image.width % block_width == 0 && image.height % block_height == 0;
+        if !supported {
+            let levels = image.levels.iter().enumerate().map(|(mip, data)| {
+                block_decode::decode(format, (image.width >> mip).max(1), (image.height >> mip).max(1), data)
+            }).collect::<Result<Vec<_>>>()?;
+            return Self::from_mip_chain(device, queue, mipmaps, &levels, srgb, settings, label);
+        }
 
-
-    pub fn bind_default_texture(device: &Device, texture: &Texture) -> (BindGroupLayout, BindGroup) {
-        let texture_bind_group_layout = device.create_bind_group_layout(
-            &BindGroupLayoutDescriptor {
-                entries: &[
-                    BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Texture { 
-                            sample_type: TextureSampleType::Float { filterable: true }, 
-                            view_dimension: TextureViewDimension::D2, 
-                            multisampled: false
-                        },
-                        count: None
-                    },
-                    BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Sampler(SamplerBindingType::Filtering),
-                        count: None
-                    }
-                ],
-                label: Some("texture_bind_group_layout")
-            },
-        );
-
-        let diffuse_bind_group = device.create_bind_group(
-            &BindGroupDescriptor { 
-                label: Some("Diffuse Bind Group"), 
-                layout: &texture_bind_group_layout, 
-                entries: &[
-                    BindGroupEntry {
-                        binding: 0,
-                        resource: BindingResource::TextureView(&texture.view)
-                    },
-                    BindGroupEntry {
-                        binding: 1,
-                        resource: BindingResource::Sampler(&texture.sampler)
-                    }
-                ] 
+        let texture = device.create_texture(
+            &TextureDescriptor {
+                size: Extent3d { width: image.width, height: image.height, depth_or_array_layers: 1 },
+                mip_level_count: image.levels.len() as u32,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                label,
+                view_formats: &[]
             }
         );
 
-        (texture_bind_group_layout, diffuse_bind_group)
+        let block_size = format.block_copy_size(None).unwrap();
+        for (mip, data) in image.levels.iter().enumerate() {
+            // copies work in whole blocks, so small mips use their physical size
+            let size = texture.size().mip_level_size(mip as u32, TextureDimension::D2).physical_size(format);
+            queue.write_texture(
+                TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level: mip as u32,
+                    origin: Origin3d::ZERO,
+                    aspect: TextureAspect::All
+                },
+                data,
+                TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(size.width / block_width * block_size),
+                    rows_per_image: Some(size.height / block_height)
+                },
+                size
+            );
+        }
+
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let sampler = settings.create_sampler(device);
+
+        Ok(Self {
+            sampler,
+            texture,
+            view
+        })
+    }
+
+    // 1x1 texture of a single value, the fallback for material slots without an image
+    pub fn from_color(device: &Device, queue: &Queue, color: [u8; 4], srgb: bool, label: &str) -> Result<Self> {
+        let img = image::RgbaImage::from_pixel(1, 1, image::Rgba(color));
+        Self::from_mip_levels(device, queue, &[img], 1, srgb, &TextureSettings::default(), Some(label))
     }
 
 
+
+
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
-    pub fn create_depth_texture(device: &Device, config: &SurfaceConfiguration, label: &str) -> Self {
+    pub fn create_depth_texture(device: &Device, config: &SurfaceConfiguration, compare: CompareFunction, sample_count: u32, label: &str) -> Self {
         let size = wgpu::Extent3d {
             width: config.width.max(1),
             height: config.height.max(1),
             depth_or_array_layers: 1,
         };
 
+        // multisampled depth is never read back, and the gl backend can't create it as a sampled texture
+        let usage = if sample_count > 1 {
+            TextureUsages::RENDER_ATTACHMENT
+        } else {
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING
+        };
+
         let desc = TextureDescriptor {
             dimension: TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
             label: Some(label),
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             size,
-            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            usage,
             view_formats: &[]
         };
 
         let texture = device.create_texture(&desc);
         let view = texture.create_view(&TextureViewDescriptor::default());
+        let sampler = Self::create_depth_sampler(device, compare);
+
+        Self { texture, view, sampler }
+    }
+
+    // a color target that later passes read back with textureLoad, so the sampler never filters. copyable both ways
+    // so a pass that can't write in place can copy its result back
+    pub fn create_render_target(device: &Device, width: u32, height: u32, format: TextureFormat, label: &str) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            dimension: TextureDimension::D2,
+            format,
+            label: Some(label),
+            mip_level_count: 1,
+            sample_count: 1,
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1
+            },
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC | TextureUsages::COPY_DST,
+            view_formats: &[]
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self { texture, view, sampler }
+    }
+
+    // six layers viewed as a cube, renderable so faces can be drawn into and copyable for uploads
+    pub fn create_cubemap(device: &Device, size: u32, mip_level_count: u32, format: TextureFormat, label: &str) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            dimension: TextureDimension::D2,
+            format,
+            label: Some(label),
+            mip_level_count,
+            sample_count: 1,
+            size: Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6
+            },
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[]
+        });
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::Cube),
+            ..Default::default()
+        });
         let sampler = device.create_sampler(&SamplerDescriptor {
             address_mode_u: AddressMode::ClampToEdge,
             address_mode_v: AddressMode::ClampToEdge,
             address_mode_w: AddressMode::ClampToEdge,
             mag_filter: FilterMode::Linear,
             min_filter: FilterMode::Linear,
-            mipmap_filter: FilterMode::Nearest,
-            compare: Some(CompareFunction::LessEqual),
-            lod_min_clamp: 0.0,
-            lod_max_clamp: 100.0,
+            mipmap_filter: FilterMode::Linear,
             ..Default::default()
         });
 
         Self { texture, view, sampler }
     }
-}
\ No newline at end of file
+
+    // six square faces in +x -x +y -y +z -z order, float images keep their range and anything else is treated as sRGB color
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn cubemap_from_images(device: &Device, queue: &Queue, faces: &[image::DynamicImage; 6], label: &str) -> Result<Self> {
+        use image::GenericImageView;
+
+        let size = faces[0].width();
+        if let Some(face) = faces.iter().find(|face| face.dimensions() != (size, size)) {
+            anyhow::bail!("cubemap faces must be square and the same size, got {}x{} and {}x{}", size, faces[0].height(), face.width(), face.height());
+        }
+
+        let hdr = matches!(faces[0].color(), image::ColorType::Rgb32F | image::ColorType::Rgba32F);
+        let format = if hdr { TextureFormat::Rgba16Float } else { color::texture_format(true) };
+        let cubemap = Self::create_cubemap(device, size, 1, format, label);
+
+        for (layer, face) in faces.iter().enumerate() {
+            let (texels, bytes_per_texel) = if hdr {
+                let texels = face.to_rgba32f().iter().map(|&c| half::f16::from_f32(c).to_bits()).collect::<Vec<u16>>();
+                (bytemuck::cast_slice(&texels).to_vec(), 8)
+            } else {
+                (face.to_rgba8().into_raw(), 4)
+            };
+
+            queue.write_texture(
+                TexelCopyTextureInfo {
+                    texture: &cubemap.texture,
+                    mip_level: 0,
+                    origin: Origin3d { x: 0, y: 0, z: layer as u32 },
+                    aspect: TextureAspect::All
+                },
+                &texels,
+                TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_texel * size),
+                    rows_per_image: Some(size)
+                },
+                Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 1
+                }
+            );
+        }
+
+        Ok(cubemap)
+    }
+
+    // one layer per shadow map, the view covers every layer for sampling as texture_depth_2d_array
+    pub fn create_depth_texture_array(device: &Device, size: u32, layers: u32, compare: CompareFunction, label: &str) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            dimension: TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            label: Some(label),
+            mip_level_count: 1,
+            sample_count: 1,
+            size: Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: layers
+            },
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[]
+        });
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = Self::create_depth_sampler(device, compare);
+
+        Self { texture, view, sampler }
+    }
+
+    fn create_depth_sampler(device: &Device, compare: CompareFunction) -> Sampler {
+        device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Nearest,
+            compare: Some(compare),
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 100.0,
+            ..Default::default()
+        })
+    }
+}
+
@@ -0,0 +1,318 @@
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, *};
+
+use crate::{postprocess::FullscreenPass, texture::Texture};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tonemapper {
+    Aces,
+    Reinhard,
+    AgX
+}
+
+impl Tonemapper {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Aces => Self::Reinhard,
+            Self::Reinhard => Self::AgX,
+            Self::AgX => Self::Aces
+        }
+    }
+
+    // matches the tonemapper switch in tonemap.wgsl
+    fn index(self) -> u32 {
+        match self {
+            Self::Aces => 0,
+            Self::Reinhard => 1,
+            Self::AgX => 2
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TonemapSettings {
+    pub operator: Tonemapper,
+    pub exposure: f32,              // in stops, the whole exposure when manual and a compensation on top of auto exposure
+    pub auto_exposure: bool,
+    pub min_log_luminance: f32,     // log2 luminance range the histogram covers, anything darker is left out of the average
+    pub max_log_luminance: f32,
+    pub adaptation_rate: f32        // how quickly auto exposure follows the scene, per second
+}
+
+impl Default for TonemapSettings {
+    fn default() -> Self {
+        Self {
+            operator: Tonemapper::Aces,
+            exposure: 0.0,
+            auto_exposure: false,
+            min_log_luminance: -10.0,
+            max_log_luminance: 6.0,
+            adaptation_rate: 1.5
+        }
+    }
+}
+
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TonemapUniform {
+    tonemapper: u32,
+    auto_exposure: u32,
+    exposure: f32,
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    adaptation: f32,    // share of this frame's measurement blended into the adapted luminance
+    _padding: [u32; 2]
+}
+
+
+// the scene renders into a float target so lighting can go past 1, this maps it back down into the output format
+pub struct Tonemap {
+    pub settings: TonemapSettings,
+    pub hdr: Texture,
+    uniform_buffer: Buffer,
+    histogram_buffer: Buffer,
+    luminance_buffer: Buffer,   // the adapted average luminance, carried from frame to frame on the gpu
+
+    pass: FullscreenPass,
+    histogram_pipeline: ComputePipeline,
+    average_pipeline: ComputePipeline,
+    bind_group_layout: BindGroupLayout,
+    exposure_bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    exposure_bind_group: BindGroup,
+
+    // the first metered frame takes the measurement as is instead of fading in from a stale value
+    adapted: bool
+}
+
+
+impl Tonemap {
+    pub const HDR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+    const HISTOGRAM_BINS: u64 = 256;
+    const WORKGROUP_SIZE: u32 = 16;
+
+    pub fn new(device: &Device, vertex_shader: &ShaderModule, width: u32, height: u32, output_format: TextureFormat, settings: TonemapSettings) -> Self {
+        let shader = device.create_shader_module(include_wgsl!("tonemap.wgsl"));
+
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Tonemap Uniform Buffer"),
+            size: std::mem::size_of::<TonemapUniform>() as BufferAddress,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let histogram_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Luminance Histogram Buffer"),
+            size: Self::HISTOGRAM_BINS * std::mem::size_of::<u32>() as BufferAddress,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false
+        });
+
+        let luminance_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Adapted Luminance Buffer"),
+            contents: bytemuck::cast_slice(&[0.18_f32]),
+            usage: BufferUsages::STORAGE
+        });
+
+        let hdr_entry = |visibility| BindGroupLayoutEntry {
+            binding: 0,
+            visibility,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: false },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false
+            },
+            count: None
+        };
+        let uniform_entry = |visibility| BindGroupLayoutEntry {
+            binding: 1,
+            visibility,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None
+            },
+            count: None
+        };
+        let storage_entry = |binding, read_only, visibility| BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None
+            },
+            count: None
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Tonemap Bind Group Layout"),
+            entries: &[
+                hdr_entry(ShaderStages::FRAGMENT),
+                uniform_entry(ShaderStages::FRAGMENT),
+                storage_entry(2, true, ShaderStages::FRAGMENT)
+            ]
+        });
+
+        let exposure_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Exposure Bind Group Layout"),
+            entries: &[
+                hdr_entry(ShaderStages::COMPUTE),
+                uniform_entry(ShaderStages::COMPUTE),
+                storage_entry(2, false, ShaderStages::COMPUTE),
+                storage_entry(3, false, ShaderStages::COMPUTE)
+            ]
+        });
+
+        let pass = FullscreenPass::new(device, "Tonemap Pipeline", vertex_shader, (&shader, "fs_main"), &[&bind_group_layout], output_format, None);
+
+        let exposure_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Exposure Pipeline Layout"),
+            bind_group_layouts: &[&exposure_bind_group_layout],
+            push_constant_ranges: &[]
+        });
+
+        let exposure_shader = device.create_shader_module(include_wgsl!("exposure.wgsl"));
+        let compute_pipeline = |label, entry_point| device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some(label),
+            layout: Some(&exposure_layout),
+            module: &exposure_shader,
+            entry_point: Some(entry_point),
+            compilation_options: PipelineCompilationOptions::default(),
+            cache: None
+        });
+        let histogram_pipeline = compute_pipeline("Luminance Histogram Pipeline", "build_histogram");
+        let average_pipeline = compute_pipeline("Average Luminance Pipeline", "average_luminance");
+
+        let hdr = Texture::create_render_target(device, width, height, Self::HDR_FORMAT, "HDR Target");
+        let (bind_group, exposure_bind_group) = Self::create_bind_groups(
+            device, &bind_group_layout, &exposure_bind_group_layout, &hdr, &uniform_buffer, &histogram_buffer, &luminance_buffer
+        );
+
+        Self {
+            settings,
+            hdr,
+            uniform_buffer,
+            histogram_buffer,
+            luminance_buffer,
+            pass,
+            histogram_pipeline,
+            average_pipeline,
+            bind_group_layout,
+            exposure_bind_group_layout,
+            bind_group,
+            exposure_bind_group,
+            adapted: false
+        }
+    }
+
+    fn create_bind_groups(
+        device: &Device,
+        layout: &BindGroupLayout,
+        exposure_layout: &BindGroupLayout,
+        hdr: &Texture,
+        uniform_buffer: &Buffer,
+        histogram_buffer: &Buffer,
+        luminance_buffer: &Buffer
+    ) -> (BindGroup, BindGroup) {
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Tonemap Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&hdr.view)
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding()
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: luminance_buffer.as_entire_binding()
+                }
+            ]
+        });
+
+        let exposure_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Exposure Bind Group"),
+            layout: exposure_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&hdr.view)
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding()
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: histogram_buffer.as_entire_binding()
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: luminance_buffer.as_entire_binding()
+                }
+            ]
+        });
+
+        (bind_group, exposure_bind_group)
+    }
+
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        self.hdr = Texture::create_render_target(device, width, height, Self::HDR_FORMAT, "HDR Target");
+        (self.bind_group, self.exposure_bind_group) = Self::create_bind_groups(
+            device, &self.bind_group_layout, &self.exposure_bind_group_layout, &self.hdr, &self.uniform_buffer, &self.histogram_buffer, &self.luminance_buffer
+        );
+    }
+
+    // call once per frame, `delta` in real seconds so exposure keeps adapting while the clock is paused
+    pub fn update(&mut self, queue: &Queue, delta: f32) {
+        let adaptation = if self.adapted {
+            1.0 - (-delta * self.settings.adaptation_rate).exp()
+        } else {
+            1.0
+        };
+        // stays false while exposure is manual, so turning auto exposure back on starts from a fresh measurement
+        self.adapted = self.settings.auto_exposure;
+
+        let min_log_luminance = self.settings.min_log_luminance;
+        let uniform = TonemapUniform {
+            tonemapper: self.settings.operator.index(),
+            auto_exposure: self.settings.auto_exposure as u32,
+            exposure: self.settings.exposure,
+            min_log_luminance,
+            log_luminance_range: (self.settings.max_log_luminance - min_log_luminance).max(1e-3),
+            adaptation,
+            _padding: [0; 2]
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // meters the hdr target into a histogram and averages it on the gpu, nothing to do with manual exposure
+    pub fn measure_exposure(&self, encoder: &mut CommandEncoder) {
+        if !self.settings.auto_exposure {
+            return;
+        }
+
+        let size = self.hdr.texture.size();
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Exposure Pass"),
+            timestamp_writes: None
+        });
+        compute_pass.set_bind_group(0, &self.exposure_bind_group, &[]);
+
+        compute_pass.set_pipeline(&self.histogram_pipeline);
+        compute_pass.dispatch_workgroups(size.width.div_ceil(Self::WORKGROUP_SIZE), size.height.div_ceil(Self::WORKGROUP_SIZE), 1);
+
+        compute_pass.set_pipeline(&self.average_pipeline);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+    }
+
+    // tonemaps the hdr target into `view`, which has to be in the output format the pipeline was made with
+    pub fn draw(&self, encoder: &mut CommandEncoder, view: &TextureView) {
+        self.pass.draw(encoder, view, &[&self.bind_group], 0.0);
+    }
+}
@@ -0,0 +1,98 @@
+// maps the hdr scene into the output format over fullscreen.wgsl's triangle, the srgb view encodes the result
+
+struct TonemapUniform {
+    tonemapper: u32,        // 0 aces, 1 reinhard, 2 agx
+    auto_exposure: u32,
+    exposure: f32,          // stops, on top of the metered exposure when auto_exposure is set
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    adaptation: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var hdr: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<uniform> tonemap: TonemapUniform;
+
+// written by exposure.wgsl
+@group(0) @binding(2)
+var<storage, read> adapted_luminance: f32;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureLoad(hdr, vec2<u32>(in.clip_position.xy), 0).rgb;
+
+    // auto exposure brings the average luminance to middle grey
+    var exposure = exp2(tonemap.exposure);
+    if tonemap.auto_exposure != 0u {
+        exposure *= 0.18 / max(adapted_luminance, 1e-4);
+    }
+    let exposed = max(color * exposure, vec3<f32>(0.0));
+
+    var mapped: vec3<f32>;
+    switch tonemap.tonemapper {
+        case 0u: { mapped = aces(exposed); }
+        case 1u: { mapped = reinhard(exposed); }
+        default: { mapped = agx(exposed); }
+    }
+    return vec4<f32>(mapped, 1.0);
+}
+
+
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+// stephen hill's fit of the aces rrt and odt, including the conversions to and from the aces working space
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    // columns here are the rows of the usual matrices, so they multiply from the left
+    let input = mat3x3<f32>(
+        vec3<f32>(0.59719, 0.35458, 0.04823),
+        vec3<f32>(0.07600, 0.90834, 0.01566),
+        vec3<f32>(0.02840, 0.13383, 0.83777),
+    );
+    let output = mat3x3<f32>(
+        vec3<f32>(1.60475, -0.53108, -0.07367),
+        vec3<f32>(-0.10208, 1.10813, -0.00605),
+        vec3<f32>(-0.00327, -0.07276, 1.07602),
+    );
+
+    let v = color * input;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return clamp((a / b) * output, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// the minimal agx base look, a log2 encoding inside an inset gamut followed by a polynomial sigmoid
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var v = inset * color;
+    v = clamp(log2(max(v, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    v = (v - min_ev) / (max_ev - min_ev);
+
+    let x2 = v * v;
+    let x4 = x2 * x2;
+    v = 15.5 * x4 * x2 - 40.14 * x4 * v + 31.96 * x4 - 6.868 * x2 * v + 0.4298 * x2 + 0.1191 * v - 0.00232;
+
+    // the curve lands in display encoded values, take them back to linear for the srgb view
+    v = outset * v;
+    return pow(clamp(v, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(2.2));
+}
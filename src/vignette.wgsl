@@ -0,0 +1,34 @@
+// darkens toward the corners, measured on a circle so wide windows don't get an oval
+
+struct VignetteUniform {
+    intensity: f32,
+    radius: f32,        // 1 reaches the corners
+    smoothness: f32,
+    aspect_ratio: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> vignette: VignetteUniform;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(source, source_sampler, in.uv, 0.0).rgb;
+
+    let offset = (in.uv - 0.5) * vec2<f32>(vignette.aspect_ratio, 1.0);
+    let corner = length(vec2<f32>(vignette.aspect_ratio, 1.0) * 0.5);
+    let distance = length(offset) / corner;
+
+    let falloff = smoothstep(vignette.radius, vignette.radius + vignette.smoothness, distance);
+    return vec4<f32>(color * (1.0 - vignette.intensity * falloff), 1.0);
+}
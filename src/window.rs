@@ -1,29 +1,35 @@
 use std::sync::Arc;
 
-#[cfg(target_arch = "wasm32")]
-use winit::event_loop::{self};
-use winit::{application::ApplicationHandler, dpi::{PhysicalSize, Size}, event::{KeyEvent, WindowEvent}, event_loop::{ActiveEventLoop, EventLoop}, keyboard::PhysicalKey, window::Window};
+#[cfg(not(target_arch = "wasm32"))]
+use winit::dpi::{PhysicalSize, Size};
+use winit::{application::ApplicationHandler, event::{DeviceEvent, DeviceId, KeyEvent, WindowEvent}, event_loop::{ActiveEventLoop, EventLoop}, keyboard::PhysicalKey, window::Window};
 
 use wgpu::*;
 
-use crate::render::*;
+use crate::{options::Options, render::*};
 
 
 
 pub struct App {
     #[cfg(target_arch = "wasm32")]
     proxy: Option<winit::event_loop::EventLoopProxy<State>>,
-    state: Option<State>
+    options: Options,
+    state: Option<State>,
+    #[cfg(not(target_arch = "wasm32"))]
+    error: Option<anyhow::Error>    // why startup failed, handed back out of run
 }
 
 impl App {
 
     #[allow(dead_code)]
-    pub fn new(#[cfg(target_arch = "wasm32")] event_loop: &EventLoop<State>) -> Self {
+    pub fn new(#[cfg(target_arch = "wasm32")] event_loop: &EventLoop<State>, options: Options) -> Self {
         #[cfg(target_arch = "wasm32")]
         let proxy = Some(event_loop.create_proxy());
         Self {
+            options,
             state: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            error: None,
             #[cfg(target_arch = "wasm32")]
             proxy
         }
@@ -69,21 +75,24 @@ impl ApplicationHandler<State> for App {
         #[cfg(not(target_arch = "wasm32"))]
         {
             // not on web, use pollster to await. otherwise make it async
-            self.state = Some(pollster::block_on(State::new(window)).unwrap());
+            match pollster::block_on(State::new(window, &self.options)) {
+                Ok(state) => self.state = Some(state),
+                Err(e) => {
+                    self.error = Some(e);
+                    event_loop.exit();
+                }
+            }
         }
 
         #[cfg(target_arch = "wasm32")]
         {
             if let Some(proxy) = self.proxy.take() {
+                let options = self.options.clone();
                 wasm_bindgen_futures::spawn_local(async move {
-                    assert!(proxy
-                        .send_event(
-                            State::new(window)
-                                .await
-                                .expect("Unable to create canvas!")
-                        )
-                        .is_ok()
-                    ) 
+                    match State::new(window, &options).await {
+                        Ok(state) => assert!(proxy.send_event(state).is_ok()),
+                        Err(e) => log::error!("{e:#}")
+                    }
                 });
             }
         }
@@ -94,11 +103,13 @@ impl ApplicationHandler<State> for App {
     fn user_event(&mut self, _event_loop: &ActiveEventLoop, mut event: State) {
         #[cfg(target_arch = "wasm32")]
         {
-            event.window.request_redraw();
-            event.resize(
-                event.window.inner_size().width,
-                event.window.inner_size().height
-            );
+            if let Some(window) = event.window.clone() {
+                window.request_redraw();
+                event.resize(
+                    window.inner_size().width,
+                    window.inner_size().height
+                );
+            }
         }
         self.state = Some(event);
     } 
@@ -122,8 +133,9 @@ impl ApplicationHandler<State> for App {
                 match state.render() {
                     Ok(_) => (),
                     Err(SurfaceError::Lost | SurfaceError::Outdated) => {
-                        let size = state.window.inner_size();
-                        state.resize(size.width, size.height);
+                        if let Some(size) = state.window.as_ref().map(|w| w.inner_size()) {
+                            state.resize(size.width, size.height);
+                        }
                     },
                     Err(e) => {
                         log::error!("Unable to render {}", e);
@@ -138,21 +150,48 @@ impl ApplicationHandler<State> for App {
                         ..
                     },
                 ..
-            } => state.handle_key(&event_loop, code, key_state.is_pressed()),
+            } => state.handle_key(event_loop, code, key_state.is_pressed()),
 
             WindowEvent::CursorMoved { 
                 position,
                 ..
-            } => state.handle_mouse_moved(&event_loop, position),
+            } => state.handle_mouse_moved(event_loop, position),
+
+            WindowEvent::MouseInput { 
+                state: button_state,
+                button,
+                ..
+            } => state.handle_mouse_input(button, button_state.is_pressed()),
+
+            WindowEvent::MouseWheel { 
+                delta,
+                ..
+            } => state.handle_mouse_wheel(delta),
 
             _ => ()
         }
     }
+
+    fn device_event(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+        _device_id: DeviceId,
+        event: DeviceEvent,
+    ) {
+        let state = match &mut self.state {
+            Some(canvas) => canvas,
+            None => return
+        };
+
+        if let DeviceEvent::MouseMotion { delta } = event {
+            state.handle_mouse_motion(delta);
+        }
+    }
 }
 
 
 #[allow(dead_code)]
-pub fn run() -> anyhow::Result<()> {
+pub fn run(options: Options) -> anyhow::Result<()> {
     #[cfg(not(target_arch = "wasm32"))]
     {
         env_logger::init();
@@ -167,8 +206,13 @@ pub fn run() -> anyhow::Result<()> {
     let mut app = App::new(
         #[cfg(target_arch = "wasm32")]
         &event_loop,
+        options
     );
     event_loop.run_app(&mut app)?;
 
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(e) = app.error {
+        return Err(e);
+    }
     Ok(())
 }
\ No newline at end of file